@@ -0,0 +1,42 @@
+//! Build info for the `/version` endpoint.
+//! `GIT_SHA` can be given in the environment when building without the `.git` folder (e.g., docker).
+
+use std::env;
+use std::process::Command;
+
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+fn main() {
+    let git_sha = env::var("GIT_SHA").ok().filter(|v| !v.is_empty()).or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short=12", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|sha| sha.trim().to_string())
+    });
+
+    let build_time = OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default();
+
+    // e.g., CARGO_FEATURE_OTEL -> otel
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(name, _)| {
+            name.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .filter(|feature| feature != "default")
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=BUILD_GIT_SHA={}", git_sha.as_deref().unwrap_or("unknown"));
+    println!("cargo:rustc-env=BUILD_TIME={build_time}");
+    println!("cargo:rustc-env=BUILD_FEATURES={}", features.join(","));
+
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+}
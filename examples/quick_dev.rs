@@ -8,7 +8,13 @@ use serde_json::{json, Value};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let hc = httpc_test::new_client("http://localhost:3000")?;
+    // The mutating requests with the cookie (and the login) must come from an allowed origin (CSRF).
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(reqwest::header::ORIGIN, "http://localhost:3000".parse()?);
+    let hc = httpc_test::new_client_with_reqwest(
+        "http://localhost:3000",
+        reqwest::Client::builder().default_headers(headers),
+    )?;
     // hc.do_get("/hello").await?.print().await?;
     // hc.do_get("/hello?name=pinku").await?.print().await?;
     // hc.do_get("/hello2/prantoran").await?.print().await?;
@@ -11,6 +11,7 @@ import base64
 import json
 from PIL import Image, ImageTk
 import io
+import os
 
 class NutritionAnalyzerApp:
     def __init__(self, root):
@@ -20,6 +21,8 @@ class NutritionAnalyzerApp:
         
         # API endpoint
         self.api_url = "http://localhost:3000/analyze-image"
+        # API key (created under /api/keys with the "analyze" scope)
+        self.api_key = os.environ.get("FOODEQ_API_KEY")
         
         # Current image data
         self.current_image_base64 = None
@@ -193,11 +196,15 @@ class NutritionAnalyzerApp:
                 "image": self.current_image_base64
             }
             
+            headers = {"Content-Type": "application/json"}
+            if self.api_key:
+                headers["Authorization"] = f"Bearer {self.api_key}"
+
             # Send request to Rust API
             response = requests.post(
                 self.api_url,
                 json=payload,
-                headers={"Content-Type": "application/json"},
+                headers=headers,
                 timeout=30
             )
             
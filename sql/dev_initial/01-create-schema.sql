@@ -1,13 +0,0 @@
----- Base app schema
-
--- User
-CREATE TABLE "user" (
-    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
-    username varchar(128) NOT NULL UNIQUE
-);
-
--- Task
-CREATE TABLE task (
-    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
-    title varchar(256) NOT NULL
-);
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- User demo (id used by the demo login)
+INSERT INTO "user" (id, username) VALUES (1, 'demo');
+
+-- User demo1
+INSERT INTO "user" (username, role) VALUES ('demo1', 'admin');
+
+-- User demo2
+INSERT INTO "user" (username, role) VALUES ('demo2', 'dietitian');
@@ -0,0 +1,8 @@
+-- Base app schema (reverse order of the foreign keys)
+
+DROP TABLE IF EXISTS user_identity;
+DROP TABLE IF EXISTS analysis_usage;
+DROP TABLE IF EXISTS dietitian_access;
+DROP TABLE IF EXISTS api_key;
+DROP TABLE IF EXISTS task;
+DROP TABLE IF EXISTS "user";
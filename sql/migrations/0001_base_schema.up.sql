@@ -0,0 +1,57 @@
+-- Base app schema
+
+-- User
+CREATE TABLE "user" (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    username varchar(128) NOT NULL UNIQUE,
+    role varchar(16) NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'dietitian', 'admin')),
+    -- NULL means the service default quota
+    analysis_daily_quota INT,
+    analysis_monthly_quota INT
+);
+
+-- Task
+CREATE TABLE task (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    title varchar(256) NOT NULL
+);
+
+-- Api Key (only the sha256 hash of the key is stored)
+CREATE TABLE api_key (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    name varchar(128) NOT NULL,
+    prefix varchar(32) NOT NULL,
+    key_hash char(64) NOT NULL UNIQUE,
+    scopes text[] NOT NULL DEFAULT '{}',
+    created_at timestamptz NOT NULL DEFAULT now(),
+    last_used_at timestamptz,
+    revoked_at timestamptz
+);
+
+
+-- Dietitian Access (granted by the client)
+CREATE TABLE dietitian_access (
+    client_id BIGINT NOT NULL REFERENCES "user" (id) ON DELETE CASCADE,
+    dietitian_id BIGINT NOT NULL REFERENCES "user" (id) ON DELETE CASCADE,
+    granted_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (client_id, dietitian_id)
+);
+
+-- Analysis Usage (image analyses per user and UTC day)
+CREATE TABLE analysis_usage (
+    user_id BIGINT NOT NULL REFERENCES "user" (id) ON DELETE CASCADE,
+    day date NOT NULL,
+    count INT NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, day)
+);
+
+-- User Identity (external OpenID Connect accounts linked to a user)
+CREATE TABLE user_identity (
+    issuer varchar(256) NOT NULL,
+    subject varchar(256) NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES "user" (id) ON DELETE CASCADE,
+    email varchar(256),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (issuer, subject)
+);
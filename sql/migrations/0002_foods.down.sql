@@ -0,0 +1,4 @@
+-- Food reference (the pg_trgm extension is kept, other objects may use it)
+
+DROP TABLE IF EXISTS food;
+DROP FUNCTION IF EXISTS food_search_text(text, text[]);
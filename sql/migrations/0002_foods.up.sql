@@ -0,0 +1,29 @@
+-- Food reference (nutrients per 100 g), imported from a dataset, e.g., USDA FoodData Central
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE TABLE food (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    -- dataset and id in it, a re-import updates the rows
+    source varchar(32) NOT NULL,
+    source_id varchar(64) NOT NULL,
+    name varchar(512) NOT NULL,
+    aliases text[] NOT NULL DEFAULT '{}',
+    -- per 100 g, NULL when not in the dataset
+    calories real,
+    protein_g real,
+    fat_g real,
+    carbohydrates_g real,
+    fiber_g real,
+    sugar_g real,
+    sodium_mg real,
+    imported_at timestamptz NOT NULL DEFAULT now(),
+    UNIQUE (source, source_id)
+);
+
+-- Name and aliases, lower cased, for the fuzzy search (immutable, so it can be indexed)
+CREATE FUNCTION food_search_text(name text, aliases text[]) RETURNS text
+    LANGUAGE sql IMMUTABLE PARALLEL SAFE
+    AS $$ SELECT lower(name || ' ' || array_to_string(aliases, ' ')) $$;
+
+CREATE INDEX food_search_trgm_idx ON food USING gin (food_search_text(name, aliases) gin_trgm_ops);
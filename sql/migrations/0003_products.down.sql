@@ -0,0 +1,3 @@
+-- Packaged products
+
+DROP TABLE IF EXISTS product;
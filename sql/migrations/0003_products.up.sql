@@ -0,0 +1,21 @@
+-- Packaged products by barcode (nutrients per 100 g), imported from an Open Food Facts dump
+
+CREATE TABLE product (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    -- EAN-13, UPC-A codes are stored with a leading 0
+    barcode char(13) NOT NULL UNIQUE,
+    name varchar(512) NOT NULL,
+    brand varchar(256),
+    -- as labelled, e.g., "1 bar (40 g)", and its weight when known
+    serving_size varchar(128),
+    serving_g real,
+    -- per 100 g, NULL when not in the dump
+    calories real,
+    protein_g real,
+    fat_g real,
+    carbohydrates_g real,
+    sugar_g real,
+    sodium_mg real,
+    source varchar(32) NOT NULL,
+    imported_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,5 @@
+-- Recipes and the meal log
+
+DROP TABLE IF EXISTS meal_log;
+DROP TABLE IF EXISTS recipe_ingredient;
+DROP TABLE IF EXISTS recipe;
@@ -0,0 +1,59 @@
+-- Recipes (ingredients with grams, nutrients computed by the server) and the meal log
+
+CREATE TABLE recipe (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    owner_id BIGINT NOT NULL REFERENCES "user" (id) ON DELETE CASCADE,
+    name varchar(256) NOT NULL,
+    servings real NOT NULL CHECK (servings > 0),
+    -- shared recipes can be read and cloned by the other users
+    shared boolean NOT NULL DEFAULT false,
+    cloned_from BIGINT REFERENCES recipe (id) ON DELETE SET NULL,
+    -- per serving, computed from the ingredients
+    calories real NOT NULL,
+    protein_g real NOT NULL,
+    fat_g real NOT NULL,
+    carbohydrates_g real NOT NULL,
+    sugar_g real NOT NULL,
+    sodium_mg real NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX recipe_owner_id_idx ON recipe (owner_id);
+
+CREATE TABLE recipe_ingredient (
+    recipe_id BIGINT NOT NULL REFERENCES recipe (id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    -- NULL for an analyzed food item, or when the food was removed from the reference
+    food_id BIGINT REFERENCES food (id) ON DELETE SET NULL,
+    name varchar(512) NOT NULL,
+    grams real NOT NULL CHECK (grams > 0),
+    -- for the grams, as computed when the recipe was saved
+    calories real NOT NULL,
+    protein_g real NOT NULL,
+    fat_g real NOT NULL,
+    carbohydrates_g real NOT NULL,
+    sugar_g real NOT NULL,
+    sodium_mg real NOT NULL,
+    PRIMARY KEY (recipe_id, position)
+);
+
+-- Meal Log (what a user ate, readable by the dietitians they granted access)
+CREATE TABLE meal_log (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES "user" (id) ON DELETE CASCADE,
+    recipe_id BIGINT REFERENCES recipe (id) ON DELETE SET NULL,
+    name varchar(256) NOT NULL,
+    servings real NOT NULL CHECK (servings > 0),
+    -- for the servings eaten
+    calories real NOT NULL,
+    protein_g real NOT NULL,
+    fat_g real NOT NULL,
+    carbohydrates_g real NOT NULL,
+    sugar_g real NOT NULL,
+    sodium_mg real NOT NULL,
+    eaten_at timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX meal_log_user_id_eaten_at_idx ON meal_log (user_id, eaten_at);
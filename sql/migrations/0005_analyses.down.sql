@@ -0,0 +1,3 @@
+-- Analyses
+
+DROP TABLE IF EXISTS analysis;
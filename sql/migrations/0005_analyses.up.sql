@@ -0,0 +1,19 @@
+-- Analyses (image analyses as answered, and as corrected by their user)
+
+CREATE TABLE analysis (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES "user" (id) ON DELETE CASCADE,
+    -- sha256 of the base64 image, the image itself is not stored
+    image_sha256 char(64) NOT NULL,
+    -- NULL when served from the cache
+    model varchar(128),
+    prompt_id varchar(64) NOT NULL,
+    -- the foods as answered (with their food reference), and as corrected
+    foods jsonb NOT NULL,
+    corrected_foods jsonb,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    corrected_at timestamptz
+);
+
+CREATE INDEX analysis_user_id_idx ON analysis (user_id, id);
+CREATE INDEX analysis_corrected_at_idx ON analysis (corrected_at) WHERE corrected_at IS NOT NULL;
@@ -0,0 +1,3 @@
+-- The api keys go with their user
+
+ALTER TABLE api_key DROP CONSTRAINT IF EXISTS api_key_user_id_fkey;
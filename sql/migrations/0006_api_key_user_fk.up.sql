@@ -0,0 +1,7 @@
+-- The api keys go with their user
+
+-- Keys of users deleted before the constraint.
+DELETE FROM api_key WHERE user_id NOT IN (SELECT id FROM "user");
+
+ALTER TABLE api_key
+    ADD CONSTRAINT api_key_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE;
@@ -1,15 +1,20 @@
 // region:    --- Modules
 
 
-mod dev_db; // recreate and seed dev db
+mod dev_db; // recreate, migrate and seed dev db
 
 use tokio::sync::OnceCell;
 use tracing::info; // once lockish
 
+#[cfg(test)]
+use crate::config::{init_config, ConfigNeeds};
+#[cfg(test)]
+use crate::model::model::ModelManager;
+
 // endregion: --- Modules
 
 /// Initialize environment for local development.
-/// (for early development, will be called from main())
+/// (called from main() when SERVICE_DEV_DB_RECREATE is set)
 pub async fn init_dev() {
     static INIT: OnceCell<()> = OnceCell::const_new();
 
@@ -18,4 +23,14 @@ pub async fn init_dev() {
         dev_db::init_dev_db().await.unwrap(); // unwrap ok for dev only, can break early but its fine
     })
     .await;
-}
\ No newline at end of file
+}
+
+/// Recreated and seeded dev db (once per test run), and a new ModelManager on it.
+/// For the tests which need the db (SERVICE_DB_URL and SERVICE_DEV_DB_ROOT_URL, see .cargo/config.toml).
+#[cfg(test)]
+pub async fn init_test() -> ModelManager {
+    init_config(ConfigNeeds { db: true, token_key: true, provider: false }).unwrap();
+    init_dev().await;
+
+    ModelManager::new().await.unwrap()
+}
@@ -0,0 +1,541 @@
+//! Offline evaluation of a prompt and a provider against a labelled dataset (see docs/eval.md).
+//! The dataset is json lines, one image each with its expected foods:
+//! `{"image": "img/lunch1.jpg", "foods": [{"name": "white rice", "aliases": ["rice"], "weight_g": 180, "calories": 234}]}`
+//! The image paths are relative to the dataset file, the expected values are optional.
+//! The provider answers can be recorded, then replayed, to re-run the parsing and the report offline.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::nutrition::{
+    parse_gemini_response, prompt_id, AnalysisCache, FoodItem, GeminiProvider, Provider, ProviderCall, FOOD_PROMPT,
+};
+
+// Expected and analyzed foods are paired from this word similarity (Jaccard) of their names.
+const MATCH_MIN_SCORE: f32 = 0.5;
+
+pub struct EvalArgs {
+    dataset: PathBuf,
+    prompt: Option<PathBuf>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+}
+
+impl EvalArgs {
+    /// `<dataset> [--prompt <file>] [--record <file>] [--replay <file>]`, None when invalid.
+    pub fn parse(args: &[&str]) -> Option<Self> {
+        let (dataset, mut options) = args.split_first()?;
+        let mut eval_args = Self {
+            dataset: PathBuf::from(dataset),
+            prompt: None,
+            record: None,
+            replay: None,
+        };
+        while let [option, value, rest @ ..] = options {
+            let value = Some(PathBuf::from(value));
+            match *option {
+                "--prompt" => eval_args.prompt = value,
+                "--record" => eval_args.record = value,
+                "--replay" => eval_args.replay = value,
+                _ => return None,
+            }
+            options = rest;
+        }
+
+        options.is_empty().then_some(eval_args)
+    }
+}
+
+// -- Eval Types
+
+/// One labelled image of the dataset.
+#[derive(Deserialize)]
+struct Sample {
+    image: String,
+    foods: Vec<ExpectedFood>,
+}
+
+#[derive(Deserialize)]
+struct ExpectedFood {
+    name: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    weight_g: Option<f32>,
+    calories: Option<f32>,
+    protein_g: Option<f32>,
+    fat_g: Option<f32>,
+    carbohydrates_g: Option<f32>,
+    sugar_g: Option<f32>,
+    sodium_mg: Option<f32>,
+}
+
+/// A provider answer (or failure), as recorded for the replays, by the sha256 of the base64 image.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize)]
+struct RecordedAnswer {
+    image: String,
+    image_sha256: String,
+    prompt_id: String,
+    model: Option<String>,
+    latency_ms: Option<f64>,
+    text: Option<String>,
+    error: Option<String>,
+}
+
+// End: -- Eval Types
+
+/// The recorded answers, instead of the provider calls.
+struct ReplayProvider {
+    answers: HashMap<String, RecordedAnswer>,
+}
+
+impl ReplayProvider {
+    fn open(file: &Path) -> Result<Self> {
+        let answers = read_json_lines::<RecordedAnswer>(file)?
+            .into_iter()
+            .map(|answer| (answer.image_sha256.clone(), answer))
+            .collect();
+
+        Ok(Self { answers })
+    }
+}
+
+#[async_trait]
+impl Provider for ReplayProvider {
+    fn name(&self) -> String {
+        "replay".to_string()
+    }
+
+    async fn generate(&self, base64_image: &str, _prompt: &str) -> core::result::Result<(String, ProviderCall), String> {
+        let answer = self
+            .answers
+            .get(&AnalysisCache::key(base64_image))
+            .ok_or("no recorded answer for this image")?;
+        if let Some(error) = &answer.error {
+            return Err(error.clone());
+        }
+
+        let provider_call = ProviderCall {
+            model: answer.model.clone().unwrap_or_default(),
+            latency_ms: answer.latency_ms.unwrap_or(0.),
+            prompt_tokens: None,
+            output_tokens: None,
+            total_tokens: None,
+        };
+        Ok((answer.text.clone().unwrap_or_default(), provider_call))
+    }
+}
+
+pub async fn run(args: EvalArgs) -> Result<()> {
+    let samples = read_json_lines::<Sample>(&args.dataset)?;
+    let dataset_dir = args.dataset.parent().unwrap_or(Path::new("."));
+
+    let prompt = match &args.prompt {
+        Some(file) => fs::read_to_string(file).map_err(|ex| fail(file, ex))?,
+        None => FOOD_PROMPT.to_string(),
+    };
+    let provider: Box<dyn Provider + Send + Sync> = match &args.replay {
+        Some(file) => Box::new(ReplayProvider::open(file)?),
+        None => Box::new(GeminiProvider::new(config().GEMINI_API_KEY.expose().clone())),
+    };
+    let mut recorder = match &args.record {
+        Some(file) => Some((file, BufWriter::new(File::create(file).map_err(|ex| fail(file, ex))?))),
+        None => None,
+    };
+
+    let mut report = Report::default();
+    for sample in &samples {
+        let image_path = dataset_dir.join(&sample.image);
+        let base64_image = STANDARD.encode(fs::read(&image_path).map_err(|ex| fail(&image_path, ex))?);
+
+        let result = provider.generate(&base64_image, &prompt).await;
+
+        if let Some((file, output)) = &mut recorder {
+            let answer = RecordedAnswer {
+                image: sample.image.clone(),
+                image_sha256: AnalysisCache::key(&base64_image),
+                prompt_id: prompt_id(&prompt),
+                model: result.as_ref().ok().map(|(_, call)| call.model.clone()),
+                latency_ms: result.as_ref().ok().map(|(_, call)| call.latency_ms),
+                text: result.as_ref().ok().map(|(text, _)| text.clone()),
+                error: result.as_ref().err().cloned(),
+            };
+            let line = serde_json::to_string(&answer).map_err(|ex| fail(file, ex))?;
+            writeln!(output, "{line}").map_err(|ex| fail(file, ex))?;
+        }
+
+        let status = match result {
+            Err(ex) => {
+                report.provider_errors += 1;
+                format!("provider error - {ex}")
+            }
+            Ok((text, provider_call)) => {
+                report.latencies_ms.push(provider_call.latency_ms);
+                match parse_gemini_response(&text).map_err(|ex| ex.to_string()) {
+                    Err(ex) => {
+                        report.parse_failures += 1;
+                        format!("parse failure - {ex}")
+                    }
+                    Ok(response) => {
+                        let matched = report.add(&sample.foods, &response.foods);
+                        format!("{matched}/{} expected found, {} analyzed", sample.foods.len(), response.foods.len())
+                    }
+                }
+            }
+        };
+        println!("{:<40} {status}", sample.image);
+    }
+
+    if let Some((file, output)) = &mut recorder {
+        output.flush().map_err(|ex| fail(file, ex))?;
+    }
+
+    let prompt_label = match &args.replay {
+        Some(_) => "as recorded".to_string(),
+        None => prompt_id(&prompt),
+    };
+    println!();
+    println!("provider {}, prompt {prompt_label}, {} images", provider.name(), samples.len());
+    report.print();
+
+    Ok(())
+}
+
+// region:    --- Report
+
+#[derive(Default)]
+struct Report {
+    provider_errors: usize,
+    parse_failures: usize,
+    latencies_ms: Vec<f64>,
+    expected_foods: usize,
+    analyzed_foods: usize,
+    matched_foods: usize,
+    // Absolute errors and expected values, of the matched foods, by nutrient.
+    errors: HashMap<&'static str, Vec<(f32, f32)>>,
+}
+
+const NUTRIENTS: [&str; 7] = ["weight_g", "calories", "protein_g", "fat_g", "carbohydrates_g", "sugar_g", "sodium_mg"];
+
+impl Report {
+    /// Adds an analyzed image, returns the count of expected foods found.
+    fn add(&mut self, expected: &[ExpectedFood], analyzed: &[FoodItem]) -> usize {
+        let pairs = match_foods(expected, analyzed);
+        self.expected_foods += expected.len();
+        self.analyzed_foods += analyzed.len();
+        self.matched_foods += pairs.len();
+
+        for (expected, analyzed) in pairs.iter().map(|(e, a)| (&expected[*e], &analyzed[*a])) {
+            let values = [
+                (expected.weight_g, analyzed.weight_g),
+                (expected.calories, Some(analyzed.calories)),
+                (expected.protein_g, Some(analyzed.protein_g)),
+                (expected.fat_g, Some(analyzed.fat_g)),
+                (expected.carbohydrates_g, Some(analyzed.carbohydrates_g)),
+                (expected.sugar_g, Some(analyzed.sugar_g)),
+                (expected.sodium_mg, Some(analyzed.sodium_mg)),
+            ];
+            for (nutrient, (expected, analyzed)) in NUTRIENTS.iter().zip(values) {
+                if let (Some(expected), Some(analyzed)) = (expected, analyzed) {
+                    self.errors.entry(nutrient).or_default().push(((analyzed - expected).abs(), expected));
+                }
+            }
+        }
+
+        pairs.len()
+    }
+
+    fn print(&self) {
+        let answers = self.latencies_ms.len();
+        let images = self.provider_errors + answers;
+        println!("provider errors  {} ({})", self.provider_errors, percent(self.provider_errors, images));
+        println!("parse failures   {} ({} of the answers)", self.parse_failures, percent(self.parse_failures, answers));
+        println!(
+            "item recall      {} ({}/{} expected foods found)",
+            ratio(self.matched_foods, self.expected_foods),
+            self.matched_foods,
+            self.expected_foods
+        );
+        println!(
+            "item precision   {} ({}/{} analyzed foods expected)",
+            ratio(self.matched_foods, self.analyzed_foods),
+            self.matched_foods,
+            self.analyzed_foods
+        );
+
+        let mut latencies_ms = self.latencies_ms.clone();
+        latencies_ms.sort_by(f64::total_cmp);
+        if !latencies_ms.is_empty() {
+            let mean = latencies_ms.iter().sum::<f64>() / latencies_ms.len() as f64;
+            let quantile = |q: f64| latencies_ms[((latencies_ms.len() - 1) as f64 * q).round() as usize];
+            println!(
+                "latency ms       mean {mean:.0}, p50 {:.0}, p95 {:.0}, max {:.0}",
+                quantile(0.5),
+                quantile(0.95),
+                quantile(1.)
+            );
+        }
+
+        // MAPE leaves out the expected values of 0.
+        if self.errors.is_empty() {
+            return;
+        }
+        println!();
+        println!("{:<16} {:>6} {:>10} {:>8}", "nutrient", "n", "MAE", "MAPE");
+        for nutrient in NUTRIENTS {
+            let Some((n, mae, mape)) = self.nutrient_errors(nutrient) else {
+                continue;
+            };
+            let mape = mape.map_or("-".to_string(), |mape| format!("{:.1}%", mape * 100.));
+            println!("{nutrient:<16} {n:>6} {mae:>10.1} {mape:>8}");
+        }
+    }
+
+    /// The count, MAE and MAPE (a ratio, None when all the expected values are 0) of a nutrient,
+    /// None when no matched food has it.
+    fn nutrient_errors(&self, nutrient: &str) -> Option<(usize, f32, Option<f32>)> {
+        let errors = self.errors.get(nutrient).filter(|errors| !errors.is_empty())?;
+
+        let mae = errors.iter().map(|(error, _)| error).sum::<f32>() / errors.len() as f32;
+        let relative: Vec<f32> = errors
+            .iter()
+            .filter(|(_, expected)| *expected > 0.)
+            .map(|(error, expected)| error / expected)
+            .collect();
+        let mape = (!relative.is_empty()).then(|| relative.iter().sum::<f32>() / relative.len() as f32);
+
+        Some((errors.len(), mae, mape))
+    }
+}
+
+fn ratio(part: usize, total: usize) -> String {
+    match total {
+        0 => "-".to_string(),
+        total => format!("{:.2}", part as f64 / total as f64),
+    }
+}
+
+fn percent(part: usize, total: usize) -> String {
+    match total {
+        0 => "-".to_string(),
+        total => format!("{:.1}%", part as f64 / total as f64 * 100.),
+    }
+}
+
+// endregion: --- Report
+
+// region:    --- Food matching
+
+/// (expected, analyzed) index pairs, each food at most once, the most similar names first.
+fn match_foods(expected: &[ExpectedFood], analyzed: &[FoodItem]) -> Vec<(usize, usize)> {
+    let mut candidates: Vec<(f32, usize, usize)> = Vec::new();
+    for (e, expected_food) in expected.iter().enumerate() {
+        let names: Vec<HashSet<String>> = std::iter::once(&expected_food.name)
+            .chain(&expected_food.aliases)
+            .map(|name| words(name))
+            .collect();
+        for (a, analyzed_food) in analyzed.iter().enumerate() {
+            let analyzed_words = words(&analyzed_food.name);
+            let score = names
+                .iter()
+                .map(|name| jaccard(name, &analyzed_words))
+                .fold(0., f32::max);
+            if score >= MATCH_MIN_SCORE {
+                candidates.push((score, e, a));
+            }
+        }
+    }
+    candidates.sort_by(|x, y| y.0.total_cmp(&x.0));
+
+    let mut pairs = Vec::new();
+    let (mut used_expected, mut used_analyzed) = (HashSet::new(), HashSet::new());
+    for (_, e, a) in candidates {
+        if !used_expected.contains(&e) && !used_analyzed.contains(&a) {
+            used_expected.insert(e);
+            used_analyzed.insert(a);
+            pairs.push((e, a));
+        }
+    }
+
+    pairs
+}
+
+// Lowercase words, without a plural "s", e.g., "Boiled Eggs" is {"boiled", "egg"} ("hummus" is kept).
+fn words(name: &str) -> HashSet<String> {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| match word.strip_suffix('s') {
+            Some(singular) if singular.len() >= 3 && !singular.ends_with(['s', 'u']) => singular.to_string(),
+            _ => word.to_string(),
+        })
+        .collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.;
+    }
+    a.intersection(b).count() as f32 / union as f32
+}
+
+// endregion: --- Food matching
+
+fn read_json_lines<T: serde::de::DeserializeOwned>(file: &Path) -> Result<Vec<T>> {
+    let reader = BufReader::new(File::open(file).map_err(|ex| fail(file, ex))?);
+
+    let mut items = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|ex| fail(file, ex))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let item = serde_json::from_str(&line)
+            .map_err(|ex| Error::EvalFail(format!("{} line {} - {ex}", file.display(), index + 1)))?;
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+fn fail(file: &Path, cause: impl std::fmt::Display) -> Error {
+    Error::EvalFail(format!("{} - {cause}", file.display()))
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected(name: &str, aliases: &[&str], calories: f32, sugar_g: f32) -> ExpectedFood {
+        ExpectedFood {
+            name: name.to_string(),
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+            weight_g: None,
+            calories: Some(calories),
+            protein_g: None,
+            fat_g: None,
+            carbohydrates_g: None,
+            sugar_g: Some(sugar_g),
+            sodium_mg: None,
+        }
+    }
+
+    fn analyzed(name: &str, calories: f32, sugar_g: f32) -> FoodItem {
+        FoodItem {
+            name: name.to_string(),
+            weight_g: Some(100.),
+            calories,
+            protein_g: 0.,
+            fat_g: 0.,
+            carbohydrates_g: 0.,
+            sugar_g,
+            sodium_mg: 0.,
+            reference: None,
+        }
+    }
+
+    fn set(words: &[&str]) -> HashSet<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn test_words_singular() {
+        assert_eq!(words("Boiled Eggs"), set(&["boiled", "egg"]));
+        assert_eq!(words("hummus"), set(&["hummus"]));
+        assert_eq!(words("Glass of milk"), set(&["glass", "of", "milk"]));
+        assert_eq!(words("peas, chips"), set(&["pea", "chip"]));
+    }
+
+    #[test]
+    fn test_jaccard() {
+        assert_eq!(jaccard(&set(&["boiled", "egg"]), &set(&["egg"])), 0.5);
+        assert_eq!(jaccard(&set(&["rice"]), &set(&["rice"])), 1.);
+        assert_eq!(jaccard(&set(&[]), &set(&[])), 0.);
+    }
+
+    #[test]
+    fn test_match_foods_duplicate_names() {
+        // Two expected eggs, two analyzed eggs and a toast: one to one, each at most once.
+        let expected = [expected("egg", &[], 78., 0.), expected("egg", &[], 78., 0.)];
+        let analyzed = [analyzed("Eggs", 80., 0.), analyzed("toast", 90., 1.), analyzed("egg", 70., 0.)];
+
+        let mut pairs = match_foods(&expected, &analyzed);
+        pairs.sort();
+
+        assert_eq!(pairs.len(), 2);
+        assert_ne!(pairs[0].1, pairs[1].1);
+        assert!(pairs.iter().all(|(_, a)| *a != 1));
+    }
+
+    #[test]
+    fn test_match_foods_alias_and_best_first() {
+        let expected = [expected("garbanzo dip", &["hummus"], 170., 0.3), expected("pita bread", &[], 165., 0.8)];
+        let analyzed = [analyzed("bread", 160., 1.), analyzed("Hummus", 180., 0.5), analyzed("pita", 150., 1.)];
+
+        let mut pairs = match_foods(&expected, &analyzed);
+        pairs.sort();
+
+        // "pita" and "bread" both score 0.5 for "pita bread", only one is paired.
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[0], (0, 1));
+        assert_eq!(pairs[1].0, 1);
+    }
+
+    #[test]
+    fn test_match_foods_below_min_score() {
+        let pairs = match_foods(&[expected("grilled chicken breast", &[], 165., 0.)], &[analyzed("chicken", 200., 0.)]);
+        assert!(pairs.is_empty()); // 1/3
+    }
+
+    #[test]
+    fn test_report_recall_precision() {
+        let mut report = Report::default();
+        let found = report.add(
+            &[expected("egg", &[], 78., 0.), expected("rice", &[], 200., 0.)],
+            &[analyzed("eggs", 80., 0.), analyzed("toast", 90., 1.), analyzed("jam", 50., 10.)],
+        );
+
+        assert_eq!(found, 1);
+        assert_eq!((report.matched_foods, report.expected_foods, report.analyzed_foods), (1, 2, 3));
+        assert_eq!(ratio(report.matched_foods, report.expected_foods), "0.50");
+        assert_eq!(ratio(report.matched_foods, report.analyzed_foods), "0.33");
+    }
+
+    #[test]
+    fn test_report_mae_mape_skips_expected_zeros() {
+        let mut report = Report::default();
+        report.add(&[expected("egg", &[], 100., 0.)], &[analyzed("egg", 110., 2.)]);
+        report.add(&[expected("apple", &[], 50., 10.)], &[analyzed("apple", 40., 12.)]);
+
+        let (n, mae, mape) = report.nutrient_errors("calories").unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(mae, 10.);
+        assert!((mape.unwrap() - 0.15).abs() < 1e-6); // (10% + 20%) / 2
+
+        // The expected 0 g of sugar counts for the MAE, not for the MAPE.
+        let (n, mae, mape) = report.nutrient_errors("sugar_g").unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(mae, 2.);
+        assert!((mape.unwrap() - 0.2).abs() < 1e-6);
+
+        // No expected value, or only zeros.
+        assert!(report.nutrient_errors("protein_g").is_none());
+        let mut zeros = Report::default();
+        zeros.add(&[expected("egg", &[], 100., 0.)], &[analyzed("egg", 100., 1.)]);
+        assert_eq!(zeros.nutrient_errors("sugar_g").unwrap().2, None);
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,132 @@
+//! USDA FoodData Central CSV export (https://fdc.nal.usda.gov/download-datasets), read from its folder:
+//! - `food.csv`: the foods, only the generic ones are kept (see `IMPORTED_DATA_TYPES`).
+//! - `food_nutrient.csv`: their nutrients, per 100 g, only the ones in `Food` are kept.
+//! - `food_attribute.csv` (optional): the common names, used as aliases.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use csv::StringRecord;
+
+use crate::error::{Error, Result};
+use crate::model::food::FoodForImport;
+
+/// The default source name of the imported foods.
+pub const SOURCE: &str = "usda_fdc";
+
+// Branded foods are left to the barcode lookup, and the lab samples are not meant to be looked up.
+const IMPORTED_DATA_TYPES: &[&str] = &["foundation_food", "sr_legacy_food", "survey_fndds_food"];
+
+const ATTRIBUTE_TYPE_COMMON_NAME: &str = "1000";
+
+// FDC nutrient ids, by preference when a nutrient has several.
+const ENERGY_KCAL: &[u32] = &[1008, 2047, 2048];
+const PROTEIN: &[u32] = &[1003];
+const FAT: &[u32] = &[1004];
+const CARBOHYDRATES: &[u32] = &[1005, 1050];
+const FIBER: &[u32] = &[1079];
+const SUGARS: &[u32] = &[2000, 1063];
+const SODIUM_MG: &[u32] = &[1093];
+
+pub fn read_foods(dir: &Path) -> Result<Vec<FoodForImport>> {
+    let mut foods: Vec<FoodForImport> = Vec::new();
+    let mut index_by_fdc_id: HashMap<String, usize> = HashMap::new();
+
+    read_csv(dir, "food.csv", &["fdc_id", "data_type", "description"], |row| {
+        if IMPORTED_DATA_TYPES.contains(&row[1].as_str()) {
+            index_by_fdc_id.insert(row[0].to_string(), foods.len());
+            foods.push(FoodForImport {
+                source_id: row[0].to_string(),
+                name: row[2].trim().to_string(),
+                ..Default::default()
+            });
+        }
+        Ok(())
+    })?;
+
+    // The nutrient amounts, by food, then the preferred id of each nutrient is picked.
+    let mut amounts: HashMap<usize, HashMap<u32, f32>> = HashMap::new();
+    read_csv(dir, "food_nutrient.csv", &["fdc_id", "nutrient_id", "amount"], |row| {
+        let Some(&index) = index_by_fdc_id.get(&row[0]) else {
+            return Ok(());
+        };
+        if row[2].is_empty() {
+            return Ok(());
+        }
+        let (Ok(nutrient_id), Ok(amount)) = (row[1].parse::<u32>(), row[2].parse::<f32>()) else {
+            return Err(format!("invalid nutrient_id '{}' or amount '{}'", &row[1], &row[2]));
+        };
+        amounts.entry(index).or_default().insert(nutrient_id, amount);
+        Ok(())
+    })?;
+    for (index, amounts) in amounts {
+        let pick = |ids: &[u32]| ids.iter().find_map(|id| amounts.get(id).copied());
+        let food = &mut foods[index];
+        food.calories = pick(ENERGY_KCAL);
+        food.protein_g = pick(PROTEIN);
+        food.fat_g = pick(FAT);
+        food.carbohydrates_g = pick(CARBOHYDRATES);
+        food.fiber_g = pick(FIBER);
+        food.sugar_g = pick(SUGARS);
+        food.sodium_mg = pick(SODIUM_MG);
+    }
+
+    if dir.join("food_attribute.csv").exists() {
+        read_csv(dir, "food_attribute.csv", &["fdc_id", "food_attribute_type_id", "value"], |row| {
+            let alias = row[2].trim();
+            if row[1] == ATTRIBUTE_TYPE_COMMON_NAME && !alias.is_empty() {
+                if let Some(&index) = index_by_fdc_id.get(&row[0]) {
+                    foods[index].aliases.push(alias.to_string());
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(foods)
+}
+
+/// Calls `on_row` with the `columns` of each row, in that order.
+/// The errors give the file and line, e.g., `food.csv:12 - ...`.
+fn read_csv(
+    dir: &Path,
+    file: &str,
+    columns: &[&str],
+    mut on_row: impl FnMut(&[String]) -> core::result::Result<(), String>,
+) -> Result<()> {
+    let fail = |line: u64, cause: String| Error::ImportFail(format!("{file}:{line} - {cause}"));
+
+    let path = dir.join(file);
+    let reader = File::open(&path).map_err(|ex| Error::ImportFail(format!("{} - {ex}", path.display())))?;
+    let mut reader = csv::Reader::from_reader(reader);
+
+    let headers = reader.headers().map_err(|ex| fail(1, ex.to_string()))?.clone();
+    let indexes = columns
+        .iter()
+        .map(|column| {
+            headers
+                .iter()
+                .position(|header| header == *column)
+                .ok_or_else(|| fail(1, format!("missing column '{column}'")))
+        })
+        .collect::<Result<Vec<usize>>>()?;
+
+    let mut record = StringRecord::new();
+    let mut row = vec![String::new(); columns.len()];
+    loop {
+        match reader.read_record(&mut record) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(ex) => return Err(fail(reader.position().line(), ex.to_string())),
+        }
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        for (value, &index) in row.iter_mut().zip(&indexes) {
+            value.clear();
+            value.push_str(record.get(index).unwrap_or_default());
+        }
+        on_row(&row).map_err(|cause| fail(line, cause))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,207 @@
+//! Subcommands, run instead of the server, e.g., `foodeq-be-webapp migrate status`.
+//! - `migrate status`: the embedded and applied migrations.
+//! - `migrate up`: apply the pending migrations.
+//! - `migrate down [steps]`: revert the last applied migrations (1 by default).
+//! - `import-foods <dir> [source]`: import a USDA FoodData Central CSV export into the food reference.
+//! - `import-products <file>`: import an Open Food Facts CSV dump into the products (by barcode).
+//! - `export-corrections <admin> [file] [since]`: export the corrected analyses of all the users,
+//!   as json lines, as the given admin user.
+//! - `eval <dataset> [--prompt <file>] [--record <file>] [--replay <file>]`: evaluate a prompt and the provider.
+
+mod eval;
+mod fdc;
+mod off;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::config::ConfigNeeds;
+use crate::ctx::Ctx;
+use crate::error::{Error, Result};
+use crate::model::migrations::MigrationState;
+use crate::model::model::ModelManager;
+
+const USAGE: &str = "\
+usage: foodeq-be-webapp [command]
+  (no command)            serve
+  migrate status          list the migrations, applied or pending
+  migrate up              apply the pending migrations
+  migrate down [steps]    revert the last applied migrations (default 1)
+  import-foods <dir> [source]
+                          import a USDA FoodData Central CSV export (source default usda_fdc)
+  import-products <file>  import an Open Food Facts CSV dump (.csv or .csv.gz)
+  export-corrections <admin> [file] [since]
+                          export the corrected analyses of all the users as json lines (default
+                          stdout, or -), corrected since an RFC 3339 time when given;
+                          <admin> is the username of an admin user
+  eval <dataset> [--prompt <file>] [--record <file>] [--replay <file>]
+                          run a labelled dataset (json lines) through the provider, and report
+                          recall, nutrient errors, parse failures and latency (see docs/eval.md)";
+
+pub async fn run(args: &[String]) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["migrate", "status"] => migrate_status().await,
+        ["migrate", "up"] => migrate_up().await,
+        ["migrate", "down"] => migrate_down(1).await,
+        ["migrate", "down", steps] => match steps.parse::<usize>() {
+            Ok(steps) if steps > 0 => migrate_down(steps).await,
+            _ => usage_exit(),
+        },
+        ["import-foods", dir] => import_foods(Path::new(dir), fdc::SOURCE).await,
+        ["import-foods", dir, source] => import_foods(Path::new(dir), source).await,
+        ["import-products", file] => import_products(Path::new(file)).await,
+        ["export-corrections", admin] => export_corrections(admin, "-", None).await,
+        ["export-corrections", admin, file] => export_corrections(admin, file, None).await,
+        ["export-corrections", admin, file, since] => match OffsetDateTime::parse(since, &Rfc3339) {
+            Ok(since) => export_corrections(admin, file, Some(since)).await,
+            Err(_) => usage_exit(),
+        },
+        ["eval", eval_args @ ..] => match eval::EvalArgs::parse(eval_args) {
+            Some(eval_args) => eval::run(eval_args).await,
+            None => usage_exit(),
+        },
+        _ => usage_exit(),
+    }
+}
+
+/// The secrets of the command: the db url, but for `eval` which only needs the provider key,
+/// and not even that one when replaying.
+pub fn config_needs(args: &[String]) -> ConfigNeeds {
+    match args.first().map(String::as_str) {
+        Some("eval") => ConfigNeeds {
+            provider: !args.iter().any(|arg| arg == "--replay"),
+            ..ConfigNeeds::NONE
+        },
+        _ => ConfigNeeds { db: true, ..ConfigNeeds::NONE },
+    }
+}
+
+fn usage_exit() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
+
+// region:    --- Migrate
+
+async fn migrate_status() -> Result<()> {
+    let mm = ModelManager::new().await?;
+
+    println!("{:<8} {:<32} state", "version", "name");
+    for status in mm.migrations_status().await? {
+        let state = match status.state {
+            MigrationState::Applied { applied_at } => format!("applied {}", applied_at.format(&Rfc3339).unwrap_or_default()),
+            MigrationState::Pending => "pending".to_string(),
+            MigrationState::ChecksumMismatch => "applied, CHECKSUM MISMATCH (edited since)".to_string(),
+            MigrationState::Unknown => "applied, unknown to this binary (newer)".to_string(),
+        };
+        println!("{:<8} {:<32} {state}", format!("{:04}", status.version), status.name);
+    }
+
+    Ok(())
+}
+
+async fn migrate_up() -> Result<()> {
+    let mm = ModelManager::new().await?;
+
+    let applied = mm.migrate_up().await?;
+    println!("{} migration(s) applied {applied:?}", applied.len());
+
+    Ok(())
+}
+
+async fn migrate_down(steps: usize) -> Result<()> {
+    let mm = ModelManager::new().await?;
+
+    let reverted = mm.migrate_down(steps).await?;
+    println!("{} migration(s) reverted {reverted:?}", reverted.len());
+
+    Ok(())
+}
+
+// endregion: --- Migrate
+
+// region:    --- Import foods
+
+/// Re-importing the same `source` updates its foods (by their fdc_id).
+async fn import_foods(dir: &Path, source: &str) -> Result<()> {
+    let foods = fdc::read_foods(dir)?;
+
+    let mm = ModelManager::new().await?;
+    let count = mm.import_foods(source, &foods).await?;
+    println!("{count} food(s) imported from {} as {source}", dir.display());
+
+    Ok(())
+}
+
+// endregion: --- Import foods
+
+// region:    --- Import products
+
+const PRODUCTS_BATCH_SIZE: usize = 1000;
+const PRODUCTS_PROGRESS_EVERY: u64 = 100_000;
+
+/// Each batch is committed on its own, an interrupted import can be run again.
+async fn import_products(file: &Path) -> Result<()> {
+    let mut reader = off::OffReader::open(file)?;
+
+    let mm = ModelManager::new().await?;
+    let mut count = 0;
+    let mut next_progress = PRODUCTS_PROGRESS_EVERY;
+    loop {
+        let batch = reader.next_batch(PRODUCTS_BATCH_SIZE)?;
+        if batch.is_empty() {
+            break;
+        }
+        count += mm.import_products(off::SOURCE, &batch).await?;
+        if count >= next_progress {
+            println!("{count} product(s) imported...");
+            next_progress += PRODUCTS_PROGRESS_EVERY;
+        }
+    }
+    println!(
+        "{count} product(s) imported from {}, {} skipped (no valid barcode or name)",
+        file.display(),
+        reader.skipped
+    );
+
+    Ok(())
+}
+
+// endregion: --- Import products
+
+// region:    --- Export corrections
+
+/// One json line per corrected analysis, the analysis foods and the corrected foods (see `CorrectionPair`).
+/// The status line goes to stderr, so that stdout is only the export.
+/// The analyses of every user are exported, so only as an admin user (`Permission::CorrectionsExport`).
+async fn export_corrections(admin: &str, file: &str, since: Option<OffsetDateTime>) -> Result<()> {
+    let mm = ModelManager::new().await?;
+    let (user_id, role) = mm
+        .get_user_by_username(admin)
+        .await?
+        .ok_or_else(|| Error::ExportFail(format!("unknown user {admin}")))?;
+    let pairs = mm.list_correction_pairs(&Ctx::new(user_id, role), since).await?;
+
+    let output: Box<dyn Write> = if file == "-" {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(file).map_err(|ex| Error::ExportFail(format!("{file} - {ex}")))?)
+    };
+    let mut output = BufWriter::new(output);
+    for pair in &pairs {
+        let line = serde_json::to_string(pair).map_err(|ex| Error::ExportFail(ex.to_string()))?;
+        writeln!(output, "{line}").map_err(|ex| Error::ExportFail(format!("{file} - {ex}")))?;
+    }
+    output.flush().map_err(|ex| Error::ExportFail(format!("{file} - {ex}")))?;
+    eprintln!("{} corrected analyses exported to {file}", pairs.len());
+
+    Ok(())
+}
+
+// endregion: --- Export corrections
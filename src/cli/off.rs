@@ -0,0 +1,166 @@
+//! Open Food Facts CSV dump (https://world.openfoodfacts.org/data), `en.openfoodfacts.org.products.csv`,
+//! or its `.gz`, read as a stream, in batches: the full dump is several GB.
+//! It is tab separated, without quoting. Nutrients are per 100 g, sodium in g.
+//! Products without a valid EAN-13/UPC-A barcode, or without a name, are skipped.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use csv::StringRecord;
+use flate2::read::MultiGzDecoder;
+
+use crate::error::{Error, Result};
+use crate::model::product::{normalize_barcode, ProductForImport};
+
+pub const SOURCE: &str = "open_food_facts";
+
+const KJ_PER_KCAL: f32 = 4.184;
+
+// The product column sizes, longer values are cut.
+const NAME_MAX_CHARS: usize = 512;
+const BRAND_MAX_CHARS: usize = 256;
+const SERVING_SIZE_MAX_CHARS: usize = 128;
+
+pub struct OffReader {
+    reader: csv::Reader<Box<dyn Read>>,
+    columns: Columns,
+    record: StringRecord,
+    pub skipped: u64,
+}
+
+// Indexes in the header, the nutrients are optional (older dumps have no energy-kcal).
+struct Columns {
+    code: usize,
+    product_name: usize,
+    brands: Option<usize>,
+    serving_size: Option<usize>,
+    serving_quantity: Option<usize>,
+    energy_kcal: Option<usize>,
+    energy_kj: Option<usize>,
+    proteins: Option<usize>,
+    fat: Option<usize>,
+    carbohydrates: Option<usize>,
+    sugars: Option<usize>,
+    sodium: Option<usize>,
+}
+
+impl OffReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|ex| Error::ImportFail(format!("{} - {ex}", path.display())))?;
+        let input: Box<dyn Read> = if path.extension().is_some_and(|ext| ext == "gz") {
+            Box::new(MultiGzDecoder::new(BufReader::new(file)))
+        } else {
+            Box::new(file)
+        };
+
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .quoting(false)
+            .flexible(true)
+            .from_reader(input);
+
+        let headers = reader.headers().map_err(|ex| fail(1, ex))?.clone();
+        let find = |name: &str| headers.iter().position(|header| header == name);
+        let required = |name: &str| find(name).ok_or_else(|| fail(1, format!("missing column '{name}'")));
+        let columns = Columns {
+            code: required("code")?,
+            product_name: required("product_name")?,
+            brands: find("brands"),
+            serving_size: find("serving_size"),
+            serving_quantity: find("serving_quantity"),
+            energy_kcal: find("energy-kcal_100g"),
+            energy_kj: find("energy_100g"),
+            proteins: find("proteins_100g"),
+            fat: find("fat_100g"),
+            carbohydrates: find("carbohydrates_100g"),
+            sugars: find("sugars_100g"),
+            sodium: find("sodium_100g"),
+        };
+
+        Ok(Self {
+            reader,
+            columns,
+            record: StringRecord::new(),
+            skipped: 0,
+        })
+    }
+
+    /// Up to `size` products, empty at the end of the dump.
+    /// A barcode is only once in a batch (the last one wins), as the dump has a few duplicates.
+    pub fn next_batch(&mut self, size: usize) -> Result<Vec<ProductForImport>> {
+        let mut batch: Vec<ProductForImport> = Vec::with_capacity(size);
+        let mut index_by_barcode: HashMap<String, usize> = HashMap::new();
+
+        while batch.len() < size {
+            match self.reader.read_record(&mut self.record) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(ex) => return Err(fail(self.reader.position().line(), ex)),
+            }
+
+            let Some(product) = self.product() else {
+                self.skipped += 1;
+                continue;
+            };
+            match index_by_barcode.get(&product.barcode) {
+                Some(&index) => batch[index] = product,
+                None => {
+                    index_by_barcode.insert(product.barcode.clone(), batch.len());
+                    batch.push(product);
+                }
+            }
+        }
+
+        Ok(batch)
+    }
+
+    fn product(&self) -> Option<ProductForImport> {
+        let c = &self.columns;
+        let text = |index: Option<usize>| {
+            index
+                .and_then(|index| self.record.get(index))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        // Missing, invalid or negative values are unknown, the dump is user contributed.
+        let number = |index: Option<usize>| {
+            text(index)
+                .and_then(|value| value.parse::<f32>().ok())
+                .filter(|value| value.is_finite() && *value >= 0.)
+        };
+
+        let barcode = normalize_barcode(&text(Some(c.code))?)?;
+        let name = cut(text(Some(c.product_name))?, NAME_MAX_CHARS);
+        // The first of the brands, e.g., "Ferrero, Nutella".
+        let brand = text(c.brands)
+            .and_then(|brands| brands.split(',').next().map(|brand| cut(brand.trim().to_string(), BRAND_MAX_CHARS)));
+
+        Some(ProductForImport {
+            barcode,
+            name,
+            brand,
+            serving_size: text(c.serving_size).map(|serving_size| cut(serving_size, SERVING_SIZE_MAX_CHARS)),
+            serving_g: number(c.serving_quantity).filter(|serving_g| *serving_g > 0.),
+            calories: number(c.energy_kcal).or_else(|| number(c.energy_kj).map(|kj| kj / KJ_PER_KCAL)),
+            protein_g: number(c.proteins),
+            fat_g: number(c.fat),
+            carbohydrates_g: number(c.carbohydrates),
+            sugar_g: number(c.sugars),
+            sodium_mg: number(c.sodium).map(|sodium_g| sodium_g * 1000.),
+        })
+    }
+}
+
+fn cut(value: String, max_chars: usize) -> String {
+    match value.char_indices().nth(max_chars) {
+        Some((end, _)) => value[..end].to_string(),
+        None => value,
+    }
+}
+
+fn fail(line: u64, cause: impl std::fmt::Display) -> Error {
+    Error::ImportFail(format!("line {line} - {cause}"))
+}
@@ -1,12 +1,74 @@
+mod role;
+
+pub use role::{Permission, Role};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
 #[derive(Clone, Debug)]
 pub struct Ctx {
     user_id: u64,
+    role: Role,
+    auth_method: AuthMethod,
+    // None means unrestricted (session auth), Some(..) are the api key scopes.
+    scopes: Option<Vec<Scope>>,
 }
 
-// Constructor.
+/// How the request was authenticated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthMethod {
+    Cookie,
+    Bearer,
+    ApiKey,
+}
+
+/// Permissions that can be granted to an api key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "tickets:read")]
+    TicketsRead,
+    #[serde(rename = "tickets:write")]
+    TicketsWrite,
+    #[serde(rename = "analyze")]
+    Analyze,
+    #[serde(rename = "foods:read")]
+    FoodsRead,
+    #[serde(rename = "recipes:read")]
+    RecipesRead,
+    #[serde(rename = "recipes:write")]
+    RecipesWrite,
+    #[serde(rename = "meals:read")]
+    MealsRead,
+    #[serde(rename = "meals:write")]
+    MealsWrite,
+}
+
+// Constructors.
 impl Ctx {
-    pub fn new(user_id: u64) -> Self {
-        Self { user_id }
+    pub fn new(user_id: u64, role: Role) -> Self {
+        Self {
+            user_id,
+            role,
+            auth_method: AuthMethod::Cookie,
+            scopes: None,
+        }
+    }
+
+    pub fn new_bearer(user_id: u64, role: Role) -> Self {
+        Self {
+            auth_method: AuthMethod::Bearer,
+            ..Self::new(user_id, role)
+        }
+    }
+
+    pub fn new_api_key(user_id: u64, role: Role, scopes: Vec<Scope>) -> Self {
+        Self {
+            user_id,
+            role,
+            auth_method: AuthMethod::ApiKey,
+            scopes: Some(scopes),
+        }
     }
 }
 
@@ -15,4 +77,40 @@ impl Ctx {
     pub fn user_id(&self) -> u64 {
         self.user_id
     }
-}
\ No newline at end of file
+
+    pub fn auth_method(&self) -> AuthMethod {
+        self.auth_method
+    }
+}
+
+// Guards.
+impl Ctx {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.role.has_permission(permission)
+    }
+
+    pub fn require_permission(&self, permission: Permission) -> Result<()> {
+        if self.has_permission(permission) {
+            Ok(())
+        } else {
+            Err(Error::PermissionDenied { permission })
+        }
+    }
+
+    pub fn require_scope(&self, scope: Scope) -> Result<()> {
+        match &self.scopes {
+            Some(scopes) if !scopes.contains(&scope) => {
+                Err(Error::AuthFailApiKeyScopeMissing { scope })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Api keys cannot be used to manage other api keys.
+    pub fn require_session(&self) -> Result<()> {
+        match self.auth_method {
+            AuthMethod::ApiKey => Err(Error::AuthFailApiKeyNotAllowed),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// Role of a user, stored in the `"user".role` column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Dietitian,
+    Admin,
+}
+
+/// Actions which are not granted to every authenticated user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Permission {
+    TicketsListAll,
+    TicketsDeleteAny,
+    ClientsRead,
+    CorrectionsExport, // every user's analyses and corrections, for the prompt evaluation
+}
+
+impl Role {
+    pub fn from_db(role: &str) -> Option<Self> {
+        match role {
+            "user" => Some(Self::User),
+            "dietitian" => Some(Self::Dietitian),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        match self {
+            Self::Admin => true,
+            Self::Dietitian => matches!(permission, Permission::ClientsRead),
+            Self::User => false,
+        }
+    }
+}
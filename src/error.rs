@@ -3,27 +3,194 @@ use reqwest::StatusCode;
 use serde::Serialize;
 use tracing::info;
 
+use crate::ctx::{Permission, Scope};
+use crate::model::analysis_quota::{QuotaPeriod, QuotaStatus};
+use crate::web::valid_json::InvalidParam;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, Clone, Serialize, strum_macros::AsRefStr)]
 #[serde(tag = "type", content = "data")]
-#[allow(dead_code)] // TODO: remove this when all errors are handled
 pub enum Error {
     // -- Config
     ConfigMissingEnv(&'static str),
+    ConfigInvalid(Vec<String>),
+
+    // -- Serve
+    ServeFail(String),
 
     // -- Login errors
     LoginFail,
+    LoginLocked {
+        retry_after_secs: u64,
+    },
+
+    // -- CSRF errors
+    CsrfFailNoOrigin,
+    CsrfFailOriginNotAllowed {
+        origin: String,
+    },
+
+    // -- Rate limit errors
+    RateLimited {
+        retry_after_secs: u64,
+    },
+
+    // -- Request body errors
+    ReqBodyMissingJsonContentType,
+    ReqBodySyntaxFail {
+        invalid_params: Vec<InvalidParam>,
+    },
+    ReqBodyDataFail {
+        invalid_params: Vec<InvalidParam>,
+    },
+    ReqBodyValidationFail {
+        invalid_params: Vec<InvalidParam>,
+    },
 
     // -- Model errors
     TicketDeleteFailIdNotFound {
         id: u64,
     },
+    ApiKeyRevokeFailIdNotFound {
+        id: i64,
+    },
+    ApiKeyCreateFailNoScopes,
+    AccessGrantFailNotDietitian {
+        dietitian_id: i64,
+    },
+    AccessRevokeFailNotFound {
+        dietitian_id: i64,
+    },
+    UserCreateFailUsernameTaken {
+        username: String,
+    },
+    FoodNotFound {
+        id: i64,
+    },
+    FoodSearchFailQueryBlank,
+    ProductNotFound {
+        barcode: String,
+    },
+    BarcodeInvalid {
+        barcode: String,
+    },
+    RecipeNotFound {
+        id: i64,
+    },
+    RecipeFailFoodNotFound {
+        food_id: i64,
+    },
+    RecipeFailAnalyzedItemNotFound {
+        analysis_id: i64,
+        item_index: usize,
+    },
+    RecipeFailIngredientInvalid {
+        index: usize,
+    },
+    RecipeScaleFailServingsInvalid {
+        servings: f32,
+    },
+    MealsReadFailNoAccess {
+        client_id: i64,
+    },
+    AnalysisNotFound {
+        id: i64,
+    },
+    AnalysisCorrectFailIndexInvalid {
+        index: usize,
+    },
+
+    // -- Nutrition errors
+    QuotaExceeded {
+        period: QuotaPeriod,
+        limit: i32,
+        reset_at: String, // RFC 3339
+        #[serde(skip_serializing)]
+        quota: Box<QuotaStatus>, // for the quota headers of the response
+    },
+    NutritionProviderFail(String),
+
+    // -- Import, export and evaluation errors (cli commands)
+    ImportFail(String),
+    ExportFail(String),
+    EvalFail(String),
+
+    // -- Token errors
+    TokenKeyFailHmac,
+
+    // -- Telemetry errors
+    TelemetryInitFail(String),
+
+    // -- Log sink errors
+    LogSinkFail(String),
+    LogSinkAlreadyInitialized,
+
+    // -- OpenID Connect errors
+    OidcDiscoveryFail(String),
+    OidcStateMismatch,
+    OidcProviderFail(String),
+    OidcTokenExchangeFail(String),
+    OidcIdTokenInvalid(String),
+
+    // -- Db errors
+    DbFailToCreatePool(String),
+    DbQueryFail(String),
+    SqlSplitFail {
+        file: String,
+        line: usize,
+        cause: String,
+    },
+    SqlExecFail {
+        file: String,
+        statement: usize,
+        line: usize,
+        cause: String,
+    },
+
+    // -- Migration errors
+    MigrationChecksumMismatch {
+        version: i64,
+        name: String,
+    },
+    MigrationFail {
+        file: String,
+        statement: usize,
+        line: usize,
+        cause: String,
+    },
+    MigrationNoDown {
+        version: i64,
+    },
+    MigrationUnknown {
+        version: i64,
+    },
 
     // -- Auth errors
     AuthFailNoAuthTokenCookie,
     AuthFailTokenWrongFormat,
-    AuthFailCtxNotInRequestExt
+    AuthFailTokenSignatureNotMatching,
+    AuthFailTokenExpired,
+    AuthFailCtxNotInRequestExt,
+    AuthFailBearerWrongFormat,
+    AuthFailApiKeyInvalid,
+    AuthFailApiKeyNotAllowed,
+    AuthFailApiKeyScopeMissing {
+        scope: Scope,
+    },
+    AuthFailUserNotFound {
+        user_id: u64,
+    },
+    AuthFailUnknownRole(String),
+
+    // -- Authorization errors
+    PermissionDenied {
+        permission: Permission,
+    },
+
+    // -- Service errors
+    ReqStampNotInReqExt,
+    ServicePanic(String), // panic message, caught by the CatchPanicLayer
 }
 
 impl std::fmt::Display for Error {
@@ -34,6 +201,12 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+impl From<sqlx::Error> for Error {
+    fn from(val: sqlx::Error) -> Self {
+        Self::DbQueryFail(val.to_string())
+    }
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response<axum::body::Body> {
         info!("{:<12} - {self:?}", "INTO_RES");
@@ -52,31 +225,196 @@ impl Error {
     pub fn client_status_and_error(&self) -> (StatusCode, ClientError) {
         match self {
             Self::LoginFail => (StatusCode::FORBIDDEN, ClientError::LOGIN_FAIL),
+            // - CSRF errors
+            Self::CsrfFailNoOrigin | Self::CsrfFailOriginNotAllowed { .. } => {
+                        (StatusCode::FORBIDDEN, ClientError::CSRF_FAIL)
+                    }
+            // - Rate limit errors
+            Self::LoginLocked { retry_after_secs } | Self::RateLimited { retry_after_secs } => (
+                        StatusCode::TOO_MANY_REQUESTS,
+                        ClientError::RATE_LIMITED {
+                            retry_after_secs: *retry_after_secs,
+                        },
+                    ),
             // - Auth errors
             Self::AuthFailNoAuthTokenCookie
                                     | Self::AuthFailTokenWrongFormat
-                                    | Self::AuthFailCtxNotInRequestExt => {
+                                    | Self::AuthFailTokenSignatureNotMatching
+                                    | Self::AuthFailTokenExpired
+                                    | Self::AuthFailCtxNotInRequestExt
+                                    | Self::AuthFailBearerWrongFormat
+                                    | Self::AuthFailApiKeyInvalid
+                                    | Self::AuthFailApiKeyNotAllowed
+                                    | Self::AuthFailUserNotFound { .. }
+                                    | Self::AuthFailUnknownRole(_) => {
                         (StatusCode::FORBIDDEN, ClientError::NO_AUTH)
                     }
+            // - Authorization errors
+            Self::PermissionDenied { .. }
+                                    | Self::AuthFailApiKeyScopeMissing { .. }
+                                    | Self::MealsReadFailNoAccess { .. } => {
+                        (StatusCode::FORBIDDEN, ClientError::PERMISSION_DENIED)
+                    }
+            // - Request body errors
+            Self::ReqBodyMissingJsonContentType => (
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        ClientError::INVALID_BODY {
+                            invalid_params: vec![InvalidParam::new(
+                                "content-type",
+                                "expected application/json",
+                            )],
+                        },
+                    ),
+            Self::ReqBodySyntaxFail { invalid_params } => (
+                        StatusCode::BAD_REQUEST,
+                        ClientError::INVALID_BODY {
+                            invalid_params: invalid_params.clone(),
+                        },
+                    ),
+            Self::ReqBodyDataFail { invalid_params }
+                                    | Self::ReqBodyValidationFail { invalid_params } => (
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        ClientError::INVALID_BODY {
+                            invalid_params: invalid_params.clone(),
+                        },
+                    ),
             // - Model errors
-            Self::TicketDeleteFailIdNotFound { .. } => {
+            Self::TicketDeleteFailIdNotFound { .. }
+                                    | Self::ApiKeyRevokeFailIdNotFound { .. }
+                                    | Self::ApiKeyCreateFailNoScopes
+                                    | Self::AccessGrantFailNotDietitian { .. }
+                                    | Self::AccessRevokeFailNotFound { .. }
+                                    | Self::FoodSearchFailQueryBlank
+                                    | Self::BarcodeInvalid { .. }
+                                    | Self::RecipeFailFoodNotFound { .. }
+                                    | Self::RecipeFailAnalyzedItemNotFound { .. }
+                                    | Self::RecipeFailIngredientInvalid { .. }
+                                    | Self::RecipeScaleFailServingsInvalid { .. }
+                                    | Self::AnalysisCorrectFailIndexInvalid { .. } => {
                         (StatusCode::BAD_REQUEST, ClientError::INVALID_PARAMS)
                     }
-            Error::ConfigMissingEnv(_) => todo!(),
-            // - Fallback
-            // _ => (
-            //     StatusCode::INTERNAL_SERVER_ERROR,
-            //     ClientError::SERVICE_ERROR,
-            // ),
+            Self::FoodNotFound { .. }
+                                    | Self::ProductNotFound { .. }
+                                    | Self::RecipeNotFound { .. }
+                                    | Self::AnalysisNotFound { .. } => {
+                        (StatusCode::NOT_FOUND, ClientError::NOT_FOUND)
+                    }
+            // - Nutrition errors
+            Self::QuotaExceeded { period, limit, reset_at, .. } => (
+                        StatusCode::TOO_MANY_REQUESTS,
+                        ClientError::QUOTA_EXCEEDED {
+                            period: *period,
+                            limit: *limit,
+                            reset_at: reset_at.clone(),
+                        },
+                    ),
+            Self::NutritionProviderFail(_) => (
+                        StatusCode::BAD_GATEWAY,
+                        ClientError::SERVICE_ERROR,
+                    ),
+            // - OpenID Connect errors
+            Self::OidcStateMismatch
+                                    | Self::OidcProviderFail(_)
+                                    | Self::OidcIdTokenInvalid(_) => {
+                        (StatusCode::FORBIDDEN, ClientError::LOGIN_FAIL)
+                    }
+            Self::OidcDiscoveryFail(_) | Self::OidcTokenExchangeFail(_) => (
+                        StatusCode::BAD_GATEWAY,
+                        ClientError::SERVICE_ERROR,
+                    ),
+            // - Db errors
+            Self::DbFailToCreatePool(_)
+                                    | Self::DbQueryFail(_)
+                                    | Self::SqlSplitFail { .. }
+                                    | Self::SqlExecFail { .. }
+                                    | Self::MigrationChecksumMismatch { .. }
+                                    | Self::MigrationFail { .. }
+                                    | Self::MigrationNoDown { .. }
+                                    | Self::MigrationUnknown { .. } => (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        ClientError::SERVICE_ERROR,
+                    ),
+            // - Service errors
+            //   No wildcard on purpose, so that a new variant has to be mapped explicitly.
+            Self::ConfigMissingEnv(_)
+                                    | Self::ConfigInvalid(_)
+                                    | Self::ServeFail(_)
+                                    | Self::TokenKeyFailHmac
+                                    | Self::TelemetryInitFail(_)
+                                    | Self::LogSinkFail(_)
+                                    | Self::LogSinkAlreadyInitialized
+                                    | Self::ImportFail(_)
+                                    | Self::ExportFail(_)
+                                    | Self::EvalFail(_)
+                                    | Self::UserCreateFailUsernameTaken { .. }
+                                    | Self::ReqStampNotInReqExt
+                                    | Self::ServicePanic(_) => (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        ClientError::SERVICE_ERROR,
+                    ),
         }
     }
 }
 
-#[derive(Debug, strum_macros::AsRefStr)]
+#[derive(Debug, Serialize, strum_macros::AsRefStr)]
+#[serde(tag = "type", content = "data")]
 #[allow(non_camel_case_types)]
 pub enum ClientError {
     LOGIN_FAIL,
     NO_AUTH,
+    PERMISSION_DENIED,
+    CSRF_FAIL,
     INVALID_PARAMS,
-    // SERVICE_ERROR,
-}
\ No newline at end of file
+    NOT_FOUND,
+    INVALID_BODY {
+        invalid_params: Vec<InvalidParam>,
+    },
+    QUOTA_EXCEEDED {
+        period: QuotaPeriod,
+        limit: i32,
+        reset_at: String,
+    },
+    RATE_LIMITED {
+        retry_after_secs: u64,
+    },
+    SERVICE_ERROR,
+}
+// Used for the `application/problem+json` (RFC 7807) responses.
+impl ClientError {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::LOGIN_FAIL => "Login failed",
+            Self::NO_AUTH => "Not authenticated",
+            Self::PERMISSION_DENIED => "Permission denied",
+            Self::CSRF_FAIL => "Cross-site request refused",
+            Self::INVALID_PARAMS => "Invalid parameters",
+            Self::NOT_FOUND => "Not found",
+            Self::INVALID_BODY { .. } => "Invalid request body",
+            Self::QUOTA_EXCEEDED { .. } => "Analysis quota exceeded",
+            Self::RATE_LIMITED { .. } => "Too many requests",
+            Self::SERVICE_ERROR => "Service error",
+        }
+    }
+
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            Self::INVALID_BODY { invalid_params } => Some(format!(
+                "{} invalid parameter(s) in the request body.",
+                invalid_params.len()
+            )),
+            Self::QUOTA_EXCEEDED { period, limit, reset_at } => {
+                let period = match period {
+                    QuotaPeriod::Daily => "daily",
+                    QuotaPeriod::Monthly => "monthly",
+                };
+                Some(format!(
+                    "The {period} limit of {limit} analyses is reached, it resets at {reset_at}."
+                ))
+            }
+            Self::RATE_LIMITED { retry_after_secs } => {
+                Some(format!("Retry after {retry_after_secs} seconds."))
+            }
+            _ => None,
+        }
+    }
+}
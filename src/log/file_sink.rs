@@ -0,0 +1,174 @@
+//! JSON-lines file, written by a dedicated thread.
+//! When the next line would exceed `max_bytes`, `app.log` is renamed to `app.log.1`
+//! (`app.log.1` to `app.log.2`, ...), keeping at most `max_files` rotated files.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+use tracing::warn;
+
+use crate::error::{Error, Result};
+use crate::log::sink::LogSink;
+
+enum SinkMsg {
+    Line(Value),
+    // Acked once the lines queued before it are written and flushed.
+    Flush(Sender<()>),
+}
+
+pub struct FileSink {
+    tx: SyncSender<SinkMsg>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl FileSink {
+    pub fn new(path: PathBuf, max_bytes: u64, max_files: usize, buffer_size: usize) -> Result<Self> {
+        let writer = RotatingWriter::open(path, max_bytes, max_files)?;
+        let (tx, rx) = mpsc::sync_channel(buffer_size);
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        let thread_dropped = dropped.clone();
+        thread::Builder::new()
+            .name("log-file-sink".to_string())
+            .spawn(move || write_loop(writer, rx, thread_dropped))
+            .map_err(|ex| Error::LogSinkFail(ex.to_string()))?;
+
+        Ok(Self { tx, dropped })
+    }
+}
+
+impl LogSink for FileSink {
+    fn send(&self, line: Value) {
+        match self.tx.try_send(SinkMsg::Line(line)) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                warn!("{:<12} - file sink writer stopped, log line lost", "LOG_SINK");
+            }
+        }
+    }
+
+    fn flush(&self, timeout: Duration) {
+        let (ack_tx, ack_rx) = mpsc::channel();
+        // Waits for room in the buffer, the writer thread is local.
+        let flushed = self.tx.send(SinkMsg::Flush(ack_tx)).is_ok() && ack_rx.recv_timeout(timeout).is_ok();
+        if !flushed {
+            warn!("{:<12} - file sink flush not done in time, log lines may be lost", "LOG_SINK");
+        }
+    }
+}
+
+fn write_loop(mut writer: RotatingWriter, rx: Receiver<SinkMsg>, dropped: Arc<AtomicU64>) {
+    // Block for the first line, then write what is already queued before flushing.
+    while let Ok(msg) = rx.recv() {
+        let mut flush_acks = Vec::new();
+        for msg in std::iter::once(msg).chain(rx.try_iter()) {
+            match msg {
+                SinkMsg::Line(line) => {
+                    if let Err(ex) = writer.write_line(&line) {
+                        warn!("{:<12} - file sink write failed - {ex}", "LOG_SINK");
+                    }
+                }
+                SinkMsg::Flush(ack) => flush_acks.push(ack),
+            }
+        }
+        if let Err(ex) = writer.flush() {
+            warn!("{:<12} - file sink flush failed - {ex}", "LOG_SINK");
+        }
+        for ack in flush_acks {
+            let _ = ack.send(());
+        }
+
+        let dropped = dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            warn!("{:<12} - file sink buffer full, {dropped} log lines dropped", "LOG_SINK");
+        }
+    }
+}
+
+// region:    --- Rotating Writer
+
+struct RotatingWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: BufWriter<File>,
+    size: u64,
+}
+
+impl RotatingWriter {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|ex| Error::LogSinkFail(ex.to_string()))?;
+        }
+        let file = open_append(&path).map_err(|ex| Error::LogSinkFail(ex.to_string()))?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files,
+            file: BufWriter::new(file),
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &Value) -> std::io::Result<()> {
+        let mut bytes = serde_json::to_vec(line)?;
+        bytes.push(b'\n');
+
+        if self.size > 0 && self.size + bytes.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+
+        self.file.write_all(&bytes)?;
+        self.size += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            // The oldest one is overwritten by the rename.
+            for i in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, i);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, i + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        self.file = BufWriter::new(open_append(&self.path)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn rotated_path(path: &Path, i: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{i}"));
+    rotated.into()
+}
+
+// endregion: --- Rotating Writer
@@ -0,0 +1,167 @@
+//! Batches of log lines POSTed as a JSON array to a collector.
+//! A batch is sent when it has `batch_size` lines, or `flush_interval_ms` after its first line.
+//! Failed batches are retried with backoff; while the collector is slow or down,
+//! the bounded buffer fills up and new lines are dropped (counted), so requests never wait.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc as std_mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use reqwest::header::AUTHORIZATION;
+use serde_json::Value;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio::time::{sleep, timeout_at, Instant};
+use tracing::warn;
+
+use crate::log::sink::LogSink;
+
+const SEND_ATTEMPTS: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const FLUSH_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+enum SinkMsg {
+    Line(Value),
+    // Acked once the lines queued before it are exported (or given up).
+    Flush(std_mpsc::Sender<()>),
+}
+
+pub struct HttpSink {
+    tx: Sender<SinkMsg>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl HttpSink {
+    pub fn new(
+        url: String,
+        authorization: Option<String>,
+        batch_size: usize,
+        flush_interval_ms: u64,
+        buffer_size: usize,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel(buffer_size);
+        let dropped = Arc::new(AtomicU64::new(0));
+
+        let exporter = Exporter {
+            http: reqwest::Client::new(),
+            url,
+            authorization,
+            batch_size: batch_size.max(1),
+            flush_interval: Duration::from_millis(flush_interval_ms),
+            dropped: dropped.clone(),
+        };
+        tokio::spawn(exporter.run(rx));
+
+        Self { tx, dropped }
+    }
+}
+
+impl LogSink for HttpSink {
+    fn send(&self, line: Value) {
+        match self.tx.try_send(SinkMsg::Line(line)) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Closed(_)) => {
+                warn!("{:<12} - http sink exporter stopped, log line lost", "LOG_SINK");
+            }
+        }
+    }
+
+    fn flush(&self, timeout: Duration) {
+        let deadline = std::time::Instant::now() + timeout;
+        let (ack_tx, ack_rx) = std_mpsc::channel();
+
+        // The buffer may be full while the collector is slow, retry until the deadline.
+        let mut msg = SinkMsg::Flush(ack_tx);
+        let flushed = loop {
+            match self.tx.try_send(msg) {
+                Ok(()) => {
+                    let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                    break ack_rx.recv_timeout(remaining).is_ok();
+                }
+                Err(TrySendError::Full(full_msg)) if std::time::Instant::now() < deadline => {
+                    msg = full_msg;
+                    thread::sleep(FLUSH_RETRY_DELAY);
+                }
+                Err(_) => break false,
+            }
+        };
+        if !flushed {
+            warn!("{:<12} - http sink flush not done in time, log lines may be lost", "LOG_SINK");
+        }
+    }
+}
+
+struct Exporter {
+    http: reqwest::Client,
+    url: String,
+    authorization: Option<String>,
+    batch_size: usize,
+    flush_interval: Duration,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Exporter {
+    async fn run(self, mut rx: Receiver<SinkMsg>) {
+        let mut batch = Vec::with_capacity(self.batch_size);
+
+        // Wait for the first line of a batch, then fill it until full, due, or flushed.
+        while let Some(msg) = rx.recv().await {
+            let mut flush_ack = match msg {
+                SinkMsg::Line(line) => {
+                    batch.push(line);
+                    None
+                }
+                SinkMsg::Flush(ack) => Some(ack),
+            };
+            let deadline = Instant::now() + self.flush_interval;
+            while flush_ack.is_none() && batch.len() < self.batch_size {
+                match timeout_at(deadline, rx.recv()).await {
+                    Ok(Some(SinkMsg::Line(line))) => batch.push(line),
+                    Ok(Some(SinkMsg::Flush(ack))) => flush_ack = Some(ack),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+
+            if !batch.is_empty() {
+                self.export(&batch).await;
+                batch.clear();
+            }
+            if let Some(ack) = flush_ack {
+                let _ = ack.send(());
+            }
+
+            let dropped = self.dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                warn!("{:<12} - http sink buffer full, {dropped} log lines dropped", "LOG_SINK");
+            }
+        }
+    }
+
+    async fn export(&self, batch: &[Value]) {
+        for attempt in 0..SEND_ATTEMPTS {
+            if attempt > 0 {
+                sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+            }
+
+            let mut req = self.http.post(&self.url).timeout(REQUEST_TIMEOUT).json(batch);
+            if let Some(authorization) = &self.authorization {
+                req = req.header(AUTHORIZATION, authorization);
+            }
+
+            match req.send().await.and_then(|res| res.error_for_status()) {
+                Ok(_) => return,
+                Err(ex) => warn!(
+                    "{:<12} - http sink export failed (attempt {}) - {ex}",
+                    "LOG_SINK",
+                    attempt + 1
+                ),
+            }
+        }
+
+        warn!("{:<12} - http sink gave up, {} log lines lost", "LOG_SINK", batch.len());
+    }
+}
@@ -1,41 +1,82 @@
-use std::{time::{SystemTime, UNIX_EPOCH}};
+mod file_sink;
+mod http_sink;
+mod sink;
+
+pub use sink::{flush_log_sink, init_log_sink, LogSinkKind};
+
+use std::net::IpAddr;
+
 use crate::{
-    ctx::Ctx, 
-    error::{ClientError, Error, Result}
+    ctx::Ctx,
+    error::{ClientError, Error, Result},
+    middlewares::mw_req_stamp::ReqStamp,
+    nutrition::ProviderCall,
 };
 use axum::http::Uri;
 use reqwest::Method;
 use serde::Serialize;
 use serde_json::{json, Value};
 use serde_with::skip_serializing_none;
+use time::format_description::well_known::Rfc3339;
 use tracing::debug;
-use uuid::Uuid;
+
+use sink::log_sink;
+
+/// Request attributes, gathered by the response mapper.
+pub struct ReqLogInfo {
+    pub method: Method,
+    pub uri: Uri,
+    pub matched_path: Option<String>, // route template, e.g., "/api/tickets/{id}"
+    pub client_ip: IpAddr,
+    pub user_agent: Option<String>,
+    pub size: Option<u64>, // from content-length, None when streamed
+}
+
+/// Response attributes, gathered by the response mapper.
+pub struct ResLogInfo {
+    pub status: u16,
+    pub size: Option<u64>,
+    pub provider_call: Option<ProviderCall>,
+}
 
 pub async fn log_request(
-    uuid: Uuid,
-    req_method: Method,
-    uri: Uri,
+    req_stamp: ReqStamp,
+    req: ReqLogInfo,
+    res: ResLogInfo,
     ctx: Result<Ctx>,
     service_error: Option<&Error>,
     client_error: Option<ClientError>,
 ) -> Result<()> {
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis();
+    let ReqStamp { req_id, trace_id, time_in, instant_in } = req_stamp;
+    let duration_ms = instant_in.elapsed().as_secs_f64() * 1000.;
+    let timestamp = time_in.format(&Rfc3339).unwrap_or_default();
 
     let error_type = service_error.map(|se| se.as_ref().to_string());
     let error_data = serde_json::to_value(service_error)
         .ok()
         .and_then(|mut v|v.get_mut("data").map(|v| v.take()));
-    
+
     // Create the RequestLogLine
     let log_line = RequestLogLine {
-        uuid: uuid.to_string(),
-        timestamp: timestamp.to_string(),
-        req_path: uri.to_string(),
-        req_method: req_method.to_string(),
-        user_id: ctx.map(|c|c.user_id()),
+        req_id,
+        trace_id,
+        timestamp,
+        duration_ms,
+
+        user_id: ctx.ok().map(|c|c.user_id()),
+
+        req_path: req.uri.to_string(),
+        req_route: req.matched_path,
+        req_method: req.method.to_string(),
+        req_size: req.size,
+        client_ip: req.client_ip.to_string(),
+        user_agent: req.user_agent,
+
+        res_status: res.status,
+        res_size: res.size,
+
+        provider: res.provider_call,
+
         client_error_type: client_error.map(|e| e.as_ref().to_string()),
         error_type,
         error_data,
@@ -43,24 +84,41 @@ pub async fn log_request(
     let json_log_line = json!(log_line);
     debug!("log_request: {json_log_line}");
 
-    // TODO: - Send to cloud-watch
+    if let Some(sink) = log_sink() {
+        sink.send(json_log_line);
+    }
+
     Ok(())
 }
 
 #[skip_serializing_none] // parameters are not serialized if they are None
 #[derive(Serialize)]
 struct RequestLogLine {
-    uuid: String, // uuid string formatted
-    timestamp: String, // ISO 8601 formatted timestamp
+    req_id: String, // from X-Request-Id or traceparent, else a new uuid
+    trace_id: Option<String>,
+    timestamp: String, // RFC 3339 formatted time the request came in
+    duration_ms: f64,
+
     // -- User and context attributes.
-    user_id: Result<u64>,
-    
-    // -- http Request attributes. 
+    user_id: Option<u64>,
+
+    // -- http Request attributes.
     req_path: String,
+    req_route: Option<String>,
     req_method: String,
+    req_size: Option<u64>,
+    client_ip: String,
+    user_agent: Option<String>,
+
+    // -- http Response attributes.
+    res_status: u16,
+    res_size: Option<u64>,
+
+    // -- Nutrition provider call (model, latency, token usage).
+    provider: Option<ProviderCall>,
 
     // -- Errors attributes.
     client_error_type: Option<String>,
     error_type: Option<String>,
     error_data: Option<Value>,
-}
\ No newline at end of file
+}
@@ -0,0 +1,121 @@
+//! Destinations for the request log lines.
+//! - `stdout`: one JSON object per line on stdout, the tracing output then goes to stderr (see telemetry).
+//! - `file`: JSON-lines file, rotated by size.
+//! - `http`: batches POSTed as a JSON array to a collector.
+//!
+//! `send` never blocks the request, the file and http sinks buffer the lines
+//! in a bounded channel and drop them (counted) when it is full.
+//! `flush` is for the shutdown, it blocks until the buffered lines are written (or a timeout).
+
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::log::file_sink::FileSink;
+use crate::log::http_sink::HttpSink;
+
+static LOG_SINK: OnceLock<Box<dyn LogSink>> = OnceLock::new();
+
+// At shutdown, a collector which is down is not waited for long.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub trait LogSink: Send + Sync {
+    fn send(&self, line: Value);
+
+    /// Blocking, call it outside of the async runtime threads (e.g., `spawn_blocking`).
+    fn flush(&self, timeout: Duration);
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum LogSinkKind {
+    #[default]
+    Stdout,
+    File,
+    Http,
+}
+
+impl FromStr for LogSinkKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "stdout" => Ok(Self::Stdout),
+            "file" => Ok(Self::File),
+            "http" => Ok(Self::Http),
+            other => Err(format!("unknown log sink '{other}'")),
+        }
+    }
+}
+
+/// Create the sink chosen by `SERVICE_LOG_SINK`. Must be called once, from the tokio runtime.
+pub fn init_log_sink() -> Result<()> {
+    let sink: Box<dyn LogSink> = match config().LOG_SINK {
+        LogSinkKind::Stdout => Box::new(StdoutSink),
+        LogSinkKind::File => {
+            let path = config()
+                .LOG_FILE_PATH
+                .clone()
+                .ok_or(Error::ConfigMissingEnv("SERVICE_LOG_FILE_PATH"))?;
+            Box::new(FileSink::new(
+                path.into(),
+                config().LOG_FILE_MAX_BYTES,
+                config().LOG_FILE_MAX_FILES,
+                config().LOG_BUFFER_SIZE,
+            )?)
+        }
+        LogSinkKind::Http => {
+            let url = config()
+                .LOG_HTTP_URL
+                .clone()
+                .ok_or(Error::ConfigMissingEnv("SERVICE_LOG_HTTP_URL"))?;
+            Box::new(HttpSink::new(
+                url,
+                config()
+                    .LOG_HTTP_AUTHORIZATION
+                    .as_ref()
+                    .map(|secret| secret.expose().clone()),
+                config().LOG_HTTP_BATCH_SIZE,
+                config().LOG_HTTP_FLUSH_INTERVAL_MS,
+                config().LOG_BUFFER_SIZE,
+            ))
+        }
+    };
+
+    LOG_SINK
+        .set(sink)
+        .map_err(|_| Error::LogSinkAlreadyInitialized)
+}
+
+/// Write the buffered lines, at shutdown. Blocking.
+pub fn flush_log_sink() {
+    if let Some(sink) = log_sink() {
+        sink.flush(FLUSH_TIMEOUT);
+    }
+}
+
+/// None until `init_log_sink` was called.
+pub fn log_sink() -> Option<&'static dyn LogSink> {
+    LOG_SINK.get().map(|sink| sink.as_ref())
+}
+
+// region:    --- Stdout Sink
+
+pub struct StdoutSink;
+
+impl LogSink for StdoutSink {
+    // Locked for the whole line, and no panic when stdout is closed.
+    fn send(&self, line: Value) {
+        let _ = writeln!(std::io::stdout().lock(), "{line}");
+    }
+
+    fn flush(&self, _timeout: Duration) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+// endregion: --- Stdout Sink
@@ -1,5 +1,6 @@
 
 // region:     --- Modules
+mod cli;
 mod config;
 mod vehicle;    
 mod hello;
@@ -9,6 +10,12 @@ mod web;
 mod middlewares;
 mod model;
 mod log;
+mod metrics;
+mod nutrition;
+mod oidc;
+mod shutdown;
+mod telemetry;
+mod token;
 
 // #[cfg(test)] // Commented during early dev
 pub mod _dev_utils;
@@ -17,279 +24,67 @@ pub mod _dev_utils;
 pub use config::config; // allows use crate::config 
 
 use axum::{
-    extract::State, 
-    http::{StatusCode},
     middleware,
-    response::Json,
     routing::{get, post},
     Router,
 };
-use serde_json::json;
 use tower_cookies::CookieManagerLayer;
+use tower_http::catch_panic::CatchPanicLayer;
 use tracing::{debug, info};
-use tracing_subscriber::EnvFilter;
-use std::env;
 
-use crate::web::{routes_login, routes_static};
+use crate::web::{routes_health, routes_login, routes_metrics, routes_nutrition, routes_oidc, routes_static};
 use vehicle::{vehicle_get, vehicle_post, vehicle_put, vehicle_post2};
 
-use crate::{middlewares::mappers::mw_response_map, model::model::ModelManager};
+use crate::{middlewares::mappers::{map_panic, mw_response_map}, model::model::ModelManager};
+use crate::middlewares::mw_rate_limit::{LoginLockout, RateLimiters};
+use crate::oidc::OidcClient;
+use crate::shutdown::Shutdown;
+use crate::config::ConfigNeeds;
+use crate::error::Error;
 
 
-// AppState holds the Gemini API client or any other shared state.
-#[derive(Clone)]
-struct AppState {
-    gemini_api_key: String,
-}
-
-
-// The request body for the /analyze-image endpoint.
-// It expects a single field `image` containing the base64-encoded image data.
-#[derive(serde::Deserialize)]
-struct ImageRequest {
-    image: String,
-}
-
-// Represents a single food item identified in the image.
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct FoodItem {
-    name: String,
-    calories: f32,
-    protein_g: f32,
-    fat_g: f32,
-    carbohydrates_g: f32,
-    sugar_g: f32,
-    sodium_mg: f32,
-}
-
-// The final JSON response structure sent back to the client.
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct NutritionResponse {
-    foods: Vec<FoodItem>,
-}
-
-// Handler for the /analyze-image endpoint
-async fn analyze_image(
-    State(state): State<AppState>,
-    Json(payload): Json<ImageRequest>,
-) -> Result<Json<NutritionResponse>, StatusCode> {
-    match call_gemini_api(&state.gemini_api_key, &payload.image).await {
-        Ok(response) => Ok(Json(response)),
-        Err(e) => {
-            eprintln!("Error calling Gemini API: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
-}
-
-// Function to call Gemini 2.5 Pro API for nutritional analysis
-async fn call_gemini_api(api_key: &str, base64_image: &str) -> Result<NutritionResponse, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    
-    let prompt = "Analyze this food image and provide detailed nutritional information. For each food item visible, provide the name, estimated calories, protein (g), fat (g), carbohydrates (g), sugar (g), and sodium (mg). Return the response as a JSON object with a 'foods' array containing objects with these exact fields: name, calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg. Only return the JSON, no additional text.";
-    
-    let request_body = json!({
-        "contents": [{
-            "parts": [
-                {
-                    "text": prompt
-                },
-                {
-                    "inline_data": {
-                        "mime_type": "image/jpeg",
-                        "data": base64_image
-                    }
-                }
-            ]
-        }]
-    });
-
-
-    let url = format!("https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash:generateContent?key={}", api_key);
-    
-    let response = client
-        .post(&url)
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await?;
-
-    let response_text = response.text().await?;
-
-    
-    let gemini_response: serde_json::Value = serde_json::from_str(&response_text)?;
-    
-    // Extract the generated text from Gemini's response
-    let generated_text = gemini_response
-        .get("candidates")
-        .and_then(|c| c.get(0))
-        .and_then(|c| c.get("content"))
-        .and_then(|c| c.get("parts"))
-        .and_then(|p| p.get(0))
-        .and_then(|p| p.get("text"))
-        .and_then(|t| t.as_str())
-        .ok_or("Failed to extract text from Gemini response")?;
-
-    debug!("generated_text: {}\n", generated_text);
-
-    // Parse the JSON response from Gemini with error handling
-    let nutrition_response = parse_gemini_response(generated_text)?;
-    
-    Ok(nutrition_response)
-}
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Load environment variables from .env file
+    dotenv::dotenv().ok();
 
-// Robust parser for Gemini response that handles missing fields and unknown keys
-fn parse_gemini_response(generated_text: &str) -> Result<NutritionResponse, Box<dyn std::error::Error>> {
-    // First, try to parse as JSON
-    let json_value: serde_json::Value = match serde_json::from_str(generated_text) {
-        Ok(value) => value,
-        Err(_) => {
-            // If direct parsing fails, try to extract JSON from the text
-            // Sometimes Gemini wraps JSON in markdown code blocks or adds extra text
-            info!("Could not parse directly\n");
-            if let Some(json_str) = extract_json_from_text(generated_text) {
-                debug!("json_str: {}", json_str);
-                serde_json::from_str(&json_str)?
-            } else {
-                return Err("No valid JSON found in Gemini response".into());
-            }
-        }
+    // Subcommands (e.g., `migrate status`) run instead of the server, with only the secrets they need.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let needs = match args.is_empty() {
+        true => ConfigNeeds::ALL,
+        false => cli::config_needs(&args),
     };
 
-    // Extract foods array
-    let foods_array = json_value
-        .get("foods")
-        .and_then(|f| f.as_array())
-        .ok_or("No 'foods' array found in response")?;
-
-    let mut foods = Vec::new();
-
-    for food_value in foods_array {
-        let food_item = parse_food_item(food_value)?;
-        foods.push(food_item);
-    }
-
-    Ok(NutritionResponse { foods })
-}
-
-// Extract JSON from text that might contain markdown or extra content
-fn extract_json_from_text(text: &str) -> Option<String> {
-    // Look for JSON wrapped in code blocks
-    if let Some(start) = text.find("```json") {
-        let json_start = start + 7; // Skip "```json"
-        if let Some(end) = text[json_start..].find("```") {
-            let json_end = json_start + end;
-            let s = text[json_start..json_end].trim().to_string();
-            return Some(s);
+    // Defaults, then SERVICE_CONFIG_FILE, then SERVICE_ env vars. All the problems at once.
+    if let Err(Error::ConfigInvalid(problems)) = config::init_config(needs) {
+        eprintln!("Invalid config:");
+        for problem in problems {
+            eprintln!("  - {problem}");
         }
+        std::process::exit(1);
     }
-    
-    // Look for JSON wrapped in regular code blocks
-    if let Some(start) = text.find("```") {
-        if let Some(end) = text[start + 3..].find("```") {
-            let json_start = start + 3;
-            let json_end = start + 3 + end;
-            let potential_json = text[json_start..json_end].trim();
-            if potential_json.starts_with('{') && potential_json.ends_with('}') {
-                return Some(potential_json.to_string());
-            }
-        }
-    }
-    
-    // Look for JSON object in the text
-    if let Some(start) = text.find('{') {
-        if let Some(end) = text.rfind('}') {
-            if end > start {
-                return Some(text[start..=end].to_string());
-            }
-        }
-    }
-    
-    None
-}
-
-// Parse individual food item with default values for missing fields
-fn parse_food_item(food_value: &serde_json::Value) -> Result<FoodItem, Box<dyn std::error::Error>> {
-    let name = food_value
-        .get("name")
-        .and_then(|n| n.as_str())
-        .unwrap_or("Unknown Food")
-        .to_string();
 
-    let calories = parse_numeric_field(food_value, "calories").unwrap_or(0.0);
-    let protein_g = parse_numeric_field(food_value, "protein_g")
-        .or_else(|| parse_numeric_field(food_value, "protein"))
-        .unwrap_or(0.0);
-    let fat_g = parse_numeric_field(food_value, "fat_g")
-        .or_else(|| parse_numeric_field(food_value, "fat"))
-        .unwrap_or(0.0);
-    let carbohydrates_g = parse_numeric_field(food_value, "carbohydrates_g")
-        .or_else(|| parse_numeric_field(food_value, "carbohydrates"))
-        .or_else(|| parse_numeric_field(food_value, "carbs"))
-        .unwrap_or(0.0);
-    let sugar_g = parse_numeric_field(food_value, "sugar_g")
-        .or_else(|| parse_numeric_field(food_value, "sugar"))
-        .unwrap_or(0.0);
-    let sodium_mg = parse_numeric_field(food_value, "sodium_mg")
-        .or_else(|| parse_numeric_field(food_value, "sodium"))
-        .unwrap_or(0.0);
+    // Stdout logs, and the OTLP span export when built with the otel feature.
+    let telemetry_guard = telemetry::init_tracing()?;
 
-    Ok(FoodItem {
-        name,
-        calories,
-        protein_g,
-        fat_g,
-        carbohydrates_g,
-        sugar_g,
-        sodium_mg,
-    })
-}
-
-// Helper function to parse numeric fields that might be strings or numbers
-fn parse_numeric_field(value: &serde_json::Value, field_name: &str) -> Option<f32> {
-    value.get(field_name).and_then(|v| {
-        match v {
-            serde_json::Value::Number(n) => n.as_f64().map(|f| f as f32),
-            serde_json::Value::String(s) => {
-                // Try to parse string as number, removing any units
-                let cleaned = s.trim()
-                    .replace("g", "")
-                    .replace("mg", "")
-                    .replace("kcal", "")
-                    .replace("cal", "")
-                    .trim()
-                    .to_string();
-                cleaned.parse::<f32>().ok()
-            }
-            _ => None,
-        }
-    })
-}
+    // -- Subcommands
+    if !args.is_empty() {
+        cli::run(&args).await?;
+        return Ok(());
+    }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Load environment variables from .env file
-    dotenv::dotenv().ok();
+    // Request log lines go to the sink chosen by SERVICE_LOG_SINK.
+    log::init_log_sink()?;
 
-    tracing_subscriber::fmt()
-        .without_time() // For early local development, omit timestamps
-        .with_target(false)
-        .with_env_filter(EnvFilter::from_default_env())
-        .init();
+    debug!("{:<12} - {:?}", "CONFIG", config()); // secrets are redacted
 
     // -- FOR-DEV-ONLY
-    _dev_utils::init_dev().await; // recreate and seed dev db
-    // since there is no ? at the end of await, it will fail if it cannot initialize.
+    if config().DEV_DB_RECREATE {
+        _dev_utils::init_dev().await; // recreate, migrate and seed dev db
+        // since there is no ? at the end of await, it will fail if it cannot initialize.
+    }
     // -- END FOR-DEV-ONLY
     
-    // Get the Gemini API key from environment
-    let gemini_api_key = env::var("GEMINI_API_KEY")
-        .expect("GEMINI_API_KEY must be set in .env file");
-    // Create app state with the API key
-    let app_state = AppState {
-        gemini_api_key,
-    };
-
     info!("Starting nutrition analysis server...");
 
     // build our application with routes
@@ -299,19 +94,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let router02: Router = Router::new()
     .route("/vehicle2", post(vehicle_post2));
 
-    // Add the nutrition analysis endpoint
-    let nutrition_router = Router::new()
-        .route("/analyze-image", post(analyze_image))
-        .with_state(app_state);
-
     // Initialize ModelManager
     let mm: ModelManager = ModelManager::new().await?;
 
+    // Pending migrations, when SERVICE_DB_MIGRATE_ON_START (otherwise with `migrate up`).
+    if config().DB_MIGRATE_ON_START {
+        mm.migrate_up().await?;
+    }
+
+    // Add the nutrition analysis endpoint (authenticated, and quota limited per user)
+    let nutrition_router = routes_nutrition::routes(mm.clone(), config().GEMINI_API_KEY.expose().clone())
+        .route_layer(middleware::from_fn(middlewares::mw_csrf::mw_csrf))
+        .route_layer(middleware::from_fn(middlewares::mw_auth::mw_require_auth));
+
+    // Login too, or another site could log its visitors in as the attacker (login CSRF).
+    let login_router = routes_login::routes(LoginLockout::from_config())
+        .route_layer(middleware::from_fn(middlewares::mw_csrf::mw_csrf));
+
     // -- Define Routes
     // let routs_rpc = rpc::routes(mm.clone()).route_layer(middleware::from_fn(mw_ctx_require));
     
     let routes_apis = web::routes_ticket::routes(mm.clone())
-        .route_layer(middleware::from_fn(middlewares::mw_auth::mw_require_auth)); // apply auth middleware to ticket routes only
+        .merge(web::routes_api_keys::routes(mm.clone()))
+        .merge(web::routes_access::routes(mm.clone()))
+        .merge(web::routes_foods::routes(mm.clone()))
+        .merge(web::routes_barcode::routes(mm.clone()))
+        .merge(web::routes_recipes::routes(mm.clone()))
+        .merge(web::routes_meals::routes(mm.clone()))
+        .merge(web::routes_analyses::routes(mm.clone()))
+        .route_layer(middleware::from_fn(middlewares::mw_csrf::mw_csrf)) // cookie authenticated non-GET requests must come from an allowed origin
+        .route_layer(middleware::from_fn(middlewares::mw_auth::mw_require_auth)); // apply auth middleware to the /api routes only
 
     let routes_all: Router = Router::new()
         .route("/", get(|| async { "Hello, World!" }))
@@ -319,23 +131,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .merge(router01)
         .merge(router02)
         .merge(nutrition_router)
-        .merge(routes_login::routes()) // api/login,logoff TODO: add encryption, secure web token
+        .merge(login_router) // api/login,logoff TODO: add encryption, secure web token
+        .merge(routes_oidc::routes(mm.clone(), OidcClient::from_config()?)) // api/oidc/login,callback
         // .nest("/api", routes_rpc) // TODO
         .nest("/api", routes_apis)
+        .layer(middleware::from_fn_with_state(
+            RateLimiters::from_config(),
+            middlewares::mw_rate_limit::mw_rate_limit,
+        )) // throttles per user (or client ip), errors are mapped by mw_response_map below
+        .layer(CatchPanicLayer::custom(map_panic)) // a panic becomes an Error::ServicePanic, mapped by mw_response_map below
         .layer(middleware::map_response(mw_response_map)) // Response mapping and logging, remaps error msgs to make sure we sent minimum info to client, and generate req line log for metrics
+        .layer(middleware::from_fn(middlewares::mw_metrics::mw_metrics)) // request counters and latency, with the mapped status
         .layer(middleware::from_fn_with_state(
             mm.clone(),
             middlewares::mw_auth::mw_ctx_resolve,
         )) // resolves ctx for a given req, auth resolve will be executed for both api/login,logoff, as well as for rpc
+        .layer(middleware::from_fn(middlewares::mw_req_stamp::mw_req_stamp)) // request id, start time and request span, the id is echoed in X-Request-Id
         .layer(CookieManagerLayer::new())
+        .merge(routes_metrics::routes(mm.clone())) // outside the layers, scraping is not a client request (bearer SERVICE_METRICS_TOKEN)
+        .merge(routes_health::routes(mm.clone())) // healthz, readyz, version, outside the layers too
         .fallback_service(routes_static::serve_dir(&config().WEB_FOLDER));
     // The layers ^ are executed from bottom to top.
 
-    // run our app with hyper, listening globally on port 3000
-    let addr: &str = "0.0.0.0:3000";
-    info!("{:<12} - {addr}\n", "LISTENING");
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, routes_all).await.unwrap();
+    // Tcp (optionally TLS, dual-stack) or unix socket, from the config.
+    // Returns on SIGTERM/SIGINT, once the in-flight requests are drained (or the drain timed out).
+    web::server::serve(routes_all, Shutdown::listen()).await?;
+
+    // -- Shutdown: the request log lines, the db pool, then the pending spans (guard drop).
+    tokio::task::spawn_blocking(log::flush_log_sink).await?;
+    mm.close_db().await;
+    info!("{:<12} - done", "SHUTDOWN");
+    drop(telemetry_guard);
+
     Ok(())
 }
 
@@ -0,0 +1,186 @@
+//! Prometheus metrics, exposed in the text format on `/metrics` (with the `SERVICE_METRICS_TOKEN` bearer).
+//! - http: requests and latency per matched route, method and status, in-flight requests.
+//! - nutrition: provider calls and latency by result, analysis cache hits and misses.
+//! - db: pool connections, sampled when scraped.
+//! - auth: failures by `Error` variant.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use prometheus::{
+    register_histogram_vec_with_registry, register_int_counter_vec_with_registry,
+    register_int_gauge_vec_with_registry, register_int_gauge_with_registry, Encoder, HistogramVec,
+    IntCounterVec, IntGauge, IntGaugeVec, Registry, TextEncoder,
+};
+
+use crate::model::store::DbPoolStatus;
+
+// Provider calls take seconds, the default buckets stop at 10s.
+const PROVIDER_BUCKETS: &[f64] = &[0.25, 0.5, 1., 2., 4., 8., 16., 32., 64.];
+
+pub fn metrics() -> &'static Metrics {
+    static INSTANCE: OnceLock<Metrics> = OnceLock::new();
+
+    INSTANCE.get_or_init(|| {
+        Metrics::new().unwrap_or_else(|ex| panic!("Failed to register metrics: {ex}"))
+    })
+}
+
+pub struct Metrics {
+    registry: Registry,
+
+    // -- http
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    http_requests_in_flight: IntGauge,
+
+    // -- Nutrition
+    provider_calls: IntCounterVec,
+    provider_call_duration: HistogramVec,
+    analysis_cache: IntCounterVec,
+
+    // -- Db
+    db_pool_connections: IntGaugeVec,
+    db_pool_max_connections: IntGauge,
+
+    // -- Auth
+    auth_failures: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("foodeq".to_string()), None)?;
+
+        Ok(Self {
+            http_requests: register_int_counter_vec_with_registry!(
+                "http_requests_total",
+                "Http requests, by matched route, method and status.",
+                &["route", "method", "status"],
+                registry
+            )?,
+            http_request_duration: register_histogram_vec_with_registry!(
+                "http_request_duration_seconds",
+                "Http request latency, by matched route, method and status.",
+                &["route", "method", "status"],
+                registry
+            )?,
+            http_requests_in_flight: register_int_gauge_with_registry!(
+                "http_requests_in_flight",
+                "Http requests being processed.",
+                registry
+            )?,
+
+            provider_calls: register_int_counter_vec_with_registry!(
+                "provider_calls_total",
+                "Nutrition provider calls, by provider and result (ok or error type).",
+                &["provider", "result"],
+                registry
+            )?,
+            provider_call_duration: register_histogram_vec_with_registry!(
+                "provider_call_duration_seconds",
+                "Nutrition provider call latency, by provider.",
+                &["provider"],
+                PROVIDER_BUCKETS.to_vec(),
+                registry
+            )?,
+            analysis_cache: register_int_counter_vec_with_registry!(
+                "analysis_cache_requests_total",
+                "Analysis cache lookups, by result (hit or miss).",
+                &["result"],
+                registry
+            )?,
+
+            db_pool_connections: register_int_gauge_vec_with_registry!(
+                "db_pool_connections",
+                "Db pool connections, by state (idle or in_use).",
+                &["state"],
+                registry
+            )?,
+            db_pool_max_connections: register_int_gauge_with_registry!(
+                "db_pool_max_connections",
+                "Db pool maximum connections.",
+                registry
+            )?,
+
+            auth_failures: register_int_counter_vec_with_registry!(
+                "auth_failures_total",
+                "Authentication and authorization failures, by Error variant.",
+                &["variant"],
+                registry
+            )?,
+
+            registry,
+        })
+    }
+}
+
+// Recording
+impl Metrics {
+    /// Decrements the in-flight gauge when dropped, also when the request future is dropped.
+    pub fn in_flight_guard(&self) -> InFlightGuard {
+        self.http_requests_in_flight.inc();
+        InFlightGuard(self.http_requests_in_flight.clone())
+    }
+
+    pub fn http_requests_in_flight(&self) -> i64 {
+        self.http_requests_in_flight.get()
+    }
+
+    pub fn observe_http_request(&self, route: &str, method: &str, status: u16, duration: Duration) {
+        let status = status.to_string();
+        let labels = [route, method, status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_request_duration
+            .with_label_values(&labels)
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Calls which were not made (e.g., circuit open) have no latency.
+    pub fn inc_provider_call(&self, provider: &str, result: &str) {
+        self.provider_calls.with_label_values(&[provider, result]).inc();
+    }
+
+    pub fn observe_provider_call(&self, provider: &str, result: &str, duration: Duration) {
+        self.inc_provider_call(provider, result);
+        self.provider_call_duration
+            .with_label_values(&[provider])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn inc_analysis_cache(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.analysis_cache.with_label_values(&[result]).inc();
+    }
+
+    pub fn inc_auth_failure(&self, variant: &str) {
+        self.auth_failures.with_label_values(&[variant]).inc();
+    }
+}
+
+// Exposition
+impl Metrics {
+    /// Text format, with the db pool gauges sampled now.
+    pub fn render(&self, db_pool: DbPoolStatus) -> String {
+        let in_use = db_pool.size.saturating_sub(db_pool.idle);
+        self.db_pool_connections
+            .with_label_values(&["idle"])
+            .set(db_pool.idle as i64);
+        self.db_pool_connections
+            .with_label_values(&["in_use"])
+            .set(in_use as i64);
+        self.db_pool_max_connections.set(db_pool.max as i64);
+
+        let mut buffer = Vec::new();
+        // Encoding to a Vec cannot fail for the text format.
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+pub struct InFlightGuard(IntGauge);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
@@ -1,50 +1,230 @@
 
+use std::any::Any;
+
 use axum::{
-    http::{Response, Uri}, response::IntoResponse, Json
+    body::HttpBody, extract::MatchedPath, http::{header, HeaderMap, HeaderValue, Response, StatusCode, Uri}, response::IntoResponse, Json
 };
 use reqwest::Method;
-use serde_json::json;
+use serde_json::{json, Value};
 use tracing::debug;
 use uuid::Uuid;
 
-use crate::{ctx::Ctx, error::{Result, Error}, log::log_request};
+use crate::{ctx::Ctx, error::{ClientError, Error, Result}, log::{log_request, ReqLogInfo, ResLogInfo}};
+use crate::metrics::metrics;
+use crate::middlewares::mw_req_stamp::ReqStamp;
+use crate::nutrition::ProviderCall;
+use crate::web::client_ip::ClientIp;
+use crate::web::routes_nutrition::quota_headers;
+
+const PROBLEM_JSON: &str = "application/problem+json";
 
+// Relative type URIs, resolved against the api base url.
+const PROBLEM_TYPE_BASE: &str = "/problems/";
+
+#[allow(clippy::too_many_arguments)] // axum extractors
 pub async fn mw_response_map(
     ctx: Result<Ctx>,
+    req_stamp: Result<ReqStamp>,
     uri: Uri,
     req_method: Method,
+    req_headers: HeaderMap,
+    matched_path: Option<MatchedPath>,
+    ClientIp(client_ip): ClientIp,
     res: Response<axum::body::Body>
 ) -> Response<axum::body::Body> {
     debug!("{:<12} - mw_response_map", "MIDDLEWARE");
     // let ctx = ctx.map(|ctx| ctx.0).ok();
-    let uuid = Uuid::new_v4();
+    let req_stamp = req_stamp.unwrap_or_else(|_| ReqStamp::now());
+    let req_id = req_stamp.req_id.clone();
 
     // -- Get the eventual response error.
-    let service_error = res.extensions().get::<Error>();
-    let client_status_error = service_error.map(|se| se.client_status_and_error());
+    let service_error = res.extensions().get::<Error>().cloned();
+    let client_status_error = service_error.as_ref().map(|se| se.client_status_and_error());
+
+    if let (Some(se), Some((_, ce))) = (&service_error, &client_status_error) {
+        if matches!(ce, ClientError::NO_AUTH | ClientError::LOGIN_FAIL | ClientError::PERMISSION_DENIED)
+            || matches!(se, Error::LoginLocked { .. })
+        {
+            metrics().inc_auth_failure(se.as_ref());
+        }
+    }
     
     // --If client error, build the new response.
     let error_response = client_status_error
         .as_ref()
         .map(|(status_code, client_error)| {
-            let client_error_body = json!({
+            // Opt-in RFC 7807 body, the legacy body stays the default.
+            if accepts_problem_json(&req_headers) {
+                return problem_response(*status_code, client_error, &req_id);
+            }
+
+            let mut client_error_body = json!({
                 "error": {
                     "type": client_error.as_ref(),
-                    "req_uuid": uuid.to_string(),
+                    "req_uuid": req_id,
                 }
             });
+
+            // Some client errors carry data for the client (e.g., quota reset time).
+            if let Some(data) = client_error_data(client_error) {
+                client_error_body["error"]["data"] = data;
+            }
  
             debug!("{:<12} - {client_error_body}", "CLIENT_ERROR");
             
             // Build the new response from the client error body.
-            (*status_code, Json(client_error_body)).into_response()
+            let mut response = (*status_code, Json(client_error_body)).into_response();
+            insert_retry_after(&mut response, client_error);
+            response
+        })
+        .map(|mut response| {
+            // When the quota is exceeded, the client still sees its state, e.g., when it resets.
+            if let Some(Error::QuotaExceeded { quota, .. }) = &service_error {
+                response.headers_mut().extend(quota_headers(quota));
+            }
+            response
         });
     
-    // -- TODO: Build and log the server log line.
+    let provider_call = res.extensions().get::<ProviderCall>().cloned();
+    let res = error_response.unwrap_or(res);
+
+    // -- Build and log the server log line.
+    let req_info = ReqLogInfo {
+        method: req_method,
+        uri,
+        matched_path: matched_path.map(|mp| mp.as_str().to_string()),
+        client_ip,
+        user_agent: header_str(&req_headers, header::USER_AGENT).map(str::to_string),
+        size: header_str(&req_headers, header::CONTENT_LENGTH).and_then(|v| v.parse().ok()),
+    };
+    let res_info = ResLogInfo {
+        status: res.status().as_u16(),
+        size: res.body().size_hint().exact(),
+        provider_call,
+    };
     let client_error = client_status_error.unzip().1;
-    let _ = log_request(uuid, req_method, uri, ctx, service_error, client_error).await;
+    let _ = log_request(req_stamp, req_info, res_info, ctx, service_error.as_ref(), client_error).await;
 
-    // debug!("server log line - {uuid} - Error: {service_error:?} Client Error: {client_error:?}");
     debug!("\n"); // will not have any meaning when multiple requests are logged at the same time.
-    error_response.unwrap_or(res)
-}
\ No newline at end of file
+    res
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+// region:    --- Client Error Body
+
+fn client_error_data(client_error: &ClientError) -> Option<Value> {
+    serde_json::to_value(client_error)
+        .ok()
+        .and_then(|mut v| v.get_mut("data").map(|v| v.take()))
+}
+
+fn insert_retry_after(response: &mut Response<axum::body::Body>, client_error: &ClientError) {
+    if let ClientError::RATE_LIMITED { retry_after_secs } = client_error {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(*retry_after_secs));
+    }
+}
+
+fn accepts_problem_json(req_headers: &HeaderMap) -> bool {
+    req_headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|media| {
+            let media = media.split(';').next().unwrap_or("").trim();
+            media.eq_ignore_ascii_case(PROBLEM_JSON)
+        })
+}
+
+/// RFC 7807 body. The client error data (e.g., `invalid_params`, `retry_after_secs`)
+/// become extension members.
+fn problem_response(
+    status_code: StatusCode,
+    client_error: &ClientError,
+    req_id: &str,
+) -> Response<axum::body::Body> {
+    let mut problem = json!({
+        "type": format!(
+            "{PROBLEM_TYPE_BASE}{}",
+            client_error.as_ref().to_lowercase().replace('_', "-")
+        ),
+        "title": client_error.title(),
+        "status": status_code.as_u16(),
+        "req_id": req_id,
+    });
+    problem["instance"] = Value::String(instance_urn(req_id));
+    if let Some(detail) = client_error.detail() {
+        problem["detail"] = Value::String(detail);
+    }
+    if let Some(Value::Object(data)) = client_error_data(client_error) {
+        for (name, value) in data {
+            problem[name] = value;
+        }
+    }
+
+    debug!("{:<12} - {problem}", "CLIENT_ERROR");
+
+    let mut response = (status_code, Json(problem)).into_response();
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    insert_retry_after(&mut response, client_error);
+    response
+}
+
+/// The request id is a uuid, unless it was propagated from the caller (any visible ascii),
+/// which is percent-encoded where not allowed in a urn.
+fn instance_urn(req_id: &str) -> String {
+    if let Ok(uuid) = Uuid::parse_str(req_id) {
+        return format!("urn:uuid:{uuid}");
+    }
+
+    let mut urn = String::from("urn:request:");
+    for b in req_id.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b':' | b'@' => {
+                urn.push(b as char)
+            }
+            b => urn.push_str(&format!("%{b:02X}")),
+        }
+    }
+    urn
+}
+
+// endregion: --- Client Error Body
+
+/// Used by the `CatchPanicLayer` (which must be inside `mw_response_map`),
+/// so that a panicking handler still gets the structured client error and a req log line.
+pub fn map_panic(err: Box<dyn Any + Send + 'static>) -> Response<axum::body::Body> {
+    let message = err
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| err.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Unknown panic message".to_string());
+
+    Error::ServicePanic(message).into_response()
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instance_urn() {
+        assert_eq!(
+            instance_urn("67e55044-10b1-426f-9247-bb680e5fe0c8"),
+            "urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8"
+        );
+        assert_eq!(instance_urn("my-debug-id-1"), "urn:request:my-debug-id-1");
+        assert_eq!(instance_urn("a/b?c#d%e"), "urn:request:a%2Fb%3Fc%23d%25e");
+    }
+}
+
+// endregion: --- Tests
@@ -1,3 +1,7 @@
 
 pub mod mappers;
-pub mod mw_auth;
\ No newline at end of file
+pub mod mw_csrf;
+pub mod mw_metrics;
+pub mod mw_auth;
+pub mod mw_rate_limit;
+pub mod mw_req_stamp;
@@ -2,20 +2,21 @@
 use axum::body::Body;
 use axum::extract::FromRequestParts;
 use axum::extract::State;
+use axum::http::header::AUTHORIZATION;
 use axum::http::request::Parts;
-use axum::http::Request;
+use axum::http::{HeaderValue, Request};
 
 use axum::http::Response;
 use axum::middleware::Next;
-use lazy_regex::regex_captures;
-use tower_cookies::Cookie;
 use tower_cookies::Cookies;
 use tracing::debug;
 
+use crate::model::api_key::API_KEY_PREFIX;
 use crate::model::model::ModelManager;
-use crate::web::AUTH_TOKEN;
+use crate::web::{remove_token_cookie, AUTH_TOKEN};
 use crate::error::{Error, Result};
-use crate::ctx::Ctx;
+use crate::ctx::{Ctx, Permission};
+use crate::token::validate_token;
 
 pub async fn mw_require_auth(
     // cookies: Cookies,
@@ -30,46 +31,99 @@ pub async fn mw_require_auth(
     Ok(next.run(req).await)
 }
 
+/// Route level permission check, e.g.,
+/// `.route_layer(middleware::from_fn_with_state(Permission::TicketsListAll, mw_require_permission))`
+pub async fn mw_require_permission(
+    State(permission): State<Permission>,
+    ctx: Result<Ctx>,
+    req: Request<Body>,
+    next: Next
+) -> Result<Response<Body>> {
+    debug!("{:12} - mw_require_permission - {permission:?}", "MIDDLEWARE");
+
+    ctx?.require_permission(permission)?;
+
+    Ok(next.run(req).await)
+}
+
 // IMPORTANT: This resolver must never fail, but rather capture the potential Auth error and put in in the
 //            request extension as CtxExtResult.
 //            This way it won't prevent downstream middleware to be executed, and will still capture the error
 //            for the appropriate middleware (.e.g., mw_ctx_require which forces successful auth) or handler
 //            to get the appropriate debugrmation.
 pub async fn mw_ctx_resolve(
-    _mm: State<ModelManager>,
+    mm: State<ModelManager>,
     cookies: Cookies,
     mut req: Request<Body>,
     next: Next,
 ) -> Result<Response<Body>> {
     debug!("{:12} - mw_ctx_resolve", "MIDDLEWARE");
 
-    let auth_token = cookies.get(AUTH_TOKEN).map(|c| c.value().to_string());
-    debug!("AUTH_TOKEN cookie: {auth_token:?}");
-    let result_ctx = match auth_token
-        .ok_or(Error::AuthFailNoAuthTokenCookie)
-        .and_then(parse_token)
-    {
-        Ok((user_id, _exp, _sign)) => {
-            // TODO: Token components validation
-            Ok(Ctx::new(user_id))
+    // An Authorization header (non-browser clients) takes precedence over the cookie.
+    let result_ctx = match req.headers().get(AUTHORIZATION) {
+        Some(auth_header) => ctx_from_bearer(&mm, auth_header).await,
+        None => {
+            let result_ctx = ctx_from_cookie(&mm, &cookies).await;
+
+            if result_ctx.as_ref().is_err_and(is_token_cookie_invalid) {
+                remove_token_cookie(&cookies);
+                debug!("{:<12} - mw_ctx_resolve - Removed AUTH_TOKEN cookie", "MIDDLEWARE");
+            }
+
+            result_ctx
         }
-        Err(e) => Err(e),
     };
 
-    // Remove the cookie if something went wrong other than NoAuthTokenCookie.
-    if result_ctx.is_err()
-        && !matches!(result_ctx, Err(Error::AuthFailNoAuthTokenCookie))
-    {
-        cookies.remove(Cookie::from(AUTH_TOKEN));
-        debug!("{:<12} - mw_ctx_resolve - Removed AUTH_TOKEN cookie", "MIDDLEWARE");
-    }
-
     // Store the ctx_result in the request extensions.
-    req.extensions_mut().insert(std::sync::Arc::new(result_ctx));
+    req.extensions_mut().insert(result_ctx);
     
     Ok(next.run(req).await)
 }
 
+/// Remove the cookie only when its token is not valid, or its user is gone.
+/// A db error (e.g., a pool timeout) is a 5xx, the session is kept.
+fn is_token_cookie_invalid(ex: &Error) -> bool {
+    matches!(
+        ex,
+        Error::AuthFailTokenWrongFormat
+            | Error::AuthFailTokenSignatureNotMatching
+            | Error::AuthFailTokenExpired
+            | Error::AuthFailUserNotFound { .. }
+    )
+}
+
+async fn ctx_from_cookie(mm: &ModelManager, cookies: &Cookies) -> Result<Ctx> {
+    let auth_token = cookies.get(AUTH_TOKEN).map(|c| c.value().to_string());
+    debug!("AUTH_TOKEN cookie: {}", if auth_token.is_some() { "present" } else { "missing" });
+
+    let user_id = auth_token
+        .ok_or(Error::AuthFailNoAuthTokenCookie)
+        .and_then(|token| validate_token(&token))?;
+    let role = mm.get_user_role(user_id).await?;
+
+    Ok(Ctx::new(user_id, role))
+}
+
+/// Resolve `Authorization: Bearer <token>`, where the token is either
+/// a session token (same format as the AUTH_TOKEN cookie) or an api key.
+async fn ctx_from_bearer(mm: &ModelManager, auth_header: &HeaderValue) -> Result<Ctx> {
+    let token = auth_header
+        .to_str()
+        .ok()
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .ok_or(Error::AuthFailBearerWrongFormat)?;
+
+    if token.starts_with(API_KEY_PREFIX) {
+        mm.resolve_api_key(token).await
+    } else {
+        let user_id = validate_token(token)?;
+        let role = mm.get_user_role(user_id).await?;
+        Ok(Ctx::new_bearer(user_id, role))
+    }
+}
+
 // Ctx Extractor
 
 // Implement async trait
@@ -93,19 +147,24 @@ where
 
 // END - Ctx Extractor
 
-/// Parse a token of format `user-[user-id].[expiration].[signature]`
-/// Returns (user_id, expiration, signature)
-pub fn parse_token(token: String) -> Result<(u64, String, String)> {
-    debug!("{:<12} - parse_token - token: {token}", "PARSE_TOKEN");
-    
-    let (_whole, user_id, exp, sign) = regex_captures!(
-        r#"^user-(\d+)\.(\d+)\.(.+)$"#, // a literal regex
-        &token
-    ).ok_or(Error::AuthFailTokenWrongFormat)?;
+// region:    --- Tests
 
-    let user_id: u64 = user_id
-        .parse()
-        .map_err(|_| Error::AuthFailTokenWrongFormat)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok((user_id, exp.to_string(), sign.to_string()))
+    #[test]
+    fn test_token_cookie_invalid() {
+        assert!(is_token_cookie_invalid(&Error::AuthFailTokenExpired));
+        assert!(is_token_cookie_invalid(&Error::AuthFailTokenSignatureNotMatching));
+        assert!(is_token_cookie_invalid(&Error::AuthFailUserNotFound { user_id: 1 }));
+    }
+
+    #[test]
+    fn test_token_cookie_kept() {
+        assert!(!is_token_cookie_invalid(&Error::AuthFailNoAuthTokenCookie));
+        assert!(!is_token_cookie_invalid(&Error::DbQueryFail("pool timed out".to_string())));
+    }
 }
+
+// endregion: --- Tests
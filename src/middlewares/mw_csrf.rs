@@ -0,0 +1,153 @@
+//! CSRF protection for cookie authenticated, mutating requests.
+//! The `Origin` (or, when absent, the `Referer`) must be one of `SERVICE_ALLOWED_ORIGINS`.
+//! Requests without either header are refused.
+//! Bearer and api key authenticated requests are exempt, since a browser
+//! does not attach those on its own.
+
+use axum::body::Body;
+use axum::http::header::{ORIGIN, REFERER};
+use axum::http::{HeaderMap, Method, Request, Response};
+use axum::middleware::Next;
+use reqwest::Url;
+use tracing::debug;
+
+use crate::config;
+use crate::ctx::{AuthMethod, Ctx};
+use crate::error::{Error, Result};
+
+pub async fn mw_csrf(
+    ctx: Result<Ctx>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>> {
+    debug!("{:<12} - mw_csrf", "MIDDLEWARE");
+
+    if needs_origin_check(req.method(), &ctx) {
+        check_origin(req.headers(), &config().ALLOWED_ORIGINS)?;
+    }
+
+    Ok(next.run(req).await)
+}
+
+/// Mutating requests, unless authenticated otherwise than by the cookie.
+fn needs_origin_check(method: &Method, ctx: &Result<Ctx>) -> bool {
+    let cookie_auth = ctx.as_ref().map(|ctx| ctx.auth_method() == AuthMethod::Cookie).unwrap_or(true);
+    !method.is_safe() && cookie_auth
+}
+
+fn check_origin(headers: &HeaderMap, allowed_origins: &[String]) -> Result<()> {
+    let origin = headers
+        .get(ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim_end_matches('/').to_string())
+        .or_else(|| {
+            headers
+                .get(REFERER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| Url::parse(v).ok())
+                .map(|url| url.origin().ascii_serialization())
+        })
+        .ok_or(Error::CsrfFailNoOrigin)?;
+
+    let allowed = allowed_origins
+        .iter()
+        .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(&origin));
+
+    if allowed {
+        Ok(())
+    } else {
+        Err(Error::CsrfFailOriginNotAllowed { origin })
+    }
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::HeaderValue;
+
+    use crate::ctx::Role;
+
+    fn headers(entries: &[(axum::http::HeaderName, &'static str)]) -> HeaderMap {
+        entries
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    fn allowed() -> Vec<String> {
+        vec!["https://app.example.com/".to_string(), "http://localhost:3000".to_string()]
+    }
+
+    #[test]
+    fn test_check_origin_matching() {
+        let res = check_origin(&headers(&[(ORIGIN, "https://app.example.com")]), &allowed());
+        assert!(res.is_ok());
+
+        let res = check_origin(&headers(&[(ORIGIN, "HTTP://LOCALHOST:3000/")]), &allowed());
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_check_origin_not_allowed() {
+        let res = check_origin(&headers(&[(ORIGIN, "https://evil.example.com")]), &allowed());
+        assert!(matches!(res, Err(Error::CsrfFailOriginNotAllowed { origin }) if origin == "https://evil.example.com"));
+    }
+
+    #[test]
+    fn test_check_origin_port_mismatch() {
+        let res = check_origin(&headers(&[(ORIGIN, "http://localhost:8080")]), &allowed());
+        assert!(matches!(res, Err(Error::CsrfFailOriginNotAllowed { .. })));
+
+        let res = check_origin(&headers(&[(REFERER, "http://localhost:8080/index.html")]), &allowed());
+        assert!(matches!(res, Err(Error::CsrfFailOriginNotAllowed { origin }) if origin == "http://localhost:8080"));
+    }
+
+    #[test]
+    fn test_check_origin_referer_fallback() {
+        let res = check_origin(&headers(&[(REFERER, "https://app.example.com/meals?day=1")]), &allowed());
+        assert!(res.is_ok());
+
+        // The Origin wins over the Referer.
+        let res = check_origin(
+            &headers(&[(ORIGIN, "https://evil.example.com"), (REFERER, "https://app.example.com/")]),
+            &allowed(),
+        );
+        assert!(matches!(res, Err(Error::CsrfFailOriginNotAllowed { .. })));
+    }
+
+    #[test]
+    fn test_check_origin_null() {
+        // Sent by sandboxed iframes and some redirects, never allowed.
+        let res = check_origin(&headers(&[(ORIGIN, "null")]), &allowed());
+        assert!(matches!(res, Err(Error::CsrfFailOriginNotAllowed { origin }) if origin == "null"));
+    }
+
+    #[test]
+    fn test_check_origin_missing() {
+        let res = check_origin(&HeaderMap::new(), &allowed());
+        assert!(matches!(res, Err(Error::CsrfFailNoOrigin)));
+
+        let res = check_origin(&headers(&[(REFERER, "not a url")]), &allowed());
+        assert!(matches!(res, Err(Error::CsrfFailNoOrigin)));
+    }
+
+    #[test]
+    fn test_needs_origin_check() {
+        let cookie = Ok(Ctx::new(1, Role::User));
+        assert!(needs_origin_check(&Method::POST, &cookie));
+        assert!(needs_origin_check(&Method::DELETE, &cookie));
+        assert!(!needs_origin_check(&Method::GET, &cookie));
+
+        // Not authenticated (e.g., login), checked too.
+        assert!(needs_origin_check(&Method::POST, &Err(Error::AuthFailNoAuthTokenCookie)));
+
+        // Bearer and api key requests are exempt.
+        assert!(!needs_origin_check(&Method::POST, &Ok(Ctx::new_bearer(1, Role::User))));
+        assert!(!needs_origin_check(&Method::POST, &Ok(Ctx::new_api_key(1, Role::User, vec![]))));
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,28 @@
+//! Http request metrics. Layered outside of `mw_response_map`,
+//! so that the status is the one sent to the client.
+
+use axum::body::Body;
+use axum::extract::MatchedPath;
+use axum::http::{Request, Response};
+use axum::middleware::Next;
+use std::time::Instant;
+
+use crate::metrics::metrics;
+
+pub async fn mw_metrics(req: Request<Body>, next: Next) -> Response<Body> {
+    let _in_flight = metrics().in_flight_guard();
+    let instant_in = Instant::now();
+
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|mp| mp.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = req.method().to_string();
+
+    let res = next.run(req).await;
+
+    metrics().observe_http_request(&route, &method, res.status().as_u16(), instant_in.elapsed());
+
+    res
+}
@@ -0,0 +1,409 @@
+//! Request throttling.
+//! - Token buckets per user id (or client ip when not authenticated),
+//!   with separate limits for login, image analysis and the rest of the api.
+//! - Progressive lockout per username after repeated login failures.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Request, Response};
+use axum::middleware::Next;
+use tracing::debug;
+
+use crate::config;
+use crate::ctx::Ctx;
+use crate::error::{Error, Result};
+use crate::web::client_ip::ClientIp;
+
+// At this many tracked keys, the idle ones are dropped, then the least recently seen ones
+// down to PRUNED_KEYS, so that a prune runs at most once per MAX_TRACKED_KEYS - PRUNED_KEYS new keys.
+// A dropped active key starts over (a full bucket, no failures).
+const MAX_TRACKED_KEYS: usize = 10_000;
+const PRUNED_KEYS: usize = MAX_TRACKED_KEYS * 3 / 4;
+
+// Lockout doubles on each failure, up to this.
+const LOGIN_LOCKOUT_MAX: Duration = Duration::from_secs(60 * 60);
+
+// The failures of a username are forgotten this long after the last one.
+const LOGIN_FAILURES_DECAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+// region:    --- Token Bucket
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket limiter: `capacity` requests in a burst, refilled at `capacity` per minute.
+#[derive(Clone)]
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn per_minute(capacity: u32) -> Self {
+        Self {
+            capacity: capacity as f64,
+            refill_per_sec: capacity as f64 / 60.,
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Take one token for `key`, or return how long until one is available.
+    pub fn check(&self, key: &str) -> core::result::Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> core::result::Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // A bucket full again is the same as a new one.
+        if !buckets.contains_key(key) {
+            prune(&mut buckets, |b| self.refilled(b, now) >= self.capacity, |b| b.updated);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1. {
+            bucket.tokens -= 1.;
+            Ok(())
+        } else {
+            let missing = 1. - bucket.tokens;
+            Err(Duration::from_secs_f64(missing / self.refill_per_sec))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity)
+    }
+}
+
+/// When at MAX_TRACKED_KEYS, drops the `idle` entries, then the least recently seen ones
+/// down to PRUNED_KEYS.
+fn prune<V>(entries: &mut HashMap<String, V>, idle: impl Fn(&V) -> bool, last_seen: impl Fn(&V) -> Instant) {
+    if entries.len() < MAX_TRACKED_KEYS {
+        return;
+    }
+
+    entries.retain(|_, entry| !idle(entry));
+    if entries.len() > PRUNED_KEYS {
+        let mut seen: Vec<Instant> = entries.values().map(&last_seen).collect();
+        let (_, cutoff, _) = seen.select_nth_unstable(entries.len() - PRUNED_KEYS);
+        let cutoff = *cutoff;
+        entries.retain(|_, entry| last_seen(entry) >= cutoff);
+    }
+}
+
+// endregion: --- Token Bucket
+
+// region:    --- Rate Limit Middleware
+
+#[derive(Clone)]
+pub struct RateLimiters {
+    login: RateLimiter,
+    analyze: RateLimiter,
+    api: RateLimiter,
+}
+
+impl RateLimiters {
+    pub fn from_config() -> Self {
+        Self {
+            login: RateLimiter::per_minute(config().RATE_LIMIT_LOGIN_PER_MIN),
+            analyze: RateLimiter::per_minute(config().RATE_LIMIT_ANALYZE_PER_MIN),
+            api: RateLimiter::per_minute(config().RATE_LIMIT_API_PER_MIN),
+        }
+    }
+}
+
+pub async fn mw_rate_limit(
+    State(limiters): State<RateLimiters>,
+    ClientIp(client_ip): ClientIp,
+    ctx: Result<Ctx>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response<Body>> {
+    debug!("{:<12} - mw_rate_limit", "MIDDLEWARE");
+
+    // Login is always keyed by ip, so attempts cannot be spread over user ids.
+    let path = req.uri().path();
+    let (limiter, key_by_user) = if path == "/api/login" {
+        (Some(&limiters.login), false)
+    } else if path == "/analyze-image" || path == "/analyze-label" {
+        (Some(&limiters.analyze), true)
+    } else if path.starts_with("/api/") {
+        (Some(&limiters.api), true)
+    } else {
+        (None, false)
+    };
+
+    if let Some(limiter) = limiter {
+        let key = match ctx {
+            Ok(ctx) if key_by_user => format!("user:{}", ctx.user_id()),
+            _ => format!("ip:{client_ip}"),
+        };
+
+        limiter.check(&key).map_err(|retry_after| Error::RateLimited {
+            retry_after_secs: retry_after.as_secs().max(1),
+        })?;
+    }
+
+    Ok(next.run(req).await)
+}
+
+// endregion: --- Rate Limit Middleware
+
+// region:    --- Login Lockout
+
+struct LoginFailures {
+    count: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Progressive lockout per username, used by the login handler.
+#[derive(Clone)]
+pub struct LoginLockout {
+    threshold: u32,
+    base: Duration,
+    failures: Arc<Mutex<HashMap<String, LoginFailures>>>,
+}
+
+impl LoginLockout {
+    pub fn from_config() -> Self {
+        Self::new(config().LOGIN_LOCKOUT_THRESHOLD, Duration::from_secs(config().LOGIN_LOCKOUT_BASE_SEC))
+    }
+
+    fn new(threshold: u32, base: Duration) -> Self {
+        Self {
+            threshold,
+            base,
+            failures: Arc::default(),
+        }
+    }
+
+    /// Fails with `LoginLocked` while the username is locked.
+    pub fn check(&self, username: &str) -> Result<()> {
+        self.check_at(username, Instant::now())
+    }
+
+    fn check_at(&self, username: &str, now: Instant) -> Result<()> {
+        let failures = self.failures.lock().unwrap();
+        let locked_until = failures.get(username).and_then(|f| f.locked_until);
+
+        match locked_until {
+            Some(until) if until > now => Err(Error::LoginLocked {
+                retry_after_secs: (until - now).as_secs().max(1),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// From the threshold on, each failure locks the username twice as long as the previous one.
+    /// The count starts over LOGIN_FAILURES_DECAY after the last failure.
+    pub fn record_failure(&self, username: &str) {
+        self.record_failure_at(username, Instant::now())
+    }
+
+    fn record_failure_at(&self, username: &str, now: Instant) {
+        let mut failures = self.failures.lock().unwrap();
+        let decayed = |f: &LoginFailures| now.saturating_duration_since(f.last_failure) >= LOGIN_FAILURES_DECAY;
+
+        if !failures.contains_key(username) {
+            prune(&mut failures, decayed, |f| f.last_failure);
+        }
+
+        let entry = failures.entry(username.to_string()).or_insert(LoginFailures {
+            count: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        if decayed(entry) {
+            entry.count = 0;
+        }
+        entry.count += 1;
+        entry.last_failure = now;
+
+        if entry.count >= self.threshold {
+            let exponent = (entry.count - self.threshold).min(16);
+            let lock = self.base.saturating_mul(1 << exponent).min(LOGIN_LOCKOUT_MAX);
+            entry.locked_until = Some(now + lock);
+        }
+    }
+
+    pub fn record_success(&self, username: &str) {
+        self.failures.lock().unwrap().remove(username);
+    }
+}
+
+// endregion: --- Login Lockout
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn retry_after_secs(result: Result<()>) -> u64 {
+        match result {
+            Err(Error::LoginLocked { retry_after_secs }) => retry_after_secs,
+            other => panic!("expected LoginLocked, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rate_limiter_burst() {
+        let limiter = RateLimiter::per_minute(3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at("ip:1", now).is_ok());
+        }
+        assert!(limiter.check_at("ip:1", now).is_err());
+        // Other keys have their own bucket.
+        assert!(limiter.check_at("ip:2", now).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limiter_refill() {
+        let limiter = RateLimiter::per_minute(6); // one token every 10s
+        let now = Instant::now();
+        for _ in 0..6 {
+            limiter.check_at("k", now).unwrap();
+        }
+
+        assert!(limiter.check_at("k", now + secs(9)).is_err());
+        assert!(limiter.check_at("k", now + secs(10)).is_ok());
+        assert!(limiter.check_at("k", now + secs(10)).is_err());
+        // Refilled up to the capacity only.
+        let later = now + secs(3600);
+        for _ in 0..6 {
+            assert!(limiter.check_at("k", later).is_ok());
+        }
+        assert!(limiter.check_at("k", later).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_retry_after() {
+        let limiter = RateLimiter::per_minute(6);
+        let now = Instant::now();
+        for _ in 0..6 {
+            limiter.check_at("k", now).unwrap();
+        }
+
+        let retry_after = limiter.check_at("k", now).unwrap_err();
+        assert_eq!(retry_after.as_secs_f64().round(), 10.);
+
+        let retry_after = limiter.check_at("k", now + secs(4)).unwrap_err();
+        assert_eq!(retry_after.as_secs_f64().round(), 6.);
+    }
+
+    #[test]
+    fn test_rate_limiter_prune_bounded() {
+        let limiter = RateLimiter::per_minute(5);
+        let now = Instant::now();
+
+        // Distinct keys, all active (not full again): the least recently seen are dropped.
+        for i in 0..MAX_TRACKED_KEYS + 10 {
+            limiter.check_at(&format!("ip:{i}"), now + Duration::from_millis(i as u64)).unwrap();
+        }
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(buckets.len() <= PRUNED_KEYS + 10);
+        assert!(buckets.contains_key(&format!("ip:{}", MAX_TRACKED_KEYS + 9)));
+        assert!(!buckets.contains_key("ip:0"));
+    }
+
+    #[test]
+    fn test_rate_limiter_prune_idle_first() {
+        let limiter = RateLimiter::per_minute(5);
+        let now = Instant::now();
+        for i in 0..MAX_TRACKED_KEYS - 1 {
+            limiter.check_at(&format!("ip:{i}"), now).unwrap();
+        }
+        limiter.check_at("ip:active", now + secs(59)).unwrap();
+
+        // A minute later the first buckets are full again, dropped, and the active one is kept.
+        limiter.check_at("ip:new", now + secs(60)).unwrap();
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert!(buckets.contains_key("ip:active"));
+    }
+
+    #[test]
+    fn test_login_lockout_threshold() {
+        let lockout = LoginLockout::new(3, secs(30));
+        let now = Instant::now();
+
+        lockout.record_failure_at("demo", now);
+        lockout.record_failure_at("demo", now);
+        assert!(lockout.check_at("demo", now).is_ok());
+
+        lockout.record_failure_at("demo", now);
+        assert_eq!(retry_after_secs(lockout.check_at("demo", now)), 30);
+        assert_eq!(retry_after_secs(lockout.check_at("demo", now + secs(20))), 10);
+        assert!(lockout.check_at("demo", now + secs(30)).is_ok());
+        assert!(lockout.check_at("other", now).is_ok());
+    }
+
+    #[test]
+    fn test_login_lockout_doubling_and_cap() {
+        let lockout = LoginLockout::new(1, secs(30));
+        let now = Instant::now();
+
+        for expected in [30, 60, 120, 240] {
+            lockout.record_failure_at("demo", now);
+            assert_eq!(retry_after_secs(lockout.check_at("demo", now)), expected);
+        }
+        for _ in 0..40 {
+            lockout.record_failure_at("demo", now);
+        }
+        assert_eq!(retry_after_secs(lockout.check_at("demo", now)), LOGIN_LOCKOUT_MAX.as_secs());
+    }
+
+    #[test]
+    fn test_login_lockout_failures_decay() {
+        let lockout = LoginLockout::new(3, secs(30));
+        let now = Instant::now();
+        lockout.record_failure_at("demo", now);
+        lockout.record_failure_at("demo", now);
+
+        // Long after, the old failures do not count toward the threshold.
+        let later = now + LOGIN_FAILURES_DECAY;
+        lockout.record_failure_at("demo", later);
+        assert!(lockout.check_at("demo", later).is_ok());
+        lockout.record_failure_at("demo", later);
+        lockout.record_failure_at("demo", later);
+        assert_eq!(retry_after_secs(lockout.check_at("demo", later)), 30);
+    }
+
+    #[test]
+    fn test_login_lockout_success_resets() {
+        let lockout = LoginLockout::new(1, secs(30));
+        let now = Instant::now();
+        lockout.record_failure_at("demo", now);
+        lockout.record_failure_at("demo", now);
+
+        lockout.record_success("demo");
+        assert!(lockout.check_at("demo", now).is_ok());
+        lockout.record_failure_at("demo", now);
+        assert_eq!(retry_after_secs(lockout.check_at("demo", now)), 30);
+    }
+}
+
+// endregion: --- Tests
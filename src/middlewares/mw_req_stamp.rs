@@ -0,0 +1,124 @@
+//! Request id and start time, stamped at ingress.
+//! - The id comes from `X-Request-Id`, else from the W3C `traceparent` trace id, else a new uuid.
+//! - The rest of the request runs in a `request` span carrying the id, so handler logs can be
+//!   correlated with the request log line.
+//! - The id is echoed back in the `X-Request-Id` response header.
+
+use std::time::Instant;
+
+use axum::body::Body;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{HeaderName, HeaderValue, Request, Response};
+use axum::middleware::Next;
+use time::OffsetDateTime;
+use tracing::{debug, info_span, Instrument};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::telemetry::{set_span_parent, span_trace_id};
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+const TRACEPARENT: &str = "traceparent";
+
+// Longer incoming ids are not trusted, a new one is generated.
+const REQUEST_ID_MAX_LEN: usize = 128;
+
+#[derive(Clone, Debug)]
+pub struct ReqStamp {
+    pub req_id: String,
+    pub trace_id: Option<String>,
+    pub time_in: OffsetDateTime,
+    pub instant_in: Instant,
+}
+
+impl ReqStamp {
+    pub fn now() -> Self {
+        Self::new(Uuid::new_v4().to_string(), None)
+    }
+
+    fn new(req_id: String, trace_id: Option<String>) -> Self {
+        Self {
+            req_id,
+            trace_id,
+            time_in: OffsetDateTime::now_utc(),
+            instant_in: Instant::now(),
+        }
+    }
+}
+
+pub async fn mw_req_stamp(mut req: Request<Body>, next: Next) -> Response<Body> {
+    let headers = req.headers();
+    let parent_trace_id = headers
+        .get(TRACEPARENT)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_traceparent_trace_id);
+    let req_id = headers
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid_request_id(v))
+        .map(str::to_string)
+        .or_else(|| parent_trace_id.clone())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let span = info_span!(
+        "request",
+        req_id = %req_id,
+        method = %req.method(),
+        path = %req.uri().path(),
+    );
+    set_span_parent(&span, headers);
+    let trace_id = span_trace_id(&span).or(parent_trace_id);
+
+    span.in_scope(|| debug!("{:<12} - mw_req_stamp", "MIDDLEWARE"));
+
+    req.extensions_mut().insert(ReqStamp::new(req_id.clone(), trace_id));
+
+    let mut res = next.run(req).instrument(span).await;
+
+    if let Ok(req_id) = HeaderValue::from_str(&req_id) {
+        res.headers_mut().insert(X_REQUEST_ID, req_id);
+    }
+
+    res
+}
+
+/// Visible ascii only, so that it can be echoed back as a header and logged as is.
+fn is_valid_request_id(req_id: &str) -> bool {
+    !req_id.is_empty()
+        && req_id.len() <= REQUEST_ID_MAX_LEN
+        && req_id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// `{version}-{trace-id}-{parent-id}-{flags}`, e.g.,
+/// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+fn parse_traceparent_trace_id(traceparent: &str) -> Option<String> {
+    let mut parts = traceparent.trim().split('-');
+    let (version, trace_id, parent_id) = (parts.next()?, parts.next()?, parts.next()?);
+
+    let is_hex = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit());
+    let is_valid = is_hex(version, 2)
+        && version != "ff"
+        && is_hex(trace_id, 32)
+        && trace_id.bytes().any(|b| b != b'0')
+        && is_hex(parent_id, 16);
+
+    is_valid.then(|| trace_id.to_ascii_lowercase())
+}
+
+// ReqStamp Extractor
+impl<S: Send + Sync> FromRequestParts<S> for ReqStamp {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self> {
+        debug!("{:<12} - ReqStamp", "EXTRACTOR");
+
+        parts
+            .extensions
+            .get::<ReqStamp>()
+            .cloned()
+            .ok_or(Error::ReqStampNotInReqExt)
+    }
+}
+// END - ReqStamp Extractor
+
@@ -0,0 +1,370 @@
+//! Image analyses, stored as answered, and as corrected by their user.
+//! A corrected food keeps the index of the food it corrects, so that the export pairs them
+//! (renamed, reweighted, removed or added), to evaluate the prompts and models (see `export-corrections`).
+
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use sqlx::types::Json;
+use sqlx::FromRow;
+use time::OffsetDateTime;
+
+use crate::ctx::{Ctx, Permission};
+use crate::error::{Error, Result};
+use crate::model::model::ModelManager;
+use crate::nutrition::FoodItem;
+use tracing::instrument;
+
+/// Analysis lists are capped to this.
+pub const ANALYSIS_LIST_MAX_LIMIT: i64 = 200;
+
+// -- Analysis Types
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Analysis {
+    pub id: i64,
+    pub image_sha256: String,
+    pub model: Option<String>, // None for the older rows stored for a cached answer
+    pub prompt_id: String,
+    pub foods: Vec<FoodItem>,
+    pub corrected_foods: Option<Vec<CorrectedFood>>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub corrected_at: Option<OffsetDateTime>,
+}
+
+/// `index` is the corrected food in the analysis `foods`, None for a food added by the user.
+/// The values are None when not known, e.g., the nutrients of an added food.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CorrectedFood {
+    pub index: Option<usize>,
+    pub name: String,
+    pub weight_g: Option<f32>,
+    pub calories: Option<f32>,
+    pub protein_g: Option<f32>,
+    pub fat_g: Option<f32>,
+    pub carbohydrates_g: Option<f32>,
+    pub sugar_g: Option<f32>,
+    pub sodium_mg: Option<f32>,
+}
+
+/// The foods as they should have been. The foods of the analysis left out are removed.
+#[derive(Deserialize)]
+pub struct AnalysisForCorrect {
+    pub foods: Vec<FoodCorrection>,
+}
+
+/// A food of the analysis (`index`) with the values to change, or an added food (no `index`).
+/// When only the weight changes, the nutrients of the analysis are scaled to it.
+#[derive(Deserialize)]
+pub struct FoodCorrection {
+    pub index: Option<usize>,
+    pub name: Option<String>,
+    pub weight_g: Option<f32>,
+    pub calories: Option<f32>,
+    pub protein_g: Option<f32>,
+    pub fat_g: Option<f32>,
+    pub carbohydrates_g: Option<f32>,
+    pub sugar_g: Option<f32>,
+    pub sodium_mg: Option<f32>,
+}
+
+/// A corrected analysis, as exported (one json line each), without its user.
+#[derive(Serialize)]
+pub struct CorrectionPair {
+    pub analysis_id: i64,
+    pub image_sha256: String,
+    pub model: Option<String>,
+    pub prompt_id: String,
+    pub foods: Vec<FoodItem>,
+    pub corrected_foods: Vec<CorrectedFood>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub corrected_at: OffsetDateTime,
+}
+
+#[derive(FromRow)]
+struct AnalysisRow {
+    id: i64,
+    image_sha256: String,
+    model: Option<String>,
+    prompt_id: String,
+    foods: Json<Vec<FoodItem>>,
+    corrected_foods: Option<Json<Vec<CorrectedFood>>>,
+    created_at: OffsetDateTime,
+    corrected_at: Option<OffsetDateTime>,
+}
+
+impl From<AnalysisRow> for Analysis {
+    fn from(row: AnalysisRow) -> Self {
+        Self {
+            id: row.id,
+            image_sha256: row.image_sha256,
+            model: row.model,
+            prompt_id: row.prompt_id,
+            foods: row.foods.0,
+            corrected_foods: row.corrected_foods.map(|foods| foods.0),
+            created_at: row.created_at,
+            corrected_at: row.corrected_at,
+        }
+    }
+}
+
+// End: -- Analysis Types
+
+const ANALYSIS_COLUMNS: &str = "id, image_sha256, model, prompt_id, foods, corrected_foods, created_at, corrected_at";
+
+impl ModelManager {
+    /// Stores the foods of an analysis of the ctx user, returns its id.
+    #[instrument(skip_all)]
+    pub async fn create_analysis(
+        &self,
+        ctx: &Ctx,
+        image_sha256: &str,
+        model: &str,
+        prompt_id: &str,
+        foods: &[FoodItem],
+    ) -> Result<i64> {
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO analysis (user_id, image_sha256, model, prompt_id, foods)
+             VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        )
+        .bind(ctx.user_id() as i64)
+        .bind(image_sha256)
+        .bind(model)
+        .bind(prompt_id)
+        .bind(Json(foods))
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// The analyses of the ctx user among `ids`, in no particular order.
+    #[instrument(skip_all)]
+    pub async fn get_analyses(&self, ctx: &Ctx, ids: &[i64]) -> Result<Vec<Analysis>> {
+        let rows: Vec<AnalysisRow> = sqlx::query_as(&format!(
+            "SELECT {ANALYSIS_COLUMNS} FROM analysis WHERE user_id = $1 AND id = ANY($2)"
+        ))
+        .bind(ctx.user_id() as i64)
+        .bind(ids)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows.into_iter().map(Analysis::from).collect())
+    }
+
+    /// The ctx user's analyses, latest first.
+    #[instrument(skip_all)]
+    pub async fn list_analyses(&self, ctx: Ctx, limit: i64) -> Result<Vec<Analysis>> {
+        let rows: Vec<AnalysisRow> = sqlx::query_as(&format!(
+            "SELECT {ANALYSIS_COLUMNS} FROM analysis WHERE user_id = $1 ORDER BY id DESC LIMIT $2"
+        ))
+        .bind(ctx.user_id() as i64)
+        .bind(limit.clamp(1, ANALYSIS_LIST_MAX_LIMIT))
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows.into_iter().map(Analysis::from).collect())
+    }
+
+    #[instrument(skip_all)]
+    pub async fn get_analysis(&self, ctx: Ctx, id: i64) -> Result<Analysis> {
+        let row: Option<AnalysisRow> = sqlx::query_as(&format!(
+            "SELECT {ANALYSIS_COLUMNS} FROM analysis WHERE id = $1 AND user_id = $2"
+        ))
+        .bind(id)
+        .bind(ctx.user_id() as i64)
+        .fetch_optional(&self.db)
+        .await?;
+
+        row.map(Analysis::from).ok_or(Error::AnalysisNotFound { id })
+    }
+
+    /// Replaces the corrections of one of the ctx user's analyses, the analysis foods are kept as is.
+    #[instrument(skip_all)]
+    pub async fn correct_analysis(&self, ctx: Ctx, id: i64, analysis_fc: AnalysisForCorrect) -> Result<Analysis> {
+        let analysis = self.get_analysis(ctx.clone(), id).await?;
+        let corrected_foods = corrected_foods(&analysis.foods, analysis_fc.foods)?;
+
+        let row: Option<AnalysisRow> = sqlx::query_as(&format!(
+            "UPDATE analysis SET corrected_foods = $3, corrected_at = now()
+             WHERE id = $1 AND user_id = $2
+             RETURNING {ANALYSIS_COLUMNS}"
+        ))
+        .bind(id)
+        .bind(ctx.user_id() as i64)
+        .bind(Json(corrected_foods))
+        .fetch_optional(&self.db)
+        .await?;
+
+        row.map(Analysis::from).ok_or(Error::AnalysisNotFound { id })
+    }
+
+    /// The corrected analyses of all the users, corrected since `since` when given, oldest first.
+    /// Requires `Permission::CorrectionsExport` (admins).
+    #[instrument(skip_all)]
+    pub async fn list_correction_pairs(&self, ctx: &Ctx, since: Option<OffsetDateTime>) -> Result<Vec<CorrectionPair>> {
+        ctx.require_permission(Permission::CorrectionsExport)?;
+
+        let rows: Vec<AnalysisRow> = sqlx::query_as(&format!(
+            "SELECT {ANALYSIS_COLUMNS} FROM analysis
+             WHERE corrected_at IS NOT NULL AND ($1::timestamptz IS NULL OR corrected_at >= $1)
+             ORDER BY corrected_at, id"
+        ))
+        .bind(since)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(CorrectionPair {
+                    analysis_id: row.id,
+                    image_sha256: row.image_sha256,
+                    model: row.model,
+                    prompt_id: row.prompt_id,
+                    foods: row.foods.0,
+                    corrected_foods: row.corrected_foods?.0,
+                    created_at: row.created_at,
+                    corrected_at: row.corrected_at?,
+                })
+            })
+            .collect())
+    }
+}
+
+/// The corrections applied to the analysis foods, in the order given.
+fn corrected_foods(foods: &[FoodItem], corrections: Vec<FoodCorrection>) -> Result<Vec<CorrectedFood>> {
+    let mut corrected_indexes = Vec::new();
+
+    corrections
+        .into_iter()
+        .map(|correction| {
+            let Some(index) = correction.index else {
+                let name = correction.name.map(|name| name.trim().to_string()).unwrap_or_default();
+                return Ok(CorrectedFood {
+                    index: None,
+                    name,
+                    weight_g: correction.weight_g,
+                    calories: correction.calories,
+                    protein_g: correction.protein_g,
+                    fat_g: correction.fat_g,
+                    carbohydrates_g: correction.carbohydrates_g,
+                    sugar_g: correction.sugar_g,
+                    sodium_mg: correction.sodium_mg,
+                });
+            };
+
+            let food = foods.get(index).ok_or(Error::AnalysisCorrectFailIndexInvalid { index })?;
+            if corrected_indexes.contains(&index) {
+                return Err(Error::AnalysisCorrectFailIndexInvalid { index });
+            }
+            corrected_indexes.push(index);
+
+            // A new weight scales the analysis nutrients which are not corrected.
+            let factor = match (correction.weight_g, food.weight_g) {
+                (Some(weight_g), Some(food_weight_g)) if food_weight_g > 0. => weight_g / food_weight_g,
+                _ => 1.,
+            };
+            let value = |corrected: Option<f32>, analyzed: f32| {
+                Some(corrected.unwrap_or_else(|| (analyzed * factor * 10.).round() / 10.))
+            };
+
+            Ok(CorrectedFood {
+                index: Some(index),
+                name: correction.name.map(|name| name.trim().to_string()).unwrap_or_else(|| food.name.clone()),
+                weight_g: correction.weight_g.or(food.weight_g),
+                calories: value(correction.calories, food.calories),
+                protein_g: value(correction.protein_g, food.protein_g),
+                fat_g: value(correction.fat_g, food.fat_g),
+                carbohydrates_g: value(correction.carbohydrates_g, food.carbohydrates_g),
+                sugar_g: value(correction.sugar_g, food.sugar_g),
+                sodium_mg: value(correction.sodium_mg, food.sodium_mg),
+            })
+        })
+        .collect()
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn foods() -> Vec<FoodItem> {
+        serde_json::from_value(json!([
+            { "name": "rice", "weight_g": 200, "calories": 260, "protein_g": 5, "fat_g": 0.6,
+              "carbohydrates_g": 56, "sugar_g": 0.2, "sodium_mg": 2 },
+            { "name": "chicken", "weight_g": 150, "calories": 248, "protein_g": 46, "fat_g": 5.4,
+              "carbohydrates_g": 0, "sugar_g": 0, "sodium_mg": 110 },
+        ]))
+        .unwrap()
+    }
+
+    fn corrections(value: serde_json::Value) -> Vec<FoodCorrection> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_corrected_foods_index_out_of_range() {
+        let result = corrected_foods(&foods(), corrections(json!([{ "index": 2, "name": "salad" }])));
+        assert!(matches!(result, Err(Error::AnalysisCorrectFailIndexInvalid { index: 2 })));
+    }
+
+    #[test]
+    fn test_corrected_foods_index_twice() {
+        let result = corrected_foods(&foods(), corrections(json!([{ "index": 0 }, { "index": 0, "calories": 1 }])));
+        assert!(matches!(result, Err(Error::AnalysisCorrectFailIndexInvalid { index: 0 })));
+    }
+
+    #[test]
+    fn test_corrected_foods_partial_override() {
+        // The new weight scales the nutrients which are not corrected, the corrected ones are kept.
+        let corrected = corrected_foods(
+            &foods(),
+            corrections(json!([{ "index": 0, "name": " brown rice ", "weight_g": 100, "protein_g": 3.1 }])),
+        )
+        .unwrap();
+
+        assert_eq!(corrected.len(), 1);
+        let rice = &corrected[0];
+        assert_eq!(rice.index, Some(0));
+        assert_eq!(rice.name, "brown rice");
+        assert_eq!(rice.weight_g, Some(100.));
+        assert_eq!(rice.calories, Some(130.));
+        assert_eq!(rice.protein_g, Some(3.1));
+        assert_eq!(rice.fat_g, Some(0.3));
+        assert_eq!(rice.carbohydrates_g, Some(28.));
+    }
+
+    #[test]
+    fn test_corrected_foods_untouched_item() {
+        // Kept with its analysis values, the food left out (rice) is removed.
+        let corrected = corrected_foods(&foods(), corrections(json!([{ "index": 1 }]))).unwrap();
+
+        assert_eq!(corrected.len(), 1);
+        let chicken = &corrected[0];
+        assert_eq!(chicken.index, Some(1));
+        assert_eq!(chicken.name, "chicken");
+        assert_eq!(chicken.weight_g, Some(150.));
+        assert_eq!(chicken.calories, Some(248.));
+        assert_eq!(chicken.fat_g, Some(5.4));
+        assert_eq!(chicken.sodium_mg, Some(110.));
+    }
+
+    #[test]
+    fn test_corrected_foods_added_item() {
+        let corrected = corrected_foods(&foods(), corrections(json!([{ "name": "salad", "weight_g": 80 }]))).unwrap();
+
+        assert_eq!(corrected[0].index, None);
+        assert_eq!(corrected[0].name, "salad");
+        assert_eq!(corrected[0].weight_g, Some(80.));
+        assert_eq!(corrected[0].calories, None);
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,281 @@
+//! Per-user daily and monthly image analysis quotas.
+//! Usage is counted per (user, UTC day) in `analysis_usage`,
+//! limits come from the `"user"` row, or the config defaults when not set.
+
+use serde::Serialize;
+use time::{format_description::well_known::Rfc3339, Date, Duration, OffsetDateTime, Time};
+
+use crate::{config, ctx::Ctx, error::{Error, Result}};
+use crate::model::model::ModelManager;
+use sqlx::PgConnection;
+use tracing::{instrument, warn};
+
+// -- Quota Types
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPeriod {
+    Daily,
+    Monthly,
+}
+
+/// Quota state, after a successful reservation, or as is.
+#[derive(Clone, Debug)]
+pub struct QuotaStatus {
+    pub day: Date,
+    pub daily_limit: i32,
+    pub daily_used: i32,
+    pub daily_reset: OffsetDateTime,
+    pub monthly_limit: i32,
+    pub monthly_used: i32,
+    pub monthly_reset: OffsetDateTime,
+}
+
+/// A reserved analysis. Dropped without `commit` (e.g., the client went away during the provider
+/// call, and the handler future was dropped), it is given back in a spawned task.
+pub struct QuotaReservation {
+    mm: ModelManager,
+    ctx: Ctx,
+    status: QuotaStatus,
+    committed: bool,
+}
+
+impl QuotaReservation {
+    pub fn status(&self) -> &QuotaStatus {
+        &self.status
+    }
+
+    /// The analysis happened, it counts.
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+
+    /// Give back the analysis now (e.g., the provider call failed).
+    pub async fn release(mut self) -> Result<()> {
+        self.committed = true;
+        self.mm.release_analysis_quota(&self.ctx, &self.status).await
+    }
+}
+
+impl Drop for QuotaReservation {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+
+        let (mm, ctx, status) = (self.mm.clone(), self.ctx.clone(), self.status.clone());
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(ex) = mm.release_analysis_quota(&ctx, &status).await {
+                        warn!("{:<12} - quota reservation not released - {ex:?}", "QUOTA");
+                    }
+                });
+            }
+            Err(_) => warn!("{:<12} - quota reservation not released, no runtime", "QUOTA"),
+        }
+    }
+}
+
+// End: -- Quota Types
+
+impl ModelManager {
+    /// Count one analysis for the ctx user, or fail with `QuotaExceeded`
+    /// when either the daily or the monthly limit is reached.
+    /// The reservation is given back unless committed.
+    #[instrument(skip_all)]
+    pub async fn reserve_analysis_quota(&self, ctx: &Ctx) -> Result<QuotaReservation> {
+        let mut tx = self.db.begin().await?;
+
+        // Locks the user row, so concurrent reservations of the same user are serialized.
+        let status = quota_status(&mut tx, ctx, true).await?;
+
+        if status.daily_used >= status.daily_limit {
+            return Err(quota_exceeded(QuotaPeriod::Daily, status));
+        }
+        if status.monthly_used >= status.monthly_limit {
+            return Err(quota_exceeded(QuotaPeriod::Monthly, status));
+        }
+
+        sqlx::query(
+            "INSERT INTO analysis_usage (user_id, day, count) VALUES ($1, $2, 1)
+             ON CONFLICT (user_id, day) DO UPDATE SET count = analysis_usage.count + 1",
+        )
+        .bind(ctx.user_id() as i64)
+        .bind(status.day)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(QuotaReservation {
+            mm: self.clone(),
+            ctx: ctx.clone(),
+            status: QuotaStatus {
+                daily_used: status.daily_used + 1,
+                monthly_used: status.monthly_used + 1,
+                ..status
+            },
+            committed: false,
+        })
+    }
+
+    /// The quota of the ctx user as is, e.g., for an answer from the cache, which does not count.
+    #[instrument(skip_all)]
+    pub async fn analysis_quota_status(&self, ctx: &Ctx) -> Result<QuotaStatus> {
+        let mut conn = self.db.acquire().await?;
+
+        quota_status(&mut conn, ctx, false).await
+    }
+
+    /// Give back a reserved analysis, see `QuotaReservation`.
+    #[instrument(skip_all)]
+    async fn release_analysis_quota(&self, ctx: &Ctx, status: &QuotaStatus) -> Result<()> {
+        sqlx::query(
+            "UPDATE analysis_usage SET count = count - 1
+             WHERE user_id = $1 AND day = $2 AND count > 0",
+        )
+        .bind(ctx.user_id() as i64)
+        .bind(status.day)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// region:    --- Quota helpers
+
+/// Limits and usage of the ctx user, with its row locked (`FOR UPDATE`) when `lock_user`.
+async fn quota_status(conn: &mut PgConnection, ctx: &Ctx, lock_user: bool) -> Result<QuotaStatus> {
+    let user_id = ctx.user_id() as i64;
+    let today = OffsetDateTime::now_utc().date();
+    let month_start = today.replace_day(1).expect("day 1 is valid for every month");
+
+    let lock = if lock_user { "FOR UPDATE" } else { "" };
+    let (daily_limit, monthly_limit): (Option<i32>, Option<i32>) = sqlx::query_as(&format!(
+        r#"SELECT analysis_daily_quota, analysis_monthly_quota FROM "user" WHERE id = $1 {lock}"#
+    ))
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(Error::AuthFailUserNotFound { user_id: ctx.user_id() })?;
+
+    let (daily_used, monthly_used): (i64, i64) = sqlx::query_as(
+        "SELECT COALESCE(SUM(count) FILTER (WHERE day = $2), 0),
+                COALESCE(SUM(count), 0)
+         FROM analysis_usage WHERE user_id = $1 AND day >= $3",
+    )
+    .bind(user_id)
+    .bind(today)
+    .bind(month_start)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(QuotaStatus {
+        day: today,
+        daily_limit: daily_limit.unwrap_or(config().ANALYSIS_DAILY_QUOTA),
+        daily_used: daily_used as i32,
+        daily_reset: start_of(today + Duration::days(1)),
+        monthly_limit: monthly_limit.unwrap_or(config().ANALYSIS_MONTHLY_QUOTA),
+        monthly_used: monthly_used as i32,
+        monthly_reset: start_of(next_month_start(month_start)),
+    })
+}
+
+pub fn format_reset(reset: OffsetDateTime) -> String {
+    reset.format(&Rfc3339).unwrap_or_default()
+}
+
+fn quota_exceeded(period: QuotaPeriod, quota: QuotaStatus) -> Error {
+    let (limit, reset) = match period {
+        QuotaPeriod::Daily => (quota.daily_limit, quota.daily_reset),
+        QuotaPeriod::Monthly => (quota.monthly_limit, quota.monthly_reset),
+    };
+
+    Error::QuotaExceeded {
+        period,
+        limit,
+        reset_at: format_reset(reset),
+        quota: Box::new(quota),
+    }
+}
+
+fn start_of(day: Date) -> OffsetDateTime {
+    day.with_time(Time::MIDNIGHT).assume_utc()
+}
+
+fn next_month_start(month_start: Date) -> Date {
+    // 32 days after the 1st is always in the next month.
+    (month_start + Duration::days(32))
+        .replace_day(1)
+        .expect("day 1 is valid for every month")
+}
+
+// endregion: --- Quota helpers
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::_dev_utils;
+
+    async fn demo2_ctx(mm: &ModelManager) -> Ctx {
+        let (user_id, role) = mm.get_user_by_username("demo2").await.unwrap().unwrap();
+        Ctx::new(user_id, role)
+    }
+
+    #[tokio::test]
+    async fn test_quota_reservation_commit_release_drop() {
+        let mm = _dev_utils::init_test().await;
+        let ctx = demo2_ctx(&mm).await;
+        let used = mm.analysis_quota_status(&ctx).await.unwrap().daily_used;
+
+        let reservation = mm.reserve_analysis_quota(&ctx).await.unwrap();
+        assert_eq!(reservation.status().daily_used, used + 1);
+        reservation.commit();
+        assert_eq!(mm.analysis_quota_status(&ctx).await.unwrap().daily_used, used + 1);
+
+        mm.reserve_analysis_quota(&ctx).await.unwrap().release().await.unwrap();
+        assert_eq!(mm.analysis_quota_status(&ctx).await.unwrap().daily_used, used + 1);
+
+        // Dropped, e.g., the client went away: given back in a spawned task.
+        drop(mm.reserve_analysis_quota(&ctx).await.unwrap());
+        let mut daily_used = 0;
+        for _ in 0..50 {
+            daily_used = mm.analysis_quota_status(&ctx).await.unwrap().daily_used;
+            if daily_used == used + 1 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(daily_used, used + 1);
+    }
+
+    #[tokio::test]
+    async fn test_quota_exceeded_has_status() {
+        let mm = _dev_utils::init_test().await;
+        let (user_id, role) = mm.get_user_by_username("demo1").await.unwrap().unwrap();
+        let ctx = Ctx::new(user_id, role);
+        let used = mm.analysis_quota_status(&ctx).await.unwrap().daily_used;
+        sqlx::query(r#"UPDATE "user" SET analysis_daily_quota = $2 WHERE id = $1"#)
+            .bind(user_id as i64)
+            .bind(used)
+            .execute(&mm.db)
+            .await
+            .unwrap();
+
+        let result = mm.reserve_analysis_quota(&ctx).await;
+
+        match result {
+            Err(Error::QuotaExceeded { period: QuotaPeriod::Daily, limit, quota, .. }) => {
+                assert_eq!(limit, used);
+                assert_eq!(quota.daily_used, used);
+            }
+            _ => panic!("expected QuotaExceeded"),
+        }
+    }
+}
+
+// endregion: --- Tests
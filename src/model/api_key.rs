@@ -0,0 +1,199 @@
+//! Long-lived per-user api keys.
+//! Only the sha256 hash of a key is stored, the plain key is returned once on creation.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+
+use crate::{ctx::{Ctx, Role, Scope}, error::{Error, Result}};
+use crate::model::model::ModelManager;
+use tracing::instrument;
+
+/// All api keys start with this, so they can be told apart from session tokens.
+pub const API_KEY_PREFIX: &str = "fdq_";
+
+// -- ApiKey Types
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub prefix: String, // displayable part of the key, e.g. "fdq_AbCd1234_..."
+    pub scopes: Vec<Scope>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_used_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub revoked_at: Option<OffsetDateTime>,
+}
+
+#[derive(Deserialize)]
+pub struct ApiKeyForCreate {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// Returned only once, at creation time.
+#[derive(Serialize)]
+pub struct ApiKeyCreated {
+    pub key: String,
+    pub api_key: ApiKey,
+}
+
+// Scopes are stored as text[], and converted through their serde names.
+#[derive(FromRow)]
+struct ApiKeyRow {
+    id: i64,
+    name: String,
+    prefix: String,
+    scopes: Vec<String>,
+    created_at: OffsetDateTime,
+    last_used_at: Option<OffsetDateTime>,
+    revoked_at: Option<OffsetDateTime>,
+}
+
+impl From<ApiKeyRow> for ApiKey {
+    fn from(row: ApiKeyRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            prefix: row.prefix,
+            scopes: scopes_from_names(row.scopes),
+            created_at: row.created_at,
+            last_used_at: row.last_used_at,
+            revoked_at: row.revoked_at,
+        }
+    }
+}
+
+// End: -- ApiKey Types
+
+const API_KEY_COLUMNS: &str = "id, name, prefix, scopes, created_at, last_used_at, revoked_at";
+
+// CRUD Implementation
+impl ModelManager {
+    #[instrument(skip_all)]
+    pub async fn create_api_key(
+        &self,
+        ctx: Ctx,
+        api_key_fc: ApiKeyForCreate,
+    ) -> Result<ApiKeyCreated> {
+        if api_key_fc.scopes.is_empty() {
+            return Err(Error::ApiKeyCreateFailNoScopes);
+        }
+
+        let (prefix, key) = generate_api_key();
+        let scopes: Vec<String> = scopes_to_names(&api_key_fc.scopes);
+
+        let row: ApiKeyRow = sqlx::query_as(&format!(
+            "INSERT INTO api_key (user_id, name, prefix, key_hash, scopes)
+             VALUES ($1, $2, $3, $4, $5) RETURNING {API_KEY_COLUMNS}"
+        ))
+        .bind(ctx.user_id() as i64)
+        .bind(api_key_fc.name)
+        .bind(prefix)
+        .bind(hash_api_key(&key))
+        .bind(scopes)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(ApiKeyCreated {
+            key,
+            api_key: row.into(),
+        })
+    }
+
+    #[instrument(skip_all)]
+    pub async fn list_api_keys(&self, ctx: Ctx) -> Result<Vec<ApiKey>> {
+        let rows: Vec<ApiKeyRow> = sqlx::query_as(&format!(
+            "SELECT {API_KEY_COLUMNS} FROM api_key WHERE user_id = $1 ORDER BY id"
+        ))
+        .bind(ctx.user_id() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows.into_iter().map(ApiKey::from).collect())
+    }
+
+    #[instrument(skip_all)]
+    pub async fn revoke_api_key(&self, ctx: Ctx, id: i64) -> Result<ApiKey> {
+        let row: Option<ApiKeyRow> = sqlx::query_as(&format!(
+            "UPDATE api_key SET revoked_at = now()
+             WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+             RETURNING {API_KEY_COLUMNS}"
+        ))
+        .bind(id)
+        .bind(ctx.user_id() as i64)
+        .fetch_optional(&self.db)
+        .await?;
+
+        row.map(ApiKey::from)
+            .ok_or(Error::ApiKeyRevokeFailIdNotFound { id })
+    }
+
+    /// Resolve a plain api key into the Ctx of its owner.
+    /// Unknown and revoked keys both fail with `AuthFailApiKeyInvalid`.
+    #[instrument(skip_all)]
+    pub async fn resolve_api_key(&self, key: &str) -> Result<Ctx> {
+        let row: Option<(i64, String, Vec<String>)> = sqlx::query_as(
+            r#"UPDATE api_key k SET last_used_at = now()
+               FROM "user" u
+               WHERE k.key_hash = $1 AND k.revoked_at IS NULL AND u.id = k.user_id
+               RETURNING k.user_id, u.role, k.scopes"#,
+        )
+        .bind(hash_api_key(key))
+        .fetch_optional(&self.db)
+        .await?;
+
+        let (user_id, role, scopes) = row.ok_or(Error::AuthFailApiKeyInvalid)?;
+        let role = Role::from_db(&role).ok_or(Error::AuthFailUnknownRole(role))?;
+
+        Ok(Ctx::new_api_key(user_id as u64, role, scopes_from_names(scopes)))
+    }
+}
+
+// region:    --- Key helpers
+
+/// Returns (prefix, key) where the key is `fdq_<prefix>_<secret>`.
+fn generate_api_key() -> (String, String) {
+    let mut rng = rand::thread_rng();
+
+    let id: String = (&mut rng)
+        .sample_iter(&Alphanumeric)
+        .take(8)
+        .map(char::from)
+        .collect();
+    let mut secret = [0u8; 32];
+    rng.fill_bytes(&mut secret);
+
+    let prefix = format!("{API_KEY_PREFIX}{id}");
+    let key = format!("{prefix}_{}", URL_SAFE_NO_PAD.encode(secret));
+
+    (prefix, key)
+}
+
+fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+fn scopes_to_names(scopes: &[Scope]) -> Vec<String> {
+    scopes
+        .iter()
+        .filter_map(|s| serde_json::to_value(s).ok())
+        .filter_map(|v| v.as_str().map(String::from))
+        .collect()
+}
+
+// Unknown names (e.g., a scope removed from the code) are dropped.
+fn scopes_from_names(names: Vec<String>) -> Vec<Scope> {
+    names
+        .into_iter()
+        .filter_map(|name| serde_json::from_value(serde_json::Value::String(name)).ok())
+        .collect()
+}
+
+// endregion: --- Key helpers
@@ -0,0 +1,98 @@
+//! Access grants from a client to a dietitian.
+//! A granted dietitian can read the client's data (e.g., meal logs).
+
+use serde::Serialize;
+use sqlx::FromRow;
+use time::OffsetDateTime;
+
+use crate::{ctx::{Ctx, Role}, error::{Error, Result}};
+use crate::model::model::ModelManager;
+use tracing::instrument;
+
+// -- DietitianAccess Types
+
+#[derive(Clone, Debug, Serialize, FromRow)]
+pub struct DietitianAccess {
+    pub client_id: i64,
+    pub dietitian_id: i64,
+    #[serde(with = "time::serde::rfc3339")]
+    pub granted_at: OffsetDateTime,
+}
+
+// End: -- DietitianAccess Types
+
+impl ModelManager {
+    /// The ctx user (client) grants `dietitian_id` access to their data.
+    #[instrument(skip_all)]
+    pub async fn grant_dietitian_access(
+        &self,
+        ctx: Ctx,
+        dietitian_id: i64,
+    ) -> Result<DietitianAccess> {
+        let role: Option<(String,)> = sqlx::query_as(r#"SELECT role FROM "user" WHERE id = $1"#)
+            .bind(dietitian_id)
+            .fetch_optional(&self.db)
+            .await?;
+        if role.and_then(|(r,)| Role::from_db(&r)) != Some(Role::Dietitian) {
+            return Err(Error::AccessGrantFailNotDietitian { dietitian_id });
+        }
+
+        let access = sqlx::query_as(
+            "INSERT INTO dietitian_access (client_id, dietitian_id) VALUES ($1, $2)
+             ON CONFLICT (client_id, dietitian_id) DO UPDATE SET granted_at = dietitian_access.granted_at
+             RETURNING client_id, dietitian_id, granted_at",
+        )
+        .bind(ctx.user_id() as i64)
+        .bind(dietitian_id)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(access)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn revoke_dietitian_access(
+        &self,
+        ctx: Ctx,
+        dietitian_id: i64,
+    ) -> Result<DietitianAccess> {
+        let access: Option<DietitianAccess> = sqlx::query_as(
+            "DELETE FROM dietitian_access WHERE client_id = $1 AND dietitian_id = $2
+             RETURNING client_id, dietitian_id, granted_at",
+        )
+        .bind(ctx.user_id() as i64)
+        .bind(dietitian_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        access.ok_or(Error::AccessRevokeFailNotFound { dietitian_id })
+    }
+
+    /// Dietitians the ctx user granted access to.
+    #[instrument(skip_all)]
+    pub async fn list_dietitian_grants(&self, ctx: Ctx) -> Result<Vec<DietitianAccess>> {
+        let grants = sqlx::query_as(
+            "SELECT client_id, dietitian_id, granted_at FROM dietitian_access
+             WHERE client_id = $1 ORDER BY granted_at",
+        )
+        .bind(ctx.user_id() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(grants)
+    }
+
+    /// Clients who granted the ctx user (dietitian) access.
+    #[instrument(skip_all)]
+    pub async fn list_dietitian_clients(&self, ctx: Ctx) -> Result<Vec<DietitianAccess>> {
+        let clients = sqlx::query_as(
+            "SELECT client_id, dietitian_id, granted_at FROM dietitian_access
+             WHERE dietitian_id = $1 ORDER BY granted_at",
+        )
+        .bind(ctx.user_id() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(clients)
+    }
+}
@@ -0,0 +1,172 @@
+//! Food reference, with known nutrients per 100 g, imported from a dataset (see `import-foods`).
+//! Searched by name and aliases with trigram similarity, so typos and partial words still match.
+
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::error::{Error, Result};
+use crate::model::model::ModelManager;
+use tracing::instrument;
+
+/// Search results are capped to this.
+pub const FOOD_SEARCH_MAX_LIMIT: i64 = 50;
+
+// -- Food Types
+
+/// Nutrients are per 100 g, None when not in the dataset.
+#[derive(Clone, Debug, Serialize, FromRow)]
+pub struct Food {
+    pub id: i64,
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub source: String,
+    pub source_id: String,
+    pub calories: Option<f32>,
+    pub protein_g: Option<f32>,
+    pub fat_g: Option<f32>,
+    pub carbohydrates_g: Option<f32>,
+    pub fiber_g: Option<f32>,
+    pub sugar_g: Option<f32>,
+    pub sodium_mg: Option<f32>,
+}
+
+/// A search or match result, `score` is 0 to 1 (see `search_foods` and `match_food`).
+#[derive(Clone, Debug, Serialize, FromRow)]
+pub struct FoodMatch {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub food: Food,
+    pub score: f32,
+}
+
+/// One food of an imported dataset, inserted or updated by (source, source_id).
+#[derive(Debug, Default)]
+pub struct FoodForImport {
+    pub source_id: String,
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub calories: Option<f32>,
+    pub protein_g: Option<f32>,
+    pub fat_g: Option<f32>,
+    pub carbohydrates_g: Option<f32>,
+    pub fiber_g: Option<f32>,
+    pub sugar_g: Option<f32>,
+    pub sodium_mg: Option<f32>,
+}
+
+// End: -- Food Types
+
+const FOOD_COLUMNS: &str = "id, name, aliases, source, source_id, \
+    calories, protein_g, fat_g, carbohydrates_g, fiber_g, sugar_g, sodium_mg";
+
+impl ModelManager {
+    /// Foods matching `query` (name or alias, fuzzy), best first.
+    #[instrument(skip_all)]
+    pub async fn search_foods(&self, query: &str, limit: i64) -> Result<Vec<FoodMatch>> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Err(Error::FoodSearchFailQueryBlank);
+        }
+
+        // `<%` is the indexed word similarity operator (pg_trgm.word_similarity_threshold).
+        // On ties, the names starting with the query, then the shorter, more generic names.
+        let foods = sqlx::query_as(&format!(
+            "SELECT {FOOD_COLUMNS}, word_similarity($1, food_search_text(name, aliases)) AS score
+             FROM food
+             WHERE $1 <% food_search_text(name, aliases)
+             ORDER BY score DESC, starts_with(lower(name), $1) DESC,
+                similarity($1, food_search_text(name, aliases)) DESC, id
+             LIMIT $2"
+        ))
+        .bind(query)
+        .bind(limit.clamp(1, FOOD_SEARCH_MAX_LIMIT))
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(foods)
+    }
+
+    /// The food closest to `name` as a whole (not only containing it, so "apple pie" is not "apple"),
+    /// the score is the trigram similarity to its name or best alias.
+    #[instrument(skip_all)]
+    pub async fn match_food(&self, name: &str) -> Result<Option<FoodMatch>> {
+        let name = name.trim().to_lowercase();
+
+        // `<%` narrows the candidates with the index, a score above its threshold implies it.
+        let food = sqlx::query_as(&format!(
+            "SELECT * FROM (
+                SELECT {FOOD_COLUMNS}, greatest(
+                    similarity($1, lower(name)),
+                    (SELECT max(similarity($1, alias)) FROM unnest(aliases) AS alias)
+                ) AS score
+                FROM food
+                WHERE $1 <% food_search_text(name, aliases)
+             ) AS candidate
+             ORDER BY score DESC, id
+             LIMIT 1"
+        ))
+        .bind(name)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(food)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn get_food(&self, id: i64) -> Result<Food> {
+        let food: Option<Food> = sqlx::query_as(&format!("SELECT {FOOD_COLUMNS} FROM food WHERE id = $1"))
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await?;
+
+        food.ok_or(Error::FoodNotFound { id })
+    }
+
+    /// The foods of `ids` which exist, in no particular order.
+    #[instrument(skip_all)]
+    pub async fn get_foods(&self, ids: &[i64]) -> Result<Vec<Food>> {
+        let foods = sqlx::query_as(&format!("SELECT {FOOD_COLUMNS} FROM food WHERE id = ANY($1)"))
+            .bind(ids)
+            .fetch_all(&self.db)
+            .await?;
+
+        Ok(foods)
+    }
+
+    /// Inserts or updates the foods of `source`, in one transaction. Returns the count.
+    #[instrument(skip_all)]
+    pub async fn import_foods(&self, source: &str, foods: &[FoodForImport]) -> Result<u64> {
+        let mut tx = self.db.begin().await?;
+
+        let mut count = 0;
+        for food in foods {
+            count += sqlx::query(
+                "INSERT INTO food (source, source_id, name, aliases,
+                    calories, protein_g, fat_g, carbohydrates_g, fiber_g, sugar_g, sodium_mg)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                 ON CONFLICT (source, source_id) DO UPDATE SET
+                    name = EXCLUDED.name, aliases = EXCLUDED.aliases,
+                    calories = EXCLUDED.calories, protein_g = EXCLUDED.protein_g, fat_g = EXCLUDED.fat_g,
+                    carbohydrates_g = EXCLUDED.carbohydrates_g, fiber_g = EXCLUDED.fiber_g,
+                    sugar_g = EXCLUDED.sugar_g, sodium_mg = EXCLUDED.sodium_mg, imported_at = now()",
+            )
+            .bind(source)
+            .bind(&food.source_id)
+            .bind(&food.name)
+            .bind(&food.aliases)
+            .bind(food.calories)
+            .bind(food.protein_g)
+            .bind(food.fat_g)
+            .bind(food.carbohydrates_g)
+            .bind(food.fiber_g)
+            .bind(food.sugar_g)
+            .bind(food.sodium_mg)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        }
+        tx.commit().await?;
+
+        Ok(count)
+    }
+}
@@ -0,0 +1,115 @@
+//! Meal log: what a user ate, e.g., servings of a recipe.
+//! Readable by the user, and by the dietitians the user granted access to.
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+
+use crate::ctx::Ctx;
+use crate::error::{Error, Result};
+use crate::model::model::ModelManager;
+use crate::model::recipe::Nutrients;
+use tracing::instrument;
+
+/// Meal lists are capped to this.
+pub const MEAL_LIST_MAX_LIMIT: i64 = 200;
+
+// -- MealLog Types
+
+/// The nutrients are for the servings eaten, as computed when logged.
+#[derive(Clone, Debug, Serialize, FromRow)]
+pub struct MealLog {
+    pub id: i64,
+    pub user_id: i64,
+    pub recipe_id: Option<i64>, // None once the recipe is deleted
+    pub name: String,
+    pub servings: f32,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub nutrients: Nutrients,
+    #[serde(with = "time::serde::rfc3339")]
+    pub eaten_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+/// One serving, eaten now, when not given.
+#[derive(Deserialize)]
+pub struct MealForLog {
+    pub servings: Option<f32>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub eaten_at: Option<OffsetDateTime>,
+}
+
+// End: -- MealLog Types
+
+const MEAL_LOG_COLUMNS: &str = "id, user_id, recipe_id, name, servings, \
+    calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg, eaten_at, created_at";
+
+impl ModelManager {
+    /// Logs servings of one of the ctx user's recipes, or of a shared one.
+    #[instrument(skip_all)]
+    pub async fn log_recipe_meal(&self, ctx: Ctx, recipe_id: i64, meal_fl: MealForLog) -> Result<MealLog> {
+        let recipe = self.get_recipe(ctx.clone(), recipe_id).await?;
+        let servings = meal_fl.servings.unwrap_or(1.);
+        let nutrients = recipe.per_serving.scaled(servings);
+
+        let meal = sqlx::query_as(&format!(
+            "INSERT INTO meal_log (user_id, recipe_id, name, servings,
+                calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg, eaten_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, coalesce($11, now()))
+             RETURNING {MEAL_LOG_COLUMNS}"
+        ))
+        .bind(ctx.user_id() as i64)
+        .bind(recipe.id)
+        .bind(&recipe.name)
+        .bind(servings)
+        .bind(nutrients.calories)
+        .bind(nutrients.protein_g)
+        .bind(nutrients.fat_g)
+        .bind(nutrients.carbohydrates_g)
+        .bind(nutrients.sugar_g)
+        .bind(nutrients.sodium_mg)
+        .bind(meal_fl.eaten_at)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(meal)
+    }
+
+    /// The ctx user's meals, latest first.
+    #[instrument(skip_all)]
+    pub async fn list_meals(&self, ctx: Ctx, limit: i64) -> Result<Vec<MealLog>> {
+        self.meals_of(ctx.user_id() as i64, limit).await
+    }
+
+    /// The meals of a client who granted the ctx user (dietitian) access, latest first.
+    #[instrument(skip_all)]
+    pub async fn list_client_meals(&self, ctx: Ctx, client_id: i64, limit: i64) -> Result<Vec<MealLog>> {
+        let (granted,): (bool,) = sqlx::query_as(
+            "SELECT EXISTS (SELECT 1 FROM dietitian_access WHERE client_id = $1 AND dietitian_id = $2)",
+        )
+        .bind(client_id)
+        .bind(ctx.user_id() as i64)
+        .fetch_one(&self.db)
+        .await?;
+        if !granted {
+            return Err(Error::MealsReadFailNoAccess { client_id });
+        }
+
+        self.meals_of(client_id, limit).await
+    }
+
+    async fn meals_of(&self, user_id: i64, limit: i64) -> Result<Vec<MealLog>> {
+        let meals = sqlx::query_as(&format!(
+            "SELECT {MEAL_LOG_COLUMNS} FROM meal_log WHERE user_id = $1
+             ORDER BY eaten_at DESC, id DESC LIMIT $2"
+        ))
+        .bind(user_id)
+        .bind(limit.clamp(1, MEAL_LIST_MAX_LIMIT))
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(meals)
+    }
+}
@@ -0,0 +1,289 @@
+//! Versioned schema migrations, embedded in the binary.
+//! - `sql/migrations/NNNN_name.up.sql` (and `.down.sql`), listed in `MIGRATIONS`.
+//! - Applied ones are recorded in `schema_migrations` with the sha256 of their up sql,
+//!   an applied migration which was edited since is refused, add a new one instead.
+//! - Each migration runs in its own transaction, under an advisory lock, so that
+//!   instances starting together do not apply it twice.
+
+use sha2::{Digest, Sha256};
+use sqlx::{Postgres, Transaction};
+use time::OffsetDateTime;
+use tracing::{info, instrument};
+
+use crate::error::{Error, Result};
+use crate::model::model::ModelManager;
+use crate::model::sql_split::split_sql;
+use crate::model::store::Db;
+
+// Advisory lock key, "foodeq" in hex, only needs to be unique in the db.
+const LOCK_KEY: i64 = 0x666f_6f64_6571;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    up: &'static str,
+    down: Option<&'static str>,
+}
+
+// In version order, append the new ones.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "base_schema",
+        up: include_str!("../../sql/migrations/0001_base_schema.up.sql"),
+        down: Some(include_str!("../../sql/migrations/0001_base_schema.down.sql")),
+    },
+    Migration {
+        version: 2,
+        name: "foods",
+        up: include_str!("../../sql/migrations/0002_foods.up.sql"),
+        down: Some(include_str!("../../sql/migrations/0002_foods.down.sql")),
+    },
+    Migration {
+        version: 3,
+        name: "products",
+        up: include_str!("../../sql/migrations/0003_products.up.sql"),
+        down: Some(include_str!("../../sql/migrations/0003_products.down.sql")),
+    },
+    Migration {
+        version: 4,
+        name: "recipes",
+        up: include_str!("../../sql/migrations/0004_recipes.up.sql"),
+        down: Some(include_str!("../../sql/migrations/0004_recipes.down.sql")),
+    },
+    Migration {
+        version: 5,
+        name: "analyses",
+        up: include_str!("../../sql/migrations/0005_analyses.up.sql"),
+        down: Some(include_str!("../../sql/migrations/0005_analyses.down.sql")),
+    },
+    Migration {
+        version: 6,
+        name: "api_key_user_fk",
+        up: include_str!("../../sql/migrations/0006_api_key_user_fk.up.sql"),
+        down: Some(include_str!("../../sql/migrations/0006_api_key_user_fk.down.sql")),
+    },
+];
+
+impl Migration {
+    fn checksum(&self) -> String {
+        format!("{:x}", Sha256::digest(self.up.as_bytes()))
+    }
+
+    /// The file name, for the errors, e.g., `0001_base_schema.up.sql`.
+    fn file(&self, direction: &str) -> String {
+        format!("{:04}_{}.{direction}.sql", self.version, self.name)
+    }
+}
+
+#[derive(Debug)]
+pub enum MigrationState {
+    Applied { applied_at: OffsetDateTime },
+    Pending,
+    // Applied, but the embedded up sql changed since.
+    ChecksumMismatch,
+    // Applied by a newer binary.
+    Unknown,
+}
+
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub state: MigrationState,
+}
+
+struct AppliedMigration {
+    version: i64,
+    name: String,
+    checksum: String,
+    applied_at: OffsetDateTime,
+}
+
+impl ModelManager {
+    /// Embedded and applied migrations, by version.
+    #[instrument(skip_all)]
+    pub async fn migrations_status(&self) -> Result<Vec<MigrationStatus>> {
+        migrations_status(&self.db).await
+    }
+
+    /// Apply the pending migrations, returns their versions.
+    #[instrument(skip_all)]
+    pub async fn migrate_up(&self) -> Result<Vec<i64>> {
+        migrate_up(&self.db).await
+    }
+
+    /// Revert the last `steps` applied migrations, returns their versions.
+    #[instrument(skip_all)]
+    pub async fn migrate_down(&self, steps: usize) -> Result<Vec<i64>> {
+        migrate_down(&self.db, steps).await
+    }
+}
+
+// Free functions, for the dev db init, which has its own pool.
+
+pub async fn migrations_status(db: &Db) -> Result<Vec<MigrationStatus>> {
+    ensure_migrations_table(db).await?;
+    let applied = applied_migrations(db).await?;
+
+    let mut statuses: Vec<MigrationStatus> = MIGRATIONS
+        .iter()
+        .map(|m| {
+            let state = match applied.iter().find(|a| a.version == m.version) {
+                Some(a) if a.checksum != m.checksum() => MigrationState::ChecksumMismatch,
+                Some(a) => MigrationState::Applied { applied_at: a.applied_at },
+                None => MigrationState::Pending,
+            };
+            MigrationStatus { version: m.version, name: m.name.to_string(), state }
+        })
+        .collect();
+    statuses.extend(
+        applied
+            .into_iter()
+            .filter(|a| !MIGRATIONS.iter().any(|m| m.version == a.version))
+            .map(|a| MigrationStatus { version: a.version, name: a.name, state: MigrationState::Unknown }),
+    );
+    statuses.sort_by_key(|s| s.version);
+
+    Ok(statuses)
+}
+
+pub async fn migrate_up(db: &Db) -> Result<Vec<i64>> {
+    let statuses = migrations_status(db).await?;
+    if let Some(status) = statuses.iter().find(|s| matches!(s.state, MigrationState::ChecksumMismatch)) {
+        return Err(Error::MigrationChecksumMismatch { version: status.version, name: status.name.clone() });
+    }
+
+    let mut applied_versions = Vec::new();
+    for status in statuses.iter().filter(|s| matches!(s.state, MigrationState::Pending)) {
+        let Some(migration) = MIGRATIONS.iter().find(|m| m.version == status.version) else {
+            continue;
+        };
+
+        let mut tx = locked_tx(db).await?;
+        // Applied by another instance while waiting for the lock.
+        if is_applied(&mut tx, migration.version).await? {
+            continue;
+        }
+        execute_sql(&mut tx, migration.file("up"), migration.up).await?;
+        sqlx::query("INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(migration.checksum())
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+
+        info!("{:<12} - applied {:04}_{}", "MIGRATION", migration.version, migration.name);
+        applied_versions.push(migration.version);
+    }
+
+    Ok(applied_versions)
+}
+
+pub async fn migrate_down(db: &Db, steps: usize) -> Result<Vec<i64>> {
+    ensure_migrations_table(db).await?;
+    let mut applied = applied_migrations(db).await?;
+    applied.reverse();
+
+    let mut reverted_versions = Vec::new();
+    for applied in applied.iter().take(steps) {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.version == applied.version)
+            .ok_or(Error::MigrationUnknown { version: applied.version })?;
+        let down = migration
+            .down
+            .ok_or(Error::MigrationNoDown { version: migration.version })?;
+
+        let mut tx = locked_tx(db).await?;
+        // Reverted by another instance while waiting for the lock.
+        if !is_applied(&mut tx, migration.version).await? {
+            continue;
+        }
+        execute_sql(&mut tx, migration.file("down"), down).await?;
+        sqlx::query("DELETE FROM schema_migrations WHERE version = $1")
+            .bind(migration.version)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+
+        info!("{:<12} - reverted {:04}_{}", "MIGRATION", migration.version, migration.name);
+        reverted_versions.push(migration.version);
+    }
+
+    Ok(reverted_versions)
+}
+
+async fn ensure_migrations_table(db: &Db) -> Result<()> {
+    let mut tx = locked_tx(db).await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version BIGINT PRIMARY KEY,
+            name varchar(256) NOT NULL,
+            checksum char(64) NOT NULL,
+            applied_at timestamptz NOT NULL DEFAULT now()
+        )",
+    )
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn applied_migrations(db: &Db) -> Result<Vec<AppliedMigration>> {
+    let rows: Vec<(i64, String, String, OffsetDateTime)> = sqlx::query_as(
+        "SELECT version, name, checksum, applied_at FROM schema_migrations ORDER BY version",
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(version, name, checksum, applied_at)| AppliedMigration { version, name, checksum, applied_at })
+        .collect())
+}
+
+/// A transaction holding the migrations lock, released on commit or rollback.
+async fn locked_tx(db: &Db) -> Result<Transaction<'static, Postgres>> {
+    let mut tx = db.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(LOCK_KEY)
+        .execute(&mut tx)
+        .await?;
+
+    Ok(tx)
+}
+
+async fn is_applied(tx: &mut Transaction<'static, Postgres>, version: i64) -> Result<bool> {
+    let (applied,): (bool,) =
+        sqlx::query_as("SELECT EXISTS (SELECT 1 FROM schema_migrations WHERE version = $1)")
+            .bind(version)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    Ok(applied)
+}
+
+async fn execute_sql(tx: &mut Transaction<'static, Postgres>, file: String, sql: &str) -> Result<()> {
+    let statements = split_sql(sql).map_err(|ex| Error::SqlSplitFail {
+        file: file.clone(),
+        line: ex.line,
+        cause: ex.cause.to_string(),
+    })?;
+
+    for (i, statement) in statements.into_iter().enumerate() {
+        sqlx::query(statement.sql)
+            .execute(&mut *tx)
+            .await
+            .map_err(|ex| Error::MigrationFail {
+                file: file.clone(),
+                statement: i + 1,
+                line: statement.line,
+                cause: ex.to_string(),
+            })?;
+    }
+
+    Ok(())
+}
@@ -1 +1,15 @@
-pub mod model;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod model;
+pub mod store;
+pub mod migrations;
+pub mod sql_split;
+pub mod api_key;
+pub mod user;
+pub mod dietitian_access;
+pub mod analysis_quota;
+pub mod user_identity;
+pub mod food;
+pub mod product;
+pub mod recipe;
+pub mod meal_log;
+pub mod analysis;
@@ -1,7 +1,8 @@
 //! Simplistic model layer
-//! (with mock-store layer)
+//! (with mock-store layer for tickets, db for the rest)
 
-use crate::{ctx::Ctx, error::{Error, Result}};
+use crate::{config, ctx::{Ctx, Permission}, error::{Error, Result}};
+use crate::model::store::{new_db_pool, Db};
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
 
@@ -27,6 +28,7 @@ pub struct TicketForCreate {
 pub struct ModelManager {
     // FIXME: Use a real database connection or ORM in production.
     tickets_store: Arc<Mutex<Vec<Option<Ticket>>>>, 
+    pub(in crate::model) db: Db, // Pool is an Arc internally, cheap to clone
 }
 
 // Constructor
@@ -34,8 +36,11 @@ impl ModelManager {
     // Control the signature of the constructor early on,
     // so that we can swap the implementation later.
     pub async fn new() -> Result<Self> {
+        let db = new_db_pool(config().DB_URL.expose()).await?;
+
         Ok(Self {
             tickets_store: Arc::default(),
+            db,
         })
     }
 }
@@ -63,6 +68,22 @@ impl ModelManager {
     }
 
     pub async fn list_tickets(
+        &self,
+        ctx: Ctx
+    ) -> Result<Vec<Ticket>> {
+        let store = self.tickets_store.lock().unwrap();
+        
+        // Filter out None values and other users' tickets
+        let tickets: Vec<Ticket> = store.iter()
+            .filter_map(|ticket| ticket.clone())
+            .filter(|ticket| ticket.cid == ctx.user_id())
+            .collect();
+
+        Ok(tickets)
+    }
+
+    /// Tickets of every user (the route requires `Permission::TicketsListAll`).
+    pub async fn list_all_tickets(
         &self,
         _ctx: Ctx
     ) -> Result<Vec<Ticket>> {
@@ -78,15 +99,21 @@ impl ModelManager {
 
     pub async fn delete_ticket(
         &self,
-        _ctx: Ctx,
+        ctx: Ctx,
         id: u64
     ) -> Result<Ticket> {
         let mut store = self.tickets_store.lock().unwrap();
         
-        let ticket = store.get_mut(id as usize)
-            .and_then(|t| t.take());
+        let slot = store.get_mut(id as usize)
+            .filter(|t| t.is_some())
+            .ok_or(Error::TicketDeleteFailIdNotFound { id })?;
+
+        // Only the creator, or a user allowed to delete any ticket.
+        if slot.as_ref().is_some_and(|t| t.cid != ctx.user_id()) {
+            ctx.require_permission(Permission::TicketsDeleteAny)?;
+        }
 
-        ticket.ok_or(Error::TicketDeleteFailIdNotFound { id })
+        slot.take().ok_or(Error::TicketDeleteFailIdNotFound { id })
     }
 }
 
@@ -0,0 +1,168 @@
+//! Packaged products, looked up by barcode, imported from an Open Food Facts dump (see `import-products`).
+//! Barcodes are EAN-13, or UPC-A stored as an EAN-13 with a leading 0, as Open Food Facts does.
+
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::error::{Error, Result};
+use crate::model::model::ModelManager;
+use tracing::instrument;
+
+// -- Product Types
+
+/// Nutrients are per 100 g, None when not in the dump.
+#[derive(Clone, Debug, Serialize, FromRow)]
+pub struct Product {
+    pub id: i64,
+    pub barcode: String,
+    pub name: String,
+    pub brand: Option<String>,
+    pub serving_size: Option<String>,
+    pub serving_g: Option<f32>,
+    pub calories: Option<f32>,
+    pub protein_g: Option<f32>,
+    pub fat_g: Option<f32>,
+    pub carbohydrates_g: Option<f32>,
+    pub sugar_g: Option<f32>,
+    pub sodium_mg: Option<f32>,
+}
+
+/// One product of an imported dump, inserted or updated by barcode (already normalized).
+#[derive(Debug, Default)]
+pub struct ProductForImport {
+    pub barcode: String,
+    pub name: String,
+    pub brand: Option<String>,
+    pub serving_size: Option<String>,
+    pub serving_g: Option<f32>,
+    pub calories: Option<f32>,
+    pub protein_g: Option<f32>,
+    pub fat_g: Option<f32>,
+    pub carbohydrates_g: Option<f32>,
+    pub sugar_g: Option<f32>,
+    pub sodium_mg: Option<f32>,
+}
+
+// End: -- Product Types
+
+const PRODUCT_COLUMNS: &str = "id, barcode, name, brand, serving_size, serving_g, \
+    calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg";
+
+impl ModelManager {
+    /// `barcode` is an EAN-13 or a UPC-A, with a valid check digit.
+    #[instrument(skip_all)]
+    pub async fn get_product_by_barcode(&self, barcode: &str) -> Result<Product> {
+        let barcode = normalize_barcode(barcode).ok_or_else(|| Error::BarcodeInvalid {
+            barcode: barcode.to_string(),
+        })?;
+
+        let product: Option<Product> =
+            sqlx::query_as(&format!("SELECT {PRODUCT_COLUMNS} FROM product WHERE barcode = $1"))
+                .bind(&barcode)
+                .fetch_optional(&self.db)
+                .await?;
+
+        product.ok_or(Error::ProductNotFound { barcode })
+    }
+
+    /// Inserts or updates a batch of products, in one statement. Returns the count.
+    #[instrument(skip_all)]
+    pub async fn import_products(&self, source: &str, products: &[ProductForImport]) -> Result<u64> {
+        // One array per column, unnested back into rows.
+        fn column<T: Clone>(products: &[ProductForImport], get: impl Fn(&ProductForImport) -> T) -> Vec<T> {
+            products.iter().map(get).collect()
+        }
+
+        let result = sqlx::query(
+            "INSERT INTO product (barcode, name, brand, serving_size, serving_g,
+                calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg, source)
+             SELECT *, $12 FROM unnest($1::text[], $2::text[], $3::text[], $4::text[], $5::real[],
+                $6::real[], $7::real[], $8::real[], $9::real[], $10::real[], $11::real[])
+             ON CONFLICT (barcode) DO UPDATE SET
+                name = EXCLUDED.name, brand = EXCLUDED.brand,
+                serving_size = EXCLUDED.serving_size, serving_g = EXCLUDED.serving_g,
+                calories = EXCLUDED.calories, protein_g = EXCLUDED.protein_g, fat_g = EXCLUDED.fat_g,
+                carbohydrates_g = EXCLUDED.carbohydrates_g, sugar_g = EXCLUDED.sugar_g,
+                sodium_mg = EXCLUDED.sodium_mg, source = EXCLUDED.source, imported_at = now()",
+        )
+        .bind(column(products, |p| p.barcode.clone()))
+        .bind(column(products, |p| p.name.clone()))
+        .bind(column(products, |p| p.brand.clone()))
+        .bind(column(products, |p| p.serving_size.clone()))
+        .bind(column(products, |p| p.serving_g))
+        .bind(column(products, |p| p.calories))
+        .bind(column(products, |p| p.protein_g))
+        .bind(column(products, |p| p.fat_g))
+        .bind(column(products, |p| p.carbohydrates_g))
+        .bind(column(products, |p| p.sugar_g))
+        .bind(column(products, |p| p.sodium_mg))
+        .bind(source)
+        .execute(&self.db)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// The EAN-13 of an EAN-13 or UPC-A barcode, None when not one, or when its check digit is wrong.
+pub fn normalize_barcode(barcode: &str) -> Option<String> {
+    let barcode = barcode.trim();
+    if !barcode.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let ean13 = match barcode.len() {
+        13 => barcode.to_string(),
+        12 => format!("0{barcode}"), // UPC-A
+        _ => return None,
+    };
+
+    // Weights 1 and 3, alternating from the left, the check digit completes the sum to a multiple of 10.
+    let digits: Vec<u32> = ean13.bytes().map(|b| u32::from(b - b'0')).collect();
+    let sum: u32 = digits[..12]
+        .iter()
+        .enumerate()
+        .map(|(i, digit)| if i % 2 == 0 { *digit } else { digit * 3 })
+        .sum();
+
+    ((10 - sum % 10) % 10 == digits[12]).then_some(ean13)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_barcode_ean13() {
+        assert_eq!(normalize_barcode("3017620422003").as_deref(), Some("3017620422003"));
+        assert_eq!(normalize_barcode(" 4006381333931\n").as_deref(), Some("4006381333931"));
+    }
+
+    #[test]
+    fn test_normalize_barcode_upc_a() {
+        assert_eq!(normalize_barcode("036000291452").as_deref(), Some("0036000291452"));
+    }
+
+    #[test]
+    fn test_normalize_barcode_wrong_check_digit() {
+        assert_eq!(normalize_barcode("3017620422004"), None);
+        assert_eq!(normalize_barcode("036000291453"), None);
+    }
+
+    #[test]
+    fn test_normalize_barcode_not_digits() {
+        assert_eq!(normalize_barcode("301762042200X"), None);
+        assert_eq!(normalize_barcode("3017-620422003"), None);
+        assert_eq!(normalize_barcode("３０１７６２０４２２００３"), None);
+    }
+
+    #[test]
+    fn test_normalize_barcode_wrong_length() {
+        assert_eq!(normalize_barcode(""), None);
+        assert_eq!(normalize_barcode("96385074"), None); // EAN-8
+        assert_eq!(normalize_barcode("03017620422003"), None); // GTIN-14
+    }
+}
+
+// endregion: --- Tests
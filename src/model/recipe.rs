@@ -0,0 +1,583 @@
+//! Recipes: ingredients (reference foods, or food items of the owner's analyses) with their grams,
+//! and a number of servings.
+//! The nutrients are computed when the recipe is saved, per ingredient and per serving.
+//! A recipe is only for its owner, unless shared, then the other users can also read, clone and log it.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use time::OffsetDateTime;
+
+use crate::ctx::Ctx;
+use crate::error::{Error, Result};
+use crate::model::food::Food;
+use crate::model::model::ModelManager;
+use crate::nutrition::FoodItem;
+use tracing::instrument;
+
+// -- Recipe Types
+
+/// Nutrients of an amount of food, e.g., an ingredient, a recipe serving, a logged meal.
+#[derive(Clone, Copy, Debug, Default, Serialize, FromRow)]
+pub struct Nutrients {
+    pub calories: f32,
+    pub protein_g: f32,
+    pub fat_g: f32,
+    pub carbohydrates_g: f32,
+    pub sugar_g: f32,
+    pub sodium_mg: f32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Recipe {
+    pub id: i64,
+    pub owner_id: i64,
+    pub name: String,
+    pub servings: f32,
+    pub shared: bool,
+    pub cloned_from: Option<i64>,
+    pub per_serving: Nutrients,
+    pub ingredients: Vec<RecipeIngredient>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+/// `food_id` is None for an analyzed food item. The nutrients are for the grams.
+#[derive(Clone, Debug, Serialize, FromRow)]
+pub struct RecipeIngredient {
+    pub food_id: Option<i64>,
+    pub name: String,
+    pub grams: f32,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub nutrients: Nutrients,
+}
+
+/// Also the body of an update, which replaces the recipe and its ingredients.
+#[derive(Deserialize)]
+pub struct RecipeForCreate {
+    pub name: String,
+    pub servings: f32,
+    #[serde(default)]
+    pub shared: bool,
+    pub ingredients: Vec<IngredientForCreate>,
+}
+
+/// Either a reference food (`food_id`), or a food item of one of the ctx user's stored analyses
+/// (`analyzed`), whose values are for its `weight_g`.
+#[derive(Deserialize)]
+pub struct IngredientForCreate {
+    pub food_id: Option<i64>,
+    pub analyzed: Option<AnalyzedItemRef>,
+    pub grams: f32,
+}
+
+/// The food at `item_index` in the `foods` of the analysis (as answered, not corrected).
+#[derive(Clone, Copy, Deserialize)]
+pub struct AnalyzedItemRef {
+    pub analysis_id: i64,
+    pub item_index: usize,
+}
+
+#[derive(FromRow)]
+struct RecipeRow {
+    id: i64,
+    owner_id: i64,
+    name: String,
+    servings: f32,
+    shared: bool,
+    cloned_from: Option<i64>,
+    #[sqlx(flatten)]
+    per_serving: Nutrients,
+    created_at: OffsetDateTime,
+    updated_at: OffsetDateTime,
+}
+
+#[derive(FromRow)]
+struct RecipeIngredientRow {
+    recipe_id: i64,
+    #[sqlx(flatten)]
+    ingredient: RecipeIngredient,
+}
+
+impl RecipeRow {
+    fn into_recipe(self, ingredients: Vec<RecipeIngredient>) -> Recipe {
+        Recipe {
+            id: self.id,
+            owner_id: self.owner_id,
+            name: self.name,
+            servings: self.servings,
+            shared: self.shared,
+            cloned_from: self.cloned_from,
+            per_serving: self.per_serving,
+            ingredients,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+// End: -- Recipe Types
+
+const RECIPE_COLUMNS: &str = "id, owner_id, name, servings, shared, cloned_from, \
+    calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg, created_at, updated_at";
+
+// CRUD Implementation
+impl ModelManager {
+    #[instrument(skip_all)]
+    pub async fn create_recipe(&self, ctx: Ctx, recipe_fc: RecipeForCreate) -> Result<Recipe> {
+        let ingredients = self.recipe_ingredients(&ctx, &recipe_fc.ingredients).await?;
+
+        let mut tx = self.db.begin().await?;
+        let recipe = insert_recipe(
+            &mut tx,
+            ctx.user_id() as i64,
+            &recipe_fc.name,
+            recipe_fc.servings,
+            recipe_fc.shared,
+            None,
+            ingredients,
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(recipe)
+    }
+
+    /// The ctx user's recipes, or with `shared`, the recipes shared by the other users.
+    #[instrument(skip_all)]
+    pub async fn list_recipes(&self, ctx: Ctx, shared: bool) -> Result<Vec<Recipe>> {
+        let filter = if shared { "shared AND owner_id <> $1" } else { "owner_id = $1" };
+        let rows: Vec<RecipeRow> = sqlx::query_as(&format!(
+            "SELECT {RECIPE_COLUMNS} FROM recipe WHERE {filter} ORDER BY id"
+        ))
+        .bind(ctx.user_id() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        self.with_ingredients(rows).await
+    }
+
+    /// One of the ctx user's recipes, or a shared one.
+    #[instrument(skip_all)]
+    pub async fn get_recipe(&self, ctx: Ctx, id: i64) -> Result<Recipe> {
+        let row: Option<RecipeRow> = sqlx::query_as(&format!(
+            "SELECT {RECIPE_COLUMNS} FROM recipe WHERE id = $1 AND (owner_id = $2 OR shared)"
+        ))
+        .bind(id)
+        .bind(ctx.user_id() as i64)
+        .fetch_optional(&self.db)
+        .await?;
+
+        let row = row.ok_or(Error::RecipeNotFound { id })?;
+        let mut recipes = self.with_ingredients(vec![row]).await?;
+
+        recipes.pop().ok_or(Error::RecipeNotFound { id })
+    }
+
+    /// Only the owner can update, a recipe shared by another user is not found.
+    #[instrument(skip_all)]
+    pub async fn update_recipe(&self, ctx: Ctx, id: i64, recipe_fu: RecipeForCreate) -> Result<Recipe> {
+        let ingredients = self.recipe_ingredients(&ctx, &recipe_fu.ingredients).await?;
+        let per_serving = per_serving(&ingredients, recipe_fu.servings);
+
+        let mut tx = self.db.begin().await?;
+        let row: Option<RecipeRow> = sqlx::query_as(&format!(
+            "UPDATE recipe SET name = $3, servings = $4, shared = $5,
+                calories = $6, protein_g = $7, fat_g = $8, carbohydrates_g = $9, sugar_g = $10, sodium_mg = $11,
+                updated_at = now()
+             WHERE id = $1 AND owner_id = $2
+             RETURNING {RECIPE_COLUMNS}"
+        ))
+        .bind(id)
+        .bind(ctx.user_id() as i64)
+        .bind(&recipe_fu.name)
+        .bind(recipe_fu.servings)
+        .bind(recipe_fu.shared)
+        .bind(per_serving.calories)
+        .bind(per_serving.protein_g)
+        .bind(per_serving.fat_g)
+        .bind(per_serving.carbohydrates_g)
+        .bind(per_serving.sugar_g)
+        .bind(per_serving.sodium_mg)
+        .fetch_optional(&mut tx)
+        .await?;
+        let row = row.ok_or(Error::RecipeNotFound { id })?;
+
+        sqlx::query("DELETE FROM recipe_ingredient WHERE recipe_id = $1")
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+        insert_ingredients(&mut tx, id, &ingredients).await?;
+        tx.commit().await?;
+
+        Ok(row.into_recipe(ingredients))
+    }
+
+    /// Only the owner can delete. The meals logged from it are kept.
+    #[instrument(skip_all)]
+    pub async fn delete_recipe(&self, ctx: Ctx, id: i64) -> Result<Recipe> {
+        let recipe = self.get_recipe(ctx.clone(), id).await?;
+        if recipe.owner_id != ctx.user_id() as i64 {
+            return Err(Error::RecipeNotFound { id });
+        }
+
+        let count = sqlx::query("DELETE FROM recipe WHERE id = $1 AND owner_id = $2")
+            .bind(id)
+            .bind(ctx.user_id() as i64)
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+        if count == 0 {
+            return Err(Error::RecipeNotFound { id });
+        }
+
+        Ok(recipe)
+    }
+
+    /// A copy of a shared (or own) recipe for the ctx user, not shared.
+    #[instrument(skip_all)]
+    pub async fn clone_recipe(&self, ctx: Ctx, id: i64) -> Result<Recipe> {
+        let recipe = self.get_recipe(ctx.clone(), id).await?;
+
+        let mut tx = self.db.begin().await?;
+        let clone = insert_recipe(
+            &mut tx,
+            ctx.user_id() as i64,
+            &recipe.name,
+            recipe.servings,
+            false,
+            Some(recipe.id),
+            recipe.ingredients,
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(clone)
+    }
+
+    /// The ingredients with their nutrients, from the reference foods, or the ctx user's analyzed items.
+    async fn recipe_ingredients(
+        &self,
+        ctx: &Ctx,
+        ingredients_fc: &[IngredientForCreate],
+    ) -> Result<Vec<RecipeIngredient>> {
+        let food_ids: Vec<i64> = ingredients_fc.iter().filter_map(|i| i.food_id).collect();
+        let foods: HashMap<i64, Food> = self
+            .get_foods(&food_ids)
+            .await?
+            .into_iter()
+            .map(|food| (food.id, food))
+            .collect();
+
+        let analysis_ids: Vec<i64> = ingredients_fc
+            .iter()
+            .filter_map(|i| i.analyzed.map(|analyzed| analyzed.analysis_id))
+            .collect();
+        let analyses: HashMap<i64, Vec<FoodItem>> = self
+            .get_analyses(ctx, &analysis_ids)
+            .await?
+            .into_iter()
+            .map(|analysis| (analysis.id, analysis.foods))
+            .collect();
+
+        ingredients_fc
+            .iter()
+            .enumerate()
+            .map(|(index, ingredient_fc)| {
+                let grams = ingredient_fc.grams;
+                match (ingredient_fc.food_id, &ingredient_fc.analyzed) {
+                    (Some(food_id), None) => {
+                        let food = foods.get(&food_id).ok_or(Error::RecipeFailFoodNotFound { food_id })?;
+                        Ok(RecipeIngredient {
+                            food_id: Some(food_id),
+                            name: food.name.clone(),
+                            grams,
+                            nutrients: food_nutrients(food).scaled(grams / 100.),
+                        })
+                    }
+                    (None, Some(AnalyzedItemRef { analysis_id, item_index })) => {
+                        let item = analyses
+                            .get(analysis_id)
+                            .and_then(|foods| foods.get(*item_index))
+                            .ok_or(Error::RecipeFailAnalyzedItemNotFound {
+                                analysis_id: *analysis_id,
+                                item_index: *item_index,
+                            })?;
+                        let weight_g = item
+                            .weight_g
+                            .filter(|weight_g| *weight_g > 0.)
+                            .ok_or(Error::RecipeFailIngredientInvalid { index })?;
+                        Ok(RecipeIngredient {
+                            food_id: None,
+                            name: item.name.trim().to_string(),
+                            grams,
+                            nutrients: item_nutrients(item).scaled(grams / weight_g),
+                        })
+                    }
+                    _ => Err(Error::RecipeFailIngredientInvalid { index }),
+                }
+            })
+            .collect()
+    }
+
+    async fn with_ingredients(&self, rows: Vec<RecipeRow>) -> Result<Vec<Recipe>> {
+        let ids: Vec<i64> = rows.iter().map(|row| row.id).collect();
+        let ingredient_rows: Vec<RecipeIngredientRow> = sqlx::query_as(
+            "SELECT recipe_id, food_id, name, grams,
+                calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg
+             FROM recipe_ingredient WHERE recipe_id = ANY($1) ORDER BY recipe_id, position",
+        )
+        .bind(&ids)
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut ingredients_by_recipe: HashMap<i64, Vec<RecipeIngredient>> = HashMap::new();
+        for row in ingredient_rows {
+            ingredients_by_recipe.entry(row.recipe_id).or_default().push(row.ingredient);
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let ingredients = ingredients_by_recipe.remove(&row.id).unwrap_or_default();
+                row.into_recipe(ingredients)
+            })
+            .collect())
+    }
+}
+
+impl Recipe {
+    /// The same recipe for `servings`, the ingredients scaled, the values per serving unchanged.
+    pub fn scaled(mut self, servings: f32) -> Self {
+        let factor = servings / self.servings;
+        for ingredient in &mut self.ingredients {
+            ingredient.grams = round1(ingredient.grams * factor);
+            ingredient.nutrients = ingredient.nutrients.scaled(factor);
+        }
+        self.servings = servings;
+        self
+    }
+}
+
+impl Nutrients {
+    /// Rounded to 0.1, as the analyses are.
+    pub fn scaled(&self, factor: f32) -> Self {
+        Self {
+            calories: round1(self.calories * factor),
+            protein_g: round1(self.protein_g * factor),
+            fat_g: round1(self.fat_g * factor),
+            carbohydrates_g: round1(self.carbohydrates_g * factor),
+            sugar_g: round1(self.sugar_g * factor),
+            sodium_mg: round1(self.sodium_mg * factor),
+        }
+    }
+}
+
+// region:    --- Recipe helpers
+
+async fn insert_recipe(
+    tx: &mut Transaction<'static, Postgres>,
+    owner_id: i64,
+    name: &str,
+    servings: f32,
+    shared: bool,
+    cloned_from: Option<i64>,
+    ingredients: Vec<RecipeIngredient>,
+) -> Result<Recipe> {
+    let per_serving = per_serving(&ingredients, servings);
+
+    let row: RecipeRow = sqlx::query_as(&format!(
+        "INSERT INTO recipe (owner_id, name, servings, shared, cloned_from,
+            calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+         RETURNING {RECIPE_COLUMNS}"
+    ))
+    .bind(owner_id)
+    .bind(name)
+    .bind(servings)
+    .bind(shared)
+    .bind(cloned_from)
+    .bind(per_serving.calories)
+    .bind(per_serving.protein_g)
+    .bind(per_serving.fat_g)
+    .bind(per_serving.carbohydrates_g)
+    .bind(per_serving.sugar_g)
+    .bind(per_serving.sodium_mg)
+    .fetch_one(&mut *tx)
+    .await?;
+    insert_ingredients(tx, row.id, &ingredients).await?;
+
+    Ok(row.into_recipe(ingredients))
+}
+
+// One array per column, unnested back into rows, in their order.
+async fn insert_ingredients(
+    tx: &mut Transaction<'static, Postgres>,
+    recipe_id: i64,
+    ingredients: &[RecipeIngredient],
+) -> Result<()> {
+    fn column<T>(ingredients: &[RecipeIngredient], get: impl Fn(&RecipeIngredient) -> T) -> Vec<T> {
+        ingredients.iter().map(get).collect()
+    }
+
+    sqlx::query(
+        "INSERT INTO recipe_ingredient (recipe_id, position, food_id, name, grams,
+            calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg)
+         SELECT $1, position - 1, food_id, name, grams,
+            calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg
+         FROM unnest($2::bigint[], $3::text[], $4::real[],
+            $5::real[], $6::real[], $7::real[], $8::real[], $9::real[], $10::real[])
+            WITH ORDINALITY AS i (food_id, name, grams,
+                calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg, position)",
+    )
+    .bind(recipe_id)
+    .bind(column(ingredients, |i| i.food_id))
+    .bind(column(ingredients, |i| i.name.clone()))
+    .bind(column(ingredients, |i| i.grams))
+    .bind(column(ingredients, |i| i.nutrients.calories))
+    .bind(column(ingredients, |i| i.nutrients.protein_g))
+    .bind(column(ingredients, |i| i.nutrients.fat_g))
+    .bind(column(ingredients, |i| i.nutrients.carbohydrates_g))
+    .bind(column(ingredients, |i| i.nutrients.sugar_g))
+    .bind(column(ingredients, |i| i.nutrients.sodium_mg))
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+fn per_serving(ingredients: &[RecipeIngredient], servings: f32) -> Nutrients {
+    let total = ingredients.iter().fold(Nutrients::default(), |total, ingredient| {
+        let n = &ingredient.nutrients;
+        Nutrients {
+            calories: total.calories + n.calories,
+            protein_g: total.protein_g + n.protein_g,
+            fat_g: total.fat_g + n.fat_g,
+            carbohydrates_g: total.carbohydrates_g + n.carbohydrates_g,
+            sugar_g: total.sugar_g + n.sugar_g,
+            sodium_mg: total.sodium_mg + n.sodium_mg,
+        }
+    });
+
+    total.scaled(1. / servings)
+}
+
+// Per 100 g, a nutrient not in the reference counts as 0.
+fn food_nutrients(food: &Food) -> Nutrients {
+    Nutrients {
+        calories: food.calories.unwrap_or(0.),
+        protein_g: food.protein_g.unwrap_or(0.),
+        fat_g: food.fat_g.unwrap_or(0.),
+        carbohydrates_g: food.carbohydrates_g.unwrap_or(0.),
+        sugar_g: food.sugar_g.unwrap_or(0.),
+        sodium_mg: food.sodium_mg.unwrap_or(0.),
+    }
+}
+
+// For the item weight, the AI estimate (not its reference values).
+fn item_nutrients(item: &FoodItem) -> Nutrients {
+    Nutrients {
+        calories: item.calories,
+        protein_g: item.protein_g,
+        fat_g: item.fat_g,
+        carbohydrates_g: item.carbohydrates_g,
+        sugar_g: item.sugar_g,
+        sodium_mg: item.sodium_mg,
+    }
+}
+
+fn round1(value: f32) -> f32 {
+    (value * 10.).round() / 10.
+}
+
+// endregion: --- Recipe helpers
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nutrients(calories: f32, protein_g: f32, sodium_mg: f32) -> Nutrients {
+        Nutrients {
+            calories,
+            protein_g,
+            sodium_mg,
+            ..Default::default()
+        }
+    }
+
+    fn ingredient(name: &str, grams: f32, nutrients: Nutrients) -> RecipeIngredient {
+        RecipeIngredient {
+            food_id: None,
+            name: name.to_string(),
+            grams,
+            nutrients,
+        }
+    }
+
+    fn recipe(servings: f32) -> Recipe {
+        let ingredients = vec![
+            ingredient("oats", 200., nutrients(758., 26.3, 4.)),
+            ingredient("milk", 500., nutrients(305., 16.5, 215.)),
+        ];
+        Recipe {
+            id: 1,
+            owner_id: 1,
+            name: "Porridge".to_string(),
+            servings,
+            shared: false,
+            cloned_from: None,
+            per_serving: per_serving(&ingredients, servings),
+            ingredients,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn test_per_serving_several_servings() {
+        let per_serving = recipe(4.).per_serving;
+
+        assert_eq!(per_serving.calories, 265.8); // (758 + 305) / 4, rounded
+        assert_eq!(per_serving.protein_g, 10.7);
+        assert_eq!(per_serving.sodium_mg, 54.8);
+        assert_eq!(per_serving.fat_g, 0.);
+    }
+
+    #[test]
+    fn test_per_serving_one_serving() {
+        let per_serving = recipe(1.).per_serving;
+
+        assert_eq!(per_serving.calories, 1063.);
+        assert_eq!(per_serving.protein_g, 42.8);
+    }
+
+    #[test]
+    fn test_recipe_scaled() {
+        let scaled = recipe(4.).scaled(6.);
+
+        assert_eq!(scaled.servings, 6.);
+        assert_eq!(scaled.ingredients[0].grams, 300.);
+        assert_eq!(scaled.ingredients[1].grams, 750.);
+        assert_eq!(scaled.ingredients[1].nutrients.calories, 457.5);
+        assert_eq!(scaled.ingredients[1].nutrients.sodium_mg, 322.5);
+        // The values per serving are unchanged.
+        assert_eq!(scaled.per_serving.calories, recipe(4.).per_serving.calories);
+    }
+
+    #[test]
+    fn test_nutrients_scaled_rounded() {
+        let scaled = nutrients(61., 0.3, 1.).scaled(1.5);
+
+        assert_eq!(scaled.calories, 91.5);
+        assert_eq!(scaled.protein_g, 0.5); // 0.45
+        assert_eq!(scaled.sodium_mg, 1.5);
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,295 @@
+//! Split of the sql files (migrations, dev seed) into statements, executed one at a time.
+//!
+//! A `;` ends a statement, except inside:
+//! - quoted strings `'it''s'`, escape strings `E'it\'s'`, quoted identifiers `"a""b"`,
+//! - line comments `-- ...` and (nested) block comments `/* ... */`,
+//! - dollar quoted strings `$$ ... $$` or `$body$ ... $body$`, e.g., function bodies and `DO` blocks.
+//!
+//! Function bodies must be dollar quoted (`BEGIN ATOMIC ... END` bodies are not supported).
+
+/// A statement, without the trailing `;`, and the line (1 based) where it starts.
+#[derive(Debug)]
+pub struct SqlStatement<'a> {
+    pub sql: &'a str,
+    pub line: usize,
+}
+
+/// Unterminated quote or comment, `line` is where it starts.
+#[derive(Debug)]
+pub struct SqlSplitError {
+    pub line: usize,
+    pub cause: &'static str,
+}
+
+pub fn split_sql(sql: &str) -> Result<Vec<SqlStatement<'_>>, SqlSplitError> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+
+    let mut i = 0;
+    let mut line = 1;
+    // Offset and line of the first token of the current statement, comments before it are skipped.
+    let mut start: Option<(usize, usize)> = None;
+
+    // Only ascii is matched, so the offsets are always on char boundaries.
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'\n', _) => {
+                line += 1;
+                i += 1;
+            }
+            (b, _) if b.is_ascii_whitespace() => i += 1,
+            (b'-', Some(b'-')) => {
+                i = find(bytes, i, b"\n").unwrap_or(bytes.len());
+            }
+            (b'/', Some(b'*')) => {
+                i = skip_block_comment(bytes, i, &mut line)?;
+            }
+            (b';', _) => {
+                if let Some((start_i, start_line)) = start.take() {
+                    statements.push(SqlStatement { sql: sql[start_i..i].trim_end(), line: start_line });
+                }
+                i += 1;
+            }
+            (b, _) => {
+                start.get_or_insert((i, line));
+                i = match b {
+                    b'\'' => skip_quoted(bytes, i, b'\'', is_escape_string(bytes, i), &mut line)?,
+                    b'"' => skip_quoted(bytes, i, b'"', false, &mut line)?,
+                    b'$' => match dollar_tag(bytes, i) {
+                        Some(tag) => skip_dollar_quoted(bytes, i, tag, &mut line)?,
+                        None => i + 1,
+                    },
+                    _ => i + 1,
+                };
+            }
+        }
+    }
+
+    // Last statement, without a trailing `;`.
+    if let Some((start_i, start_line)) = start {
+        statements.push(SqlStatement { sql: sql[start_i..].trim_end(), line: start_line });
+    }
+
+    Ok(statements)
+}
+
+// region:    --- Tokens
+
+/// Offset after the closing quote. `''` (or `""`) is an escaped quote,
+/// and with `backslash_escapes` (`E'...'`) so is `\'`.
+fn skip_quoted(
+    bytes: &[u8],
+    open: usize,
+    quote: u8,
+    backslash_escapes: bool,
+    line: &mut usize,
+) -> Result<usize, SqlSplitError> {
+    let open_line = *line;
+    let mut i = open + 1;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => *line += 1,
+            b'\\' if backslash_escapes => {
+                if bytes.get(i + 1) == Some(&b'\n') {
+                    *line += 1;
+                }
+                i += 1;
+            }
+            b if b == quote => {
+                if bytes.get(i + 1) == Some(&quote) {
+                    i += 1;
+                } else {
+                    return Ok(i + 1);
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+
+    let cause = if quote == b'"' { "unterminated quoted identifier" } else { "unterminated string" };
+    Err(SqlSplitError { line: open_line, cause })
+}
+
+/// Offset after the closing `*/`, block comments nest.
+fn skip_block_comment(bytes: &[u8], open: usize, line: &mut usize) -> Result<usize, SqlSplitError> {
+    let open_line = *line;
+    let mut depth = 0;
+    let mut i = open;
+
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'/', Some(b'*')) => {
+                depth += 1;
+                i += 2;
+            }
+            (b'*', Some(b'/')) => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            }
+            (b, _) => {
+                if b == b'\n' {
+                    *line += 1;
+                }
+                i += 1;
+            }
+        }
+    }
+
+    Err(SqlSplitError { line: open_line, cause: "unterminated block comment" })
+}
+
+/// Offset after the closing tag, e.g., `$body$`.
+fn skip_dollar_quoted(bytes: &[u8], open: usize, tag: &[u8], line: &mut usize) -> Result<usize, SqlSplitError> {
+    let open_line = *line;
+    let body_start = open + tag.len();
+
+    match find(bytes, body_start, tag) {
+        Some(close) => {
+            *line += count_lines(&bytes[open..close]);
+            Ok(close + tag.len())
+        }
+        None => Err(SqlSplitError { line: open_line, cause: "unterminated dollar quoted string" }),
+    }
+}
+
+/// The opening tag at `i` (`$$` or `$name$`), None for a `$1` parameter or a `$` in an identifier.
+fn dollar_tag(bytes: &[u8], i: usize) -> Option<&[u8]> {
+    if i > 0 && is_ident_byte(bytes[i - 1]) {
+        return None;
+    }
+
+    let name_len = bytes[i + 1..]
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+        .count();
+    let name = &bytes[i + 1..i + 1 + name_len];
+    if name.first().is_some_and(u8::is_ascii_digit) {
+        return None;
+    }
+
+    (bytes.get(i + 1 + name_len) == Some(&b'$')).then(|| &bytes[i..i + name_len + 2])
+}
+
+/// `E'...'` (or `e'...'`), where `E` is not the end of an identifier.
+fn is_escape_string(bytes: &[u8], quote: usize) -> bool {
+    match quote.checked_sub(1).map(|i| (i, bytes[i])) {
+        Some((i, b'E' | b'e')) => i == 0 || !is_ident_byte(bytes[i - 1]),
+        _ => false,
+    }
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$'
+}
+
+fn find(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    bytes[from..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|pos| from + pos)
+}
+
+fn count_lines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|b| **b == b'\n').count()
+}
+
+// endregion: --- Tokens
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(sql: &str) -> Vec<&str> {
+        split_sql(sql).unwrap().iter().map(|statement| statement.sql).collect()
+    }
+
+    #[test]
+    fn test_split_sql_simple() {
+        assert_eq!(split("SELECT 1; SELECT 2;\nSELECT 3"), ["SELECT 1", "SELECT 2", "SELECT 3"]);
+    }
+
+    #[test]
+    fn test_split_sql_quoted_string() {
+        assert_eq!(
+            split("INSERT INTO t VALUES ('it''s; ok'); SELECT 1;"),
+            ["INSERT INTO t VALUES ('it''s; ok')", "SELECT 1"]
+        );
+    }
+
+    #[test]
+    fn test_split_sql_escape_string() {
+        assert_eq!(split(r"SELECT E'\'; still'; SELECT 1;"), [r"SELECT E'\'; still'", "SELECT 1"]);
+        // Not an escape string, the `E` ends an identifier.
+        assert_eq!(split(r"SELECT naive'\'; SELECT 1;"), [r"SELECT naive'\'", "SELECT 1"]);
+    }
+
+    #[test]
+    fn test_split_sql_quoted_identifier() {
+        assert_eq!(split(r#"SELECT "a;b" FROM t; SELECT 1;"#), [r#"SELECT "a;b" FROM t"#, "SELECT 1"]);
+    }
+
+    #[test]
+    fn test_split_sql_line_comment() {
+        assert_eq!(split("SELECT 1 -- ; not the end\n, 2;"), ["SELECT 1 -- ; not the end\n, 2"]);
+    }
+
+    #[test]
+    fn test_split_sql_nested_block_comment() {
+        assert_eq!(split("SELECT /* /* ; */ ; */ 1; SELECT 2;"), ["SELECT /* /* ; */ ; */ 1", "SELECT 2"]);
+    }
+
+    #[test]
+    fn test_split_sql_dollar_quoted() {
+        assert_eq!(split("DO $$ BEGIN PERFORM 1; END $$; SELECT 1;"), ["DO $$ BEGIN PERFORM 1; END $$", "SELECT 1"]);
+    }
+
+    #[test]
+    fn test_split_sql_dollar_quoted_tag() {
+        let sql = "CREATE FUNCTION f() RETURNS text AS $body$ SELECT $$;$$; $body$ LANGUAGE sql; SELECT 1;";
+        assert_eq!(
+            split(sql),
+            ["CREATE FUNCTION f() RETURNS text AS $body$ SELECT $$;$$; $body$ LANGUAGE sql", "SELECT 1"]
+        );
+    }
+
+    #[test]
+    fn test_split_sql_dollar_parameter() {
+        assert_eq!(split("SELECT $1; SELECT a$b$c;"), ["SELECT $1", "SELECT a$b$c"]);
+    }
+
+    #[test]
+    fn test_split_sql_lines_after_comments() {
+        let sql = "-- header\n/* block\n comment */\n\nSELECT 1;\n-- between\nSELECT\n 'a\nb';\n\nSELECT 3";
+        let statements = split_sql(sql).unwrap();
+        let lines: Vec<usize> = statements.iter().map(|statement| statement.line).collect();
+
+        assert_eq!(lines, [5, 7, 11]);
+        assert_eq!(statements[0].sql, "SELECT 1");
+    }
+
+    #[test]
+    fn test_split_sql_unterminated() {
+        let err = split_sql("SELECT 1;\n\nSELECT 'abc;\nSELECT 2;").unwrap_err();
+        assert_eq!((err.line, err.cause), (3, "unterminated string"));
+
+        let err = split_sql("SELECT 1;\n/* /* */\nSELECT 2;").unwrap_err();
+        assert_eq!((err.line, err.cause), (2, "unterminated block comment"));
+
+        let err = split_sql("DO $body$\nBEGIN END $$;").unwrap_err();
+        assert_eq!((err.line, err.cause), (1, "unterminated dollar quoted string"));
+    }
+
+    #[test]
+    fn test_split_sql_empty_statements() {
+        assert!(split(" ;; -- only a comment\n").is_empty());
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,54 @@
+//! Db pool creation for the model layer.
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::model::model::ModelManager;
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use std::time::Duration;
+
+pub type Db = Pool<Postgres>;
+
+// Readiness must answer quickly, even when no connection can be acquired.
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub async fn new_db_pool(db_con_url: &str) -> Result<Db> {
+    PgPoolOptions::new()
+        .max_connections(config().DB_MAX_CONNECTIONS)
+        .min_connections(config().DB_MIN_CONNECTIONS)
+        .acquire_timeout(Duration::from_secs(config().DB_ACQUIRE_TIMEOUT_SEC))
+        .connect(db_con_url)
+        .await
+        .map_err(|ex| Error::DbFailToCreatePool(ex.to_string()))
+}
+
+/// Connection counts of the db pool, for the metrics.
+pub struct DbPoolStatus {
+    pub size: u32,
+    pub idle: u32,
+    pub max: u32,
+}
+
+impl ModelManager {
+    /// Round trip to the db, for the readiness check.
+    pub async fn ping_db(&self) -> Result<()> {
+        let ping = sqlx::query("SELECT 1").execute(&self.db);
+        tokio::time::timeout(PING_TIMEOUT, ping)
+            .await
+            .map_err(|_| Error::DbQueryFail("ping timed out".to_string()))??;
+
+        Ok(())
+    }
+
+    /// Wait for the checked out connections to be returned, then close them all. At shutdown.
+    pub async fn close_db(&self) {
+        self.db.close().await;
+    }
+
+    pub fn db_pool_status(&self) -> DbPoolStatus {
+        DbPoolStatus {
+            size: self.db.size(),
+            idle: self.db.num_idle() as u32,
+            max: config().DB_MAX_CONNECTIONS,
+        }
+    }
+}
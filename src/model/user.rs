@@ -0,0 +1,32 @@
+//! Users are stored in the `"user"` table.
+
+use crate::{ctx::Role, error::{Error, Result}};
+use crate::model::model::ModelManager;
+use tracing::instrument;
+
+impl ModelManager {
+    /// Used by the ctx resolver, so that the role always reflects the db.
+    #[instrument(skip_all)]
+    pub async fn get_user_role(&self, user_id: u64) -> Result<Role> {
+        let role: Option<(String,)> = sqlx::query_as(r#"SELECT role FROM "user" WHERE id = $1"#)
+            .bind(user_id as i64)
+            .fetch_optional(&self.db)
+            .await?;
+
+        let (role,) = role.ok_or(Error::AuthFailUserNotFound { user_id })?;
+
+        Role::from_db(&role).ok_or(Error::AuthFailUnknownRole(role))
+    }
+
+    /// The id and role of a user, None when there is no such user. For the cli commands.
+    #[instrument(skip_all)]
+    pub async fn get_user_by_username(&self, username: &str) -> Result<Option<(u64, Role)>> {
+        let user: Option<(i64, String)> = sqlx::query_as(r#"SELECT id, role FROM "user" WHERE username = $1"#)
+            .bind(username)
+            .fetch_optional(&self.db)
+            .await?;
+
+        user.map(|(id, role)| Ok((id as u64, Role::from_db(&role).ok_or(Error::AuthFailUnknownRole(role))?)))
+            .transpose()
+    }
+}
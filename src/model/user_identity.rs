@@ -0,0 +1,103 @@
+//! External (OpenID Connect) identities linked to a local `"user"` row.
+
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{ctx::Ctx, error::{Error, Result}};
+use crate::model::model::ModelManager;
+use tracing::instrument;
+
+// Attempts with a numbered suffix before falling back to a random username.
+const USERNAME_ATTEMPTS: usize = 5;
+
+pub struct IdentityForLink {
+    pub issuer: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub username_hint: Option<String>,
+}
+
+impl ModelManager {
+    /// Return the local user id for the identity, linking it on first use:
+    /// - to the ctx user when already logged in (account linking),
+    /// - otherwise to a new user.
+    #[instrument(skip_all)]
+    pub async fn link_user_identity(
+        &self,
+        ctx: Option<&Ctx>,
+        identity: IdentityForLink,
+    ) -> Result<u64> {
+        let mut tx = self.db.begin().await?;
+
+        let linked: Option<(i64,)> = sqlx::query_as(
+            "SELECT user_id FROM user_identity WHERE issuer = $1 AND subject = $2",
+        )
+        .bind(&identity.issuer)
+        .bind(&identity.subject)
+        .fetch_optional(&mut tx)
+        .await?;
+        if let Some((user_id,)) = linked {
+            return Ok(user_id as u64);
+        }
+
+        let user_id = match ctx {
+            Some(ctx) => ctx.user_id() as i64,
+            None => {
+                let hint = identity
+                    .username_hint
+                    .as_deref()
+                    .or(identity.email.as_deref())
+                    .unwrap_or("user");
+                create_user_with_unique_username(&mut tx, hint).await?
+            }
+        };
+
+        sqlx::query(
+            "INSERT INTO user_identity (issuer, subject, user_id, email) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&identity.issuer)
+        .bind(&identity.subject)
+        .bind(user_id)
+        .bind(&identity.email)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(user_id as u64)
+    }
+}
+
+async fn create_user_with_unique_username(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    hint: &str,
+) -> Result<i64> {
+    let base: String = hint.chars().take(100).collect();
+    let random_suffix: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect();
+
+    let candidates = (0..USERNAME_ATTEMPTS)
+        .map(|i| match i {
+            0 => base.clone(),
+            i => format!("{base}-{i}"),
+        })
+        .chain(std::iter::once(format!("{base}-{random_suffix}")));
+
+    for username in candidates {
+        let created: Option<(i64,)> = sqlx::query_as(
+            r#"INSERT INTO "user" (username) VALUES ($1)
+               ON CONFLICT (username) DO NOTHING RETURNING id"#,
+        )
+        .bind(&username)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some((id,)) = created {
+            return Ok(id);
+        }
+    }
+
+    Err(Error::UserCreateFailUsernameTaken { username: base })
+}
@@ -0,0 +1,99 @@
+//! In-memory cache of analyses, keyed by the user and the sha256 of the image,
+//! so that the same image sent again does not call the provider again, nor count against the quota.
+//! The users do not share the entries, an analysis is only served back to whom sent the image.
+//! An entry keeps the model and the stored analysis of the provider call, so a repeat returns that
+//! analysis instead of storing a copy without its model.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+use crate::metrics::metrics;
+use crate::nutrition::NutritionResponse;
+
+/// The provider answer (before the grounding), its model, and its stored analysis if any.
+#[derive(Clone)]
+pub struct CachedAnalysis {
+    pub response: NutritionResponse,
+    pub model: String,
+    pub analysis_id: Option<i64>,
+}
+
+struct Entry {
+    analysis: CachedAnalysis,
+    inserted: Instant,
+}
+
+#[derive(Clone)]
+pub struct AnalysisCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+impl AnalysisCache {
+    /// A capacity of 0 disables the cache.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Arc::default(),
+        }
+    }
+
+    /// The sha256 of the base64 image, as stored with the analyses.
+    pub fn key(base64_image: &str) -> String {
+        format!("{:x}", Sha256::digest(base64_image.as_bytes()))
+    }
+
+    pub fn get(&self, user_id: u64, key: &str) -> Option<CachedAnalysis> {
+        if self.capacity == 0 {
+            return None;
+        }
+
+        let entries = self.entries.lock().unwrap();
+        let analysis = entries
+            .get(&user_key(user_id, key))
+            .filter(|entry| entry.inserted.elapsed() < self.ttl)
+            .map(|entry| entry.analysis.clone());
+
+        metrics().inc_analysis_cache(analysis.is_some());
+        analysis
+    }
+
+    /// When full, the expired entries are dropped first, then the oldest one.
+    pub fn insert(&self, user_id: u64, key: &str, analysis: CachedAnalysis) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = user_key(user_id, key);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.inserted.elapsed() < self.ttl);
+        }
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.inserted)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(
+            key,
+            Entry {
+                analysis,
+                inserted: Instant::now(),
+            },
+        );
+    }
+}
+
+fn user_key(user_id: u64, key: &str) -> String {
+    format!("{user_id}:{key}")
+}
@@ -0,0 +1,197 @@
+//! Circuit breaker for the provider calls.
+//! After `threshold` consecutive failures the circuit opens, and calls fail fast for `open_for`.
+//! Then one call at a time is let through (half-open): a success closes the circuit,
+//! a failure opens it again. A trial dropped without a result (e.g., the request was cancelled)
+//! lets the next call through.
+
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::config;
+
+pub fn provider_circuit() -> &'static CircuitBreaker {
+    static INSTANCE: OnceLock<CircuitBreaker> = OnceLock::new();
+
+    INSTANCE.get_or_init(|| {
+        CircuitBreaker::new(
+            config().PROVIDER_CIRCUIT_FAILURE_THRESHOLD,
+            Duration::from_secs(config().PROVIDER_CIRCUIT_OPEN_SEC),
+        )
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitStatus {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+struct CircuitState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    trial_in_flight: bool,
+}
+
+pub struct CircuitBreaker {
+    threshold: u32,
+    open_for: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, open_for: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            open_for,
+            state: Mutex::new(CircuitState {
+                consecutive_failures: 0,
+                open_until: None,
+                trial_in_flight: false,
+            }),
+        }
+    }
+
+    /// A permit when a call may go out now, to record its result with.
+    /// When half-open, only one trial call at a time.
+    pub fn allow(&self) -> Option<CircuitPermit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        let trial = match self.status_of(&state) {
+            CircuitStatus::Closed => false,
+            CircuitStatus::Open => return None,
+            CircuitStatus::HalfOpen if state.trial_in_flight => return None,
+            CircuitStatus::HalfOpen => {
+                state.trial_in_flight = true;
+                true
+            }
+        };
+
+        Some(CircuitPermit {
+            breaker: self,
+            trial,
+        })
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+        state.trial_in_flight = false;
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        state.trial_in_flight = false;
+        if state.consecutive_failures >= self.threshold {
+            state.open_until = Some(Instant::now() + self.open_for);
+        }
+    }
+
+    pub fn status(&self) -> CircuitStatus {
+        self.status_of(&self.state.lock().unwrap())
+    }
+
+    fn status_of(&self, state: &CircuitState) -> CircuitStatus {
+        match state.open_until {
+            Some(open_until) if Instant::now() < open_until => CircuitStatus::Open,
+            Some(_) => CircuitStatus::HalfOpen,
+            None => CircuitStatus::Closed,
+        }
+    }
+}
+
+/// A call let through by the circuit. Dropped without a result, a half-open trial is
+/// given back, so the circuit does not stay open.
+pub struct CircuitPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    trial: bool,
+}
+
+impl CircuitPermit<'_> {
+    pub fn record_success(mut self) {
+        self.trial = false;
+        self.breaker.record_success();
+    }
+
+    pub fn record_failure(mut self) {
+        self.trial = false;
+        self.breaker.record_failure();
+    }
+}
+
+impl Drop for CircuitPermit<'_> {
+    fn drop(&mut self) {
+        if self.trial {
+            self.breaker.state.lock().unwrap().trial_in_flight = false;
+        }
+    }
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_breaker() -> CircuitBreaker {
+        let breaker = CircuitBreaker::new(2, Duration::ZERO);
+        breaker.allow().unwrap().record_failure();
+        breaker.allow().unwrap().record_failure();
+        breaker
+    }
+
+    #[test]
+    fn test_circuit_opens_after_threshold() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.allow().unwrap().record_failure();
+        assert_eq!(breaker.status(), CircuitStatus::Closed);
+
+        breaker.allow().unwrap().record_failure();
+        assert_eq!(breaker.status(), CircuitStatus::Open);
+        assert!(breaker.allow().is_none());
+    }
+
+    #[test]
+    fn test_circuit_half_open_one_trial() {
+        let breaker = open_breaker();
+        assert_eq!(breaker.status(), CircuitStatus::HalfOpen);
+
+        let trial = breaker.allow().unwrap();
+        assert!(breaker.allow().is_none());
+
+        trial.record_success();
+        assert_eq!(breaker.status(), CircuitStatus::Closed);
+        assert!(breaker.allow().is_some());
+    }
+
+    #[test]
+    fn test_circuit_half_open_trial_failure_reopens() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        breaker.allow().unwrap().record_failure();
+        breaker.state.lock().unwrap().open_until = Some(Instant::now());
+        assert_eq!(breaker.status(), CircuitStatus::HalfOpen);
+
+        breaker.allow().unwrap().record_failure();
+        assert_eq!(breaker.status(), CircuitStatus::Open);
+        assert!(breaker.allow().is_none());
+    }
+
+    #[test]
+    fn test_circuit_dropped_trial_lets_next_through() {
+        let breaker = open_breaker();
+
+        let trial = breaker.allow().unwrap();
+        assert!(breaker.allow().is_none());
+        drop(trial);
+
+        let next = breaker.allow();
+        assert!(next.is_some());
+        assert_eq!(breaker.status(), CircuitStatus::HalfOpen);
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,72 @@
+//! Grounding of the analyzed foods against the food reference:
+//! each food name is matched to the reference (closest name or alias), and when the match
+//! is confident, the reference values per 100 g are scaled to the estimated weight.
+//! The AI estimate is kept as is, the reference values are next to it.
+
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use tracing::{debug, instrument, warn};
+
+use crate::config;
+use crate::model::food::FoodMatch;
+use crate::model::model::ModelManager;
+use crate::nutrition::{FoodItem, NutritionResponse};
+
+/// The reference food matched to an analyzed food, and its values for the estimated weight.
+/// The values are None when the weight was not estimated, or the nutrient is not in the reference.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FoodReference {
+    pub food_id: i64,
+    pub name: String,
+    pub score: f32,
+    pub calories: Option<f32>,
+    pub protein_g: Option<f32>,
+    pub fat_g: Option<f32>,
+    pub carbohydrates_g: Option<f32>,
+    pub sugar_g: Option<f32>,
+    pub sodium_mg: Option<f32>,
+}
+
+/// Sets the `reference` of the foods with a confident match (SERVICE_ANALYSIS_GROUNDING_MIN_SCORE).
+/// A failed lookup leaves the food ungrounded, the analysis itself is still valid.
+#[instrument(skip_all)]
+pub async fn ground_foods(mm: &ModelManager, response: &mut NutritionResponse) {
+    if !config().ANALYSIS_GROUNDING {
+        return;
+    }
+
+    for food in response.foods.iter_mut().filter(|food| !food.name.trim().is_empty()) {
+        food.reference = match mm.match_food(&food.name).await {
+            Ok(food_match) => food_match
+                .filter(|m| m.score >= config().ANALYSIS_GROUNDING_MIN_SCORE)
+                .map(|m| food_reference(food, m)),
+            Err(ex) => {
+                warn!("{:<12} - food reference lookup failed for '{}' - {ex:?}", "GROUNDING", food.name);
+                None
+            }
+        };
+        debug!("{:<12} - '{}' -> {:?}", "GROUNDING", food.name, food.reference);
+    }
+}
+
+fn food_reference(food: &FoodItem, food_match: FoodMatch) -> FoodReference {
+    let FoodMatch { food: reference, score } = food_match;
+    let for_weight = |per_100g: Option<f32>| Some(round1(per_100g? * food.weight_g? / 100.));
+
+    FoodReference {
+        food_id: reference.id,
+        name: reference.name,
+        score,
+        calories: for_weight(reference.calories),
+        protein_g: for_weight(reference.protein_g),
+        fat_g: for_weight(reference.fat_g),
+        carbohydrates_g: for_weight(reference.carbohydrates_g),
+        sugar_g: for_weight(reference.sugar_g),
+        sodium_mg: for_weight(reference.sodium_mg),
+    }
+}
+
+fn round1(value: f32) -> f32 {
+    (value * 10.).round() / 10.
+}
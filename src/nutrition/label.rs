@@ -0,0 +1,291 @@
+//! Nutrition label mode: the provider transcribes a nutrition facts panel, instead of estimating
+//! the foods of a photo. The values are per serving and per 100 g, the missing ones are derived
+//! from the others with the serving weight, and the label calories are checked against its macros.
+
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+
+use crate::nutrition::{call_provider, parse_json_value, parse_numeric_field, ProviderCall};
+
+const LABEL_PROMPT: &str = "This image is the nutrition facts panel (label) of a packaged food. Transcribe it. Return a JSON object with these exact fields: serving_size (the serving as printed, text), serving_g (the serving weight in g, or in ml for drinks), servings_per_container, per_serving and per_100g. Both are objects with these exact fields: calories (kcal), protein_g, fat_g, carbohydrates_g, sugar_g, fiber_g, sodium_mg, salt_g. Use null for what is not printed on the label, do not compute or estimate values. Only return the JSON, no additional text.";
+
+// Sodium is 40% of salt, the european labels only have salt.
+const SODIUM_MG_PER_SALT_G: f32 = 400.;
+
+// The label calories are rounded, and the fiber counts less than the other carbohydrates.
+const CALORIES_CHECK_TOLERANCE: f32 = 0.2;
+const CALORIES_CHECK_MIN_KCAL: f32 = 10.;
+
+#[skip_serializing_none]
+#[derive(Serialize, Debug, Clone)]
+pub struct LabelResponse {
+    pub serving_size: Option<String>,
+    pub serving_g: Option<f32>,
+    pub servings_per_container: Option<f32>,
+    pub per_serving: Option<LabelValues>,
+    pub per_100g: Option<LabelValues>,
+    // None when the label has no calories, or no macros.
+    pub calories_check: Option<CaloriesCheck>,
+}
+
+/// None when not on the label (or not derivable from it).
+#[skip_serializing_none]
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct LabelValues {
+    pub calories: Option<f32>,
+    pub protein_g: Option<f32>,
+    pub fat_g: Option<f32>,
+    pub carbohydrates_g: Option<f32>,
+    pub sugar_g: Option<f32>,
+    pub fiber_g: Option<f32>,
+    pub sodium_mg: Option<f32>,
+}
+
+/// The label calories, against 4 kcal/g of protein and carbohydrates, and 9 kcal/g of fat.
+#[derive(Serialize, Debug, Clone)]
+pub struct CaloriesCheck {
+    pub label_calories: f32,
+    pub macro_calories: f32,
+    pub consistent: bool,
+}
+
+pub async fn call_gemini_label_api(
+    api_key: &str,
+    base64_image: &str,
+) -> Result<(LabelResponse, ProviderCall), Box<dyn std::error::Error>> {
+    call_provider(api_key, base64_image, LABEL_PROMPT, parse_label_response).await
+}
+
+fn parse_label_response(generated_text: &str) -> Result<LabelResponse, Box<dyn std::error::Error>> {
+    let json_value = parse_json_value(generated_text)?;
+
+    let serving_size = json_value
+        .get("serving_size")
+        .and_then(|s| s.as_str())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    let serving_g = parse_numeric_field(&json_value, "serving_g").filter(|g| *g > 0.);
+    let servings_per_container = parse_numeric_field(&json_value, "servings_per_container");
+
+    let mut per_serving = json_value.get("per_serving").and_then(parse_label_values);
+    let mut per_100g = json_value.get("per_100g").and_then(parse_label_values);
+    if let Some(serving_g) = serving_g {
+        per_serving = per_serving.or_else(|| per_100g.as_ref().map(|v| v.scaled(serving_g / 100.)));
+        per_100g = per_100g.or_else(|| per_serving.as_ref().map(|v| v.scaled(100. / serving_g)));
+    }
+    if per_serving.is_none() && per_100g.is_none() {
+        return Err("No nutrition values found on the label".into());
+    }
+
+    let calories_check = per_serving.as_ref().or(per_100g.as_ref()).and_then(calories_check);
+
+    Ok(LabelResponse {
+        serving_size,
+        serving_g,
+        servings_per_container,
+        per_serving,
+        per_100g,
+        calories_check,
+    })
+}
+
+/// None when no value at all, e.g., a label without the per 100 g column.
+fn parse_label_values(value: &serde_json::Value) -> Option<LabelValues> {
+    let sodium_mg = parse_numeric_field(value, "sodium_mg")
+        .or_else(|| parse_numeric_field(value, "sodium"))
+        .or_else(|| parse_numeric_field(value, "salt_g").map(|salt_g| salt_g * SODIUM_MG_PER_SALT_G));
+
+    let values = LabelValues {
+        calories: parse_numeric_field(value, "calories"),
+        protein_g: parse_numeric_field(value, "protein_g").or_else(|| parse_numeric_field(value, "protein")),
+        fat_g: parse_numeric_field(value, "fat_g").or_else(|| parse_numeric_field(value, "fat")),
+        carbohydrates_g: parse_numeric_field(value, "carbohydrates_g")
+            .or_else(|| parse_numeric_field(value, "carbohydrates")),
+        sugar_g: parse_numeric_field(value, "sugar_g").or_else(|| parse_numeric_field(value, "sugar")),
+        fiber_g: parse_numeric_field(value, "fiber_g").or_else(|| parse_numeric_field(value, "fiber")),
+        sodium_mg,
+    };
+
+    values.has_any().then_some(values)
+}
+
+impl LabelValues {
+    fn has_any(&self) -> bool {
+        [
+            self.calories,
+            self.protein_g,
+            self.fat_g,
+            self.carbohydrates_g,
+            self.sugar_g,
+            self.fiber_g,
+            self.sodium_mg,
+        ]
+        .iter()
+        .any(Option::is_some)
+    }
+
+    fn scaled(&self, factor: f32) -> Self {
+        let scale = |value: Option<f32>| value.map(|value| (value * factor * 10.).round() / 10.);
+        Self {
+            calories: scale(self.calories),
+            protein_g: scale(self.protein_g),
+            fat_g: scale(self.fat_g),
+            carbohydrates_g: scale(self.carbohydrates_g),
+            sugar_g: scale(self.sugar_g),
+            fiber_g: scale(self.fiber_g),
+            sodium_mg: scale(self.sodium_mg),
+        }
+    }
+}
+
+fn calories_check(values: &LabelValues) -> Option<CaloriesCheck> {
+    let label_calories = values.calories?;
+    if values.protein_g.is_none() && values.fat_g.is_none() && values.carbohydrates_g.is_none() {
+        return None;
+    }
+
+    let macro_calories = 4. * values.protein_g.unwrap_or(0.)
+        + 4. * values.carbohydrates_g.unwrap_or(0.)
+        + 9. * values.fat_g.unwrap_or(0.);
+    let tolerance = (label_calories * CALORIES_CHECK_TOLERANCE).max(CALORIES_CHECK_MIN_KCAL);
+
+    Some(CaloriesCheck {
+        label_calories,
+        macro_calories: (macro_calories * 10.).round() / 10.,
+        consistent: (label_calories - macro_calories).abs() <= tolerance,
+    })
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A US label, per serving only.
+    const LABEL_US: &str = r#"```json
+{
+  "serving_size": "1 cup (228g)",
+  "serving_g": 228,
+  "servings_per_container": 2,
+  "per_serving": {
+    "calories": 250, "protein_g": 5, "fat_g": 12, "carbohydrates_g": 31,
+    "sugar_g": 5, "fiber_g": 0, "sodium_mg": 470, "salt_g": null
+  },
+  "per_100g": null
+}
+```"#;
+
+    // A european label, per 100 g only, salt instead of sodium, decimal commas.
+    const LABEL_EU: &str = r#"{
+  "serving_size": "2 biscuits (25 g)",
+  "serving_g": "25 g",
+  "servings_per_container": null,
+  "per_serving": {
+    "calories": null, "protein_g": null, "fat_g": null, "carbohydrates_g": null,
+    "sugar_g": null, "fiber_g": null, "sodium_mg": null, "salt_g": null
+  },
+  "per_100g": {
+    "calories": 480, "protein_g": "6,8 g", "fat_g": "21 g", "carbohydrates_g": "65 g",
+    "sugar_g": "28 g", "fiber_g": "3,1 g", "sodium_mg": null, "salt_g": "0,75 g"
+  }
+}"#;
+
+    #[test]
+    fn test_parse_label_per_serving_to_per_100g() {
+        let label = parse_label_response(LABEL_US).unwrap();
+        let per_100g = label.per_100g.unwrap();
+
+        assert_eq!(label.serving_size.as_deref(), Some("1 cup (228g)"));
+        assert_eq!(label.serving_g, Some(228.));
+        assert_eq!(label.servings_per_container, Some(2.));
+        assert_eq!(label.per_serving.unwrap().sodium_mg, Some(470.));
+        assert_eq!(per_100g.calories, Some(109.6));
+        assert_eq!(per_100g.fat_g, Some(5.3));
+        assert_eq!(per_100g.sodium_mg, Some(206.1));
+    }
+
+    #[test]
+    fn test_parse_label_per_100g_to_per_serving_salt() {
+        let label = parse_label_response(LABEL_EU).unwrap();
+        let per_100g = label.per_100g.unwrap();
+        let per_serving = label.per_serving.unwrap();
+
+        assert_eq!(label.serving_g, Some(25.));
+        assert_eq!(per_100g.protein_g, Some(6.8));
+        assert_eq!(per_100g.sodium_mg, Some(300.));
+        assert_eq!(per_serving.calories, Some(120.));
+        assert_eq!(per_serving.fiber_g, Some(0.8));
+        assert_eq!(per_serving.sodium_mg, Some(75.));
+    }
+
+    #[test]
+    fn test_parse_label_without_serving_weight() {
+        let label = parse_label_response(r#"{"serving_g": null, "per_100g": {"calories": 52, "sugar_g": 10.4}}"#).unwrap();
+
+        assert!(label.per_serving.is_none());
+        assert_eq!(label.per_100g.unwrap().sugar_g, Some(10.4));
+        assert!(label.calories_check.is_none()); // no macros
+    }
+
+    #[test]
+    fn test_parse_label_no_values() {
+        let result = parse_label_response(r#"{"serving_g": 30, "per_serving": {"calories": null}, "per_100g": null}"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_label_thousands_and_decimal_comma() {
+        let label = parse_label_response(
+            r#"{"per_serving": {"calories": "Calories 1,050", "sodium_mg": "1,200 mg", "fat_g": "2,5 g", "sugar_g": "1,234.5 g"}}"#,
+        )
+        .unwrap();
+        let per_serving = label.per_serving.unwrap();
+
+        assert_eq!(per_serving.calories, Some(1050.));
+        assert_eq!(per_serving.sodium_mg, Some(1200.));
+        assert_eq!(per_serving.fat_g, Some(2.5));
+        assert_eq!(per_serving.sugar_g, Some(1234.5));
+    }
+
+    #[test]
+    fn test_parse_label_unknown_unit() {
+        let label = parse_label_response(r#"{"per_serving": {"calories": 90, "sodium_mg": "5 %", "protein_g": "3 oz"}}"#).unwrap();
+        let per_serving = label.per_serving.unwrap();
+
+        assert_eq!(per_serving.sodium_mg, None);
+        assert_eq!(per_serving.protein_g, None);
+    }
+
+    #[test]
+    fn test_calories_check_consistent() {
+        // 4 * 5 + 4 * 31 + 9 * 12 = 252 kcal, for 250 on the label.
+        let check = parse_label_response(LABEL_US).unwrap().calories_check.unwrap();
+
+        assert_eq!(check.label_calories, 250.);
+        assert_eq!(check.macro_calories, 252.);
+        assert!(check.consistent);
+    }
+
+    #[test]
+    fn test_calories_check_tolerance() {
+        let values = |calories: f32| LabelValues {
+            calories: Some(calories),
+            protein_g: Some(10.),
+            fat_g: Some(10.),
+            carbohydrates_g: Some(10.),
+            ..Default::default()
+        };
+
+        // 170 kcal from the macros, within 20% of the label.
+        assert!(calories_check(&values(142.)).unwrap().consistent);
+        assert!(calories_check(&values(212.)).unwrap().consistent);
+        assert!(!calories_check(&values(140.)).unwrap().consistent);
+        assert!(!calories_check(&values(215.)).unwrap().consistent);
+        // Small values, at least 10 kcal of tolerance.
+        let small = LabelValues { calories: Some(0.), protein_g: Some(2.), ..Default::default() };
+        assert!(calories_check(&small).unwrap().consistent);
+    }
+}
+
+// endregion: --- Tests
@@ -0,0 +1,434 @@
+//! Nutrition analysis of food images through the Gemini api.
+
+mod cache;
+mod circuit;
+mod grounding;
+mod label;
+mod provider;
+
+pub use cache::{AnalysisCache, CachedAnalysis};
+pub use circuit::provider_circuit;
+pub use grounding::{ground_foods, FoodReference};
+pub use label::{call_gemini_label_api, LabelResponse};
+pub use provider::{GeminiProvider, Provider};
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::json;
+use serde_with::skip_serializing_none;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, instrument};
+
+use crate::config;
+use crate::metrics::metrics;
+
+const KJ_PER_KCAL: f32 = 4.184;
+
+
+// Represents a single food item identified in the image.
+// The nutrients are the AI estimate, for the whole portion of `weight_g` grams.
+#[skip_serializing_none]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct FoodItem {
+    pub name: String,
+    #[serde(default)]
+    pub weight_g: Option<f32>,
+    pub calories: f32,
+    pub protein_g: f32,
+    pub fat_g: f32,
+    pub carbohydrates_g: f32,
+    pub sugar_g: f32,
+    pub sodium_mg: f32,
+    // The matched food reference, and its values for the portion, when grounded.
+    #[serde(default)]
+    pub reference: Option<FoodReference>,
+}
+
+// The final JSON response structure sent back to the client.
+// `analysis_id` is the stored analysis, for its corrections (None when it could not be stored).
+#[skip_serializing_none]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct NutritionResponse {
+    #[serde(default)]
+    pub analysis_id: Option<i64>,
+    pub foods: Vec<FoodItem>,
+}
+
+/// Model, latency and token usage (from `usageMetadata`) of a provider call, for the request log.
+#[skip_serializing_none]
+#[derive(Clone, Debug, Serialize)]
+pub struct ProviderCall {
+    pub model: String,
+    pub latency_ms: f64,
+    pub prompt_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    pub total_tokens: Option<u64>,
+}
+
+/// Non-success http status from the provider.
+#[derive(Debug)]
+struct ProviderStatusError(u16);
+
+impl std::fmt::Display for ProviderStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "provider responded with http status {}", self.0)
+    }
+}
+
+impl std::error::Error for ProviderStatusError {}
+
+/// The provider circuit is open, the call was not made.
+#[derive(Debug)]
+struct ProviderCircuitOpenError;
+
+impl std::fmt::Display for ProviderCircuitOpenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "provider circuit open, too many recent failures")
+    }
+}
+
+impl std::error::Error for ProviderCircuitOpenError {}
+
+pub const FOOD_PROMPT: &str = "Analyze this food image and provide detailed nutritional information. For each food item visible, provide the name, the estimated weight of the portion (g), and for that portion the estimated calories, protein (g), fat (g), carbohydrates (g), sugar (g), and sodium (mg). Use a generic food name, without brand or preparation details unless they matter. Return the response as a JSON object with a 'foods' array containing objects with these exact fields: name, weight_g, calories, protein_g, fat_g, carbohydrates_g, sugar_g, sodium_mg. Only return the JSON, no additional text.";
+
+/// Short hash of the food prompt, stored with the analyses, to compare the prompt versions.
+pub fn food_prompt_id() -> String {
+    prompt_id(FOOD_PROMPT)
+}
+
+pub fn prompt_id(prompt: &str) -> String {
+    format!("{:x}", Sha256::digest(prompt.as_bytes()))[..12].to_string()
+}
+
+// Function to call Gemini 2.5 Pro API for nutritional analysis
+pub async fn call_gemini_api(api_key: &str, base64_image: &str) -> Result<(NutritionResponse, ProviderCall), Box<dyn std::error::Error>> {
+    call_provider(api_key, base64_image, FOOD_PROMPT, parse_gemini_response).await
+}
+
+/// The provider call, behind the circuit breaker, with `parse` for the generated text.
+/// An unparsable answer counts as a failed call (metrics), but not for the circuit.
+#[instrument(skip_all, fields(provider = "gemini", model = %config().GEMINI_MODEL))]
+async fn call_provider<T>(
+    api_key: &str,
+    base64_image: &str,
+    prompt: &str,
+    parse: fn(&str) -> Result<T, Box<dyn std::error::Error>>,
+) -> Result<(T, ProviderCall), Box<dyn std::error::Error>> {
+    let Some(circuit_permit) = provider_circuit().allow() else {
+        metrics().inc_provider_call("gemini", "circuit_open");
+        return Err(Box::new(ProviderCircuitOpenError));
+    };
+
+    let instant_start = Instant::now();
+    let result = analyze_with_gemini(api_key, base64_image, prompt)
+        .await
+        .and_then(|(generated_text, provider_call)| Ok((parse(&generated_text)?, provider_call)));
+
+    let call_result = match &result {
+        Ok(_) => "ok".to_string(),
+        Err(ex) => provider_error_type(ex.as_ref()),
+    };
+    metrics().observe_provider_call("gemini", &call_result, instant_start.elapsed());
+
+    match &result {
+        Err(ex) if is_provider_unavailable(ex.as_ref()) => circuit_permit.record_failure(),
+        _ => circuit_permit.record_success(),
+    }
+
+    result
+}
+
+/// Failures which count for the circuit breaker. A bad request or an unparsable answer
+/// still means the provider is up.
+fn is_provider_unavailable(ex: &(dyn std::error::Error + 'static)) -> bool {
+    match ex.downcast_ref::<ProviderStatusError>() {
+        Some(ProviderStatusError(status)) => *status == 429 || *status >= 500,
+        None => ex.is::<reqwest::Error>(),
+    }
+}
+
+/// Metric label for a failed provider call.
+fn provider_error_type(ex: &(dyn std::error::Error + 'static)) -> String {
+    if let Some(ProviderStatusError(status)) = ex.downcast_ref::<ProviderStatusError>() {
+        return format!("status_{status}");
+    }
+    if let Some(ex) = ex.downcast_ref::<reqwest::Error>() {
+        let error_type = if ex.is_timeout() {
+            "timeout"
+        } else if ex.is_connect() {
+            "connect"
+        } else {
+            "request"
+        };
+        return error_type.to_string();
+    }
+    // Invalid json, or json without the expected content.
+    "parse".to_string()
+}
+
+/// The generated text of the provider, for `prompt` and the image.
+async fn analyze_with_gemini(api_key: &str, base64_image: &str, prompt: &str) -> Result<(String, ProviderCall), Box<dyn std::error::Error>> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config().PROVIDER_TIMEOUT_SEC))
+        .build()?;
+    
+    let request_body = json!({
+        "contents": [{
+            "parts": [
+                {
+                    "text": prompt
+                },
+                {
+                    "inline_data": {
+                        "mime_type": "image/jpeg",
+                        "data": base64_image
+                    }
+                }
+            ]
+        }]
+    });
+
+
+    let url = format!("https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}", config().GEMINI_MODEL, api_key);
+
+    let instant_start = Instant::now();
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .json(&request_body)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        debug!("{:<12} - gemini error body: {}", "PROVIDER", response.text().await.unwrap_or_default());
+        return Err(Box::new(ProviderStatusError(status)));
+    }
+
+    let response_text = response.text().await?;
+
+    
+    let gemini_response: serde_json::Value = serde_json::from_str(&response_text)?;
+
+    let usage = gemini_response.get("usageMetadata");
+    let usage_count = |name: &str| usage.and_then(|u| u.get(name)).and_then(|c| c.as_u64());
+    let provider_call = ProviderCall {
+        model: gemini_response
+            .get("modelVersion")
+            .and_then(|m| m.as_str())
+            .unwrap_or(&config().GEMINI_MODEL)
+            .to_string(),
+        latency_ms: instant_start.elapsed().as_secs_f64() * 1000.,
+        prompt_tokens: usage_count("promptTokenCount"),
+        output_tokens: usage_count("candidatesTokenCount"),
+        total_tokens: usage_count("totalTokenCount"),
+    };
+    debug!("{:<12} - {provider_call:?}", "PROVIDER");
+    
+    // Extract the generated text from Gemini's response
+    let generated_text = gemini_response
+        .get("candidates")
+        .and_then(|c| c.get(0))
+        .and_then(|c| c.get("content"))
+        .and_then(|c| c.get("parts"))
+        .and_then(|p| p.get(0))
+        .and_then(|p| p.get("text"))
+        .and_then(|t| t.as_str())
+        .ok_or("Failed to extract text from Gemini response")?;
+
+    debug!("generated_text: {}\n", generated_text);
+
+    Ok((generated_text.to_string(), provider_call))
+}
+
+// Robust parser for Gemini response that handles missing fields and unknown keys
+pub fn parse_gemini_response(generated_text: &str) -> Result<NutritionResponse, Box<dyn std::error::Error>> {
+    let json_value = parse_json_value(generated_text)?;
+
+    // Extract foods array
+    let foods_array = json_value
+        .get("foods")
+        .and_then(|f| f.as_array())
+        .ok_or("No 'foods' array found in response")?;
+
+    let mut foods = Vec::new();
+
+    for food_value in foods_array {
+        let food_item = parse_food_item(food_value)?;
+        foods.push(food_item);
+    }
+
+    Ok(NutritionResponse { analysis_id: None, foods })
+}
+
+// The JSON of the generated text, which may be wrapped in markdown or extra text.
+fn parse_json_value(generated_text: &str) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    // First, try to parse as JSON
+    let json_value: serde_json::Value = match serde_json::from_str(generated_text) {
+        Ok(value) => value,
+        Err(_) => {
+            // If direct parsing fails, try to extract JSON from the text
+            // Sometimes Gemini wraps JSON in markdown code blocks or adds extra text
+            info!("Could not parse directly\n");
+            if let Some(json_str) = extract_json_from_text(generated_text) {
+                debug!("json_str: {}", json_str);
+                serde_json::from_str(&json_str)?
+            } else {
+                return Err("No valid JSON found in Gemini response".into());
+            }
+        }
+    };
+
+    Ok(json_value)
+}
+
+// Extract JSON from text that might contain markdown or extra content
+fn extract_json_from_text(text: &str) -> Option<String> {
+    // Look for JSON wrapped in code blocks
+    if let Some(start) = text.find("```json") {
+        let json_start = start + 7; // Skip "```json"
+        if let Some(end) = text[json_start..].find("```") {
+            let json_end = json_start + end;
+            let s = text[json_start..json_end].trim().to_string();
+            return Some(s);
+        }
+    }
+    
+    // Look for JSON wrapped in regular code blocks
+    if let Some(start) = text.find("```") {
+        if let Some(end) = text[start + 3..].find("```") {
+            let json_start = start + 3;
+            let json_end = start + 3 + end;
+            let potential_json = text[json_start..json_end].trim();
+            if potential_json.starts_with('{') && potential_json.ends_with('}') {
+                return Some(potential_json.to_string());
+            }
+        }
+    }
+    
+    // Look for JSON object in the text
+    if let Some(start) = text.find('{') {
+        if let Some(end) = text.rfind('}') {
+            if end > start {
+                return Some(text[start..=end].to_string());
+            }
+        }
+    }
+    
+    None
+}
+
+// Parse individual food item with default values for missing fields
+fn parse_food_item(food_value: &serde_json::Value) -> Result<FoodItem, Box<dyn std::error::Error>> {
+    let name = food_value
+        .get("name")
+        .and_then(|n| n.as_str())
+        .unwrap_or("Unknown Food")
+        .to_string();
+
+    // None when not estimated, the food can then not be grounded.
+    let weight_g = parse_numeric_field(food_value, "weight_g")
+        .or_else(|| parse_numeric_field(food_value, "weight"))
+        .filter(|weight_g| *weight_g > 0.0);
+    let calories = parse_numeric_field(food_value, "calories").unwrap_or(0.0);
+    let protein_g = parse_numeric_field(food_value, "protein_g")
+        .or_else(|| parse_numeric_field(food_value, "protein"))
+        .unwrap_or(0.0);
+    let fat_g = parse_numeric_field(food_value, "fat_g")
+        .or_else(|| parse_numeric_field(food_value, "fat"))
+        .unwrap_or(0.0);
+    let carbohydrates_g = parse_numeric_field(food_value, "carbohydrates_g")
+        .or_else(|| parse_numeric_field(food_value, "carbohydrates"))
+        .or_else(|| parse_numeric_field(food_value, "carbs"))
+        .unwrap_or(0.0);
+    let sugar_g = parse_numeric_field(food_value, "sugar_g")
+        .or_else(|| parse_numeric_field(food_value, "sugar"))
+        .unwrap_or(0.0);
+    let sodium_mg = parse_numeric_field(food_value, "sodium_mg")
+        .or_else(|| parse_numeric_field(food_value, "sodium"))
+        .unwrap_or(0.0);
+
+    Ok(FoodItem {
+        name,
+        weight_g,
+        calories,
+        protein_g,
+        fat_g,
+        carbohydrates_g,
+        sugar_g,
+        sodium_mg,
+        reference: None,
+    })
+}
+
+// Helper function to parse numeric fields that might be strings or numbers.
+// Strings may have a label and a unit, e.g., "12 g", "150mg", "1,5 g", "<0.5 g" or "Calories 1,050",
+// converted to the unit of the field (its `_g` or `_mg` suffix, kcal for calories).
+// None for an unknown unit, rather than a value in the wrong unit.
+fn parse_numeric_field(value: &serde_json::Value, field_name: &str) -> Option<f32> {
+    value.get(field_name).and_then(|v| {
+        match v {
+            serde_json::Value::Number(n) => n.as_f64().map(|f| f as f32),
+            serde_json::Value::String(s) => parse_quantity(s, field_name),
+            _ => None,
+        }
+    })
+}
+
+fn parse_quantity(s: &str, field_name: &str) -> Option<f32> {
+    // A label before the number, e.g., "Calories 1,050" or "less than 1 g".
+    let number_start = s.find(|c: char| c.is_ascii_digit())?;
+    if !s[..number_start]
+        .chars()
+        .all(|c| c.is_alphabetic() || c.is_whitespace() || matches!(c, '<' | '>' | '~' | ':'))
+    {
+        return None;
+    }
+    let s = &s[number_start..];
+    let number_len = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+        .unwrap_or(s.len());
+    let number = parse_number(&s[..number_len])?;
+
+    let unit = s[number_len..].trim().to_lowercase();
+    let field_unit = match field_name {
+        "calories" => "kcal",
+        "sodium" => "mg",
+        "servings_per_container" => "",
+        _ if field_name.ends_with("_mg") => "mg",
+        _ => "g",
+    };
+    let factor = match (field_unit, unit.as_str()) {
+        (_, "") => 1.,
+        ("g", "g") | ("mg", "mg") | ("kcal", "kcal" | "cal") => 1.,
+        ("g", "ml") if field_name.starts_with("serving") || field_name.starts_with("weight") => 1.,
+        ("g", "mg") => 0.001,
+        ("mg", "g") => 1000.,
+        ("kcal", "kj") => 1. / KJ_PER_KCAL,
+        _ => return None,
+    };
+
+    Some(number * factor)
+}
+
+/// A `,` is a thousands separator when the number also has a `.`, or when groups of exactly
+/// three digits follow it ("1,200", "10,000,000"). Otherwise it is a decimal comma ("2,5"),
+/// as on the european labels.
+fn parse_number(s: &str) -> Option<f32> {
+    if s.contains('.') {
+        return s.replace(',', "").parse().ok();
+    }
+
+    let groups: Vec<&str> = s.split(',').collect();
+    let number = match groups.as_slice() {
+        [integer] => integer.to_string(),
+        [first, rest @ ..] if !first.is_empty() && *first != "0" && rest.iter().all(|g| g.len() == 3) => {
+            groups.concat()
+        }
+        [integer, decimals] => format!("{integer}.{decimals}"),
+        _ => return None,
+    };
+
+    number.parse().ok()
+}
@@ -0,0 +1,44 @@
+//! Providers as the offline evaluation sees them (see the `eval` command):
+//! the generated text for a prompt and an image, without the circuit breaker and the metrics.
+//! The server calls Gemini through `call_gemini_api`.
+
+use async_trait::async_trait;
+
+use crate::config;
+use crate::nutrition::{analyze_with_gemini, ProviderCall};
+
+#[async_trait]
+pub trait Provider {
+    /// e.g., "gemini (gemini-2.5-flash)", for the report.
+    fn name(&self) -> String;
+
+    /// The generated text, not parsed yet. The error is the reason of a failed call.
+    async fn generate(&self, base64_image: &str, prompt: &str) -> Result<(String, ProviderCall), String>;
+}
+
+pub struct GeminiProvider {
+    api_key: String,
+}
+
+impl GeminiProvider {
+    pub fn new(api_key: String) -> Self {
+        Self { api_key }
+    }
+}
+
+#[async_trait]
+impl Provider for GeminiProvider {
+    fn name(&self) -> String {
+        format!("gemini ({})", config().GEMINI_MODEL)
+    }
+
+    async fn generate(&self, base64_image: &str, prompt: &str) -> Result<(String, ProviderCall), String> {
+        // The request url has the api key, and the errors end up in the recorded answers.
+        analyze_with_gemini(&self.api_key, base64_image, prompt)
+            .await
+            .map_err(|ex| match ex.downcast::<reqwest::Error>() {
+                Ok(ex) => ex.without_url().to_string(),
+                Err(ex) => ex.to_string(),
+            })
+    }
+}
@@ -0,0 +1,280 @@
+//! OpenID Connect client (authorization code flow with PKCE).
+//! - Provider metadata from `{issuer}/.well-known/openid-configuration` (cached).
+//! - ID token signature validated against the provider JWKS (refreshed on unknown `kid`).
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use rand::RngCore;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::{OnceCell, RwLock};
+use tracing::debug;
+
+use crate::config;
+use crate::error::{Error, Result};
+
+// Symmetric algorithms are refused, the key must come from the JWKS.
+const ALLOWED_ALGS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+#[derive(Clone, Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// The validated ID token claims which are used for the account linking.
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub email: Option<String>,
+    pub preferred_username: Option<String>,
+    nonce: Option<String>,
+}
+
+/// What needs to be remembered between the login redirect and the callback.
+pub struct AuthRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+pub struct OidcClient {
+    http: reqwest::Client,
+    issuer_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_url: String,
+    metadata: OnceCell<ProviderMetadata>,
+    jwks: RwLock<JwkSet>,
+}
+
+// Constructor
+impl OidcClient {
+    /// None when `SERVICE_OIDC_ISSUER_URL` is not set.
+    pub fn from_config() -> Result<Option<Self>> {
+        let Some(issuer_url) = config().OIDC_ISSUER_URL.clone() else {
+            return Ok(None);
+        };
+        let client_id = config()
+            .OIDC_CLIENT_ID
+            .clone()
+            .ok_or(Error::ConfigMissingEnv("SERVICE_OIDC_CLIENT_ID"))?;
+        let redirect_url = config()
+            .OIDC_REDIRECT_URL
+            .clone()
+            .ok_or(Error::ConfigMissingEnv("SERVICE_OIDC_REDIRECT_URL"))?;
+
+        let client_secret = config()
+            .OIDC_CLIENT_SECRET
+            .as_ref()
+            .map(|secret| secret.expose().clone());
+
+        Ok(Some(Self::new(&issuer_url, client_id, client_secret, redirect_url)))
+    }
+
+    pub fn new(
+        issuer_url: &str,
+        client_id: String,
+        client_secret: Option<String>,
+        redirect_url: String,
+    ) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            issuer_url: issuer_url.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret,
+            redirect_url,
+            metadata: OnceCell::new(),
+            jwks: RwLock::new(JwkSet { keys: Vec::new() }),
+        }
+    }
+}
+
+// Flow
+impl OidcClient {
+    /// Build the authorization url, with new state, nonce and PKCE verifier.
+    pub async fn authorize_url(&self) -> Result<AuthRequest> {
+        let metadata = self.metadata().await?;
+
+        let state = random_b64u();
+        let nonce = random_b64u();
+        let code_verifier = random_b64u();
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let mut url = Url::parse(&metadata.authorization_endpoint)
+            .map_err(|ex| Error::OidcDiscoveryFail(ex.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", &self.redirect_url)
+            .append_pair("scope", "openid email profile")
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &code_challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(AuthRequest {
+            url: url.to_string(),
+            state,
+            nonce,
+            code_verifier,
+        })
+    }
+
+    /// Exchange the authorization code, and return the validated ID token claims.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims> {
+        let metadata = self.metadata().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_url),
+            ("client_id", &self.client_id),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(client_secret) = &self.client_secret {
+            form.push(("client_secret", client_secret));
+        }
+
+        let res = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|ex| Error::OidcTokenExchangeFail(ex.to_string()))?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = res.text().await.unwrap_or_default();
+            return Err(Error::OidcTokenExchangeFail(format!("{status} - {body}")));
+        }
+        let TokenResponse { id_token } = res
+            .json()
+            .await
+            .map_err(|ex| Error::OidcTokenExchangeFail(ex.to_string()))?;
+
+        self.validate_id_token(&id_token, nonce).await
+    }
+
+    async fn validate_id_token(&self, id_token: &str, nonce: &str) -> Result<IdTokenClaims> {
+        let metadata = self.metadata().await?;
+
+        let header = decode_header(id_token)
+            .map_err(|ex| Error::OidcIdTokenInvalid(ex.to_string()))?;
+        if !ALLOWED_ALGS.contains(&header.alg) {
+            return Err(Error::OidcIdTokenInvalid(format!("alg {:?} not allowed", header.alg)));
+        }
+        let key = self.decoding_key(header.kid.as_deref()).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(|ex| Error::OidcIdTokenInvalid(ex.to_string()))?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(Error::OidcIdTokenInvalid("nonce mismatch".to_string()));
+        }
+
+        Ok(claims)
+    }
+}
+
+// Provider metadata and keys
+impl OidcClient {
+    async fn metadata(&self) -> Result<&ProviderMetadata> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer_url);
+                debug!("{:<12} - oidc discovery - {url}", "OIDC");
+
+                let metadata: ProviderMetadata = self
+                    .http
+                    .get(&url)
+                    .send()
+                    .await
+                    .and_then(|res| res.error_for_status())
+                    .map_err(|ex| Error::OidcDiscoveryFail(ex.to_string()))?
+                    .json()
+                    .await
+                    .map_err(|ex| Error::OidcDiscoveryFail(ex.to_string()))?;
+
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// Key for `kid` from the cached JWKS, which is fetched again when the kid is unknown
+    /// (e.g., after a key rotation of the provider).
+    async fn decoding_key(&self, kid: Option<&str>) -> Result<DecodingKey> {
+        if let Some(key) = find_key(&*self.jwks.read().await, kid) {
+            return key;
+        }
+
+        let metadata = self.metadata().await?;
+        debug!("{:<12} - oidc jwks fetch - {}", "OIDC", metadata.jwks_uri);
+        let jwks: JwkSet = self
+            .http
+            .get(&metadata.jwks_uri)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|ex| Error::OidcDiscoveryFail(ex.to_string()))?
+            .json()
+            .await
+            .map_err(|ex| Error::OidcDiscoveryFail(ex.to_string()))?;
+
+        let key = find_key(&jwks, kid);
+        *self.jwks.write().await = jwks;
+
+        key.unwrap_or_else(|| {
+            Err(Error::OidcIdTokenInvalid(format!("no jwk for kid {kid:?}")))
+        })
+    }
+}
+
+/// Without a `kid`, only a single key set is accepted.
+fn find_key(jwks: &JwkSet, kid: Option<&str>) -> Option<Result<DecodingKey>> {
+    let jwk = match kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }?;
+
+    Some(DecodingKey::from_jwk(jwk).map_err(|ex| Error::OidcIdTokenInvalid(ex.to_string())))
+}
+
+fn random_b64u() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
@@ -0,0 +1,84 @@
+//! Graceful shutdown, on SIGTERM (e.g., Fly.io stopping the machine) or SIGINT (Ctrl-C):
+//! 1. draining: readiness fails, and no new connections are accepted,
+//! 2. the in-flight requests finish, for up to `SERVICE_SHUTDOWN_DRAIN_TIMEOUT_SEC`,
+//! 3. then main flushes the log sink, closes the db pool, and the span export.
+
+use std::future::pending;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::metrics::metrics;
+
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// Whether a shutdown signal was received, for the readiness check.
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::Relaxed)
+}
+
+/// Resolves `triggered` once a shutdown signal was received, for all the clones.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Spawn the signal listener. Must be called from the tokio runtime.
+    pub fn listen() -> Self {
+        let (tx, rx) = watch::channel(false);
+
+        tokio::spawn(async move {
+            let signal = wait_for_signal().await;
+            DRAINING.store(true, Ordering::Relaxed);
+            info!(
+                "{:<12} - {signal} received, draining {} in-flight requests",
+                "SHUTDOWN",
+                metrics().http_requests_in_flight()
+            );
+            let _ = tx.send(true);
+        });
+
+        Self(rx)
+    }
+
+    pub async fn triggered(&self) {
+        let mut rx = self.0.clone();
+        // Err only if the listener task is gone without sending, then never.
+        if rx.wait_for(|triggered| *triggered).await.is_err() {
+            pending::<()>().await;
+        }
+    }
+}
+
+/// The name of the signal. A handler which cannot be installed never resolves.
+async fn wait_for_signal() -> &'static str {
+    let ctrl_c = async {
+        if let Err(ex) = tokio::signal::ctrl_c().await {
+            warn!("{:<12} - cannot listen for SIGINT - {ex}", "SHUTDOWN");
+            pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(ex) => {
+                warn!("{:<12} - cannot listen for SIGTERM - {ex}", "SHUTDOWN");
+                pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => "SIGINT",
+        _ = terminate => "SIGTERM",
+    }
+}
@@ -0,0 +1,170 @@
+//! Tracing subscriber setup.
+//! - Always: logs filtered by `RUST_LOG`, human readable or json (`SERVICE_LOG_FORMAT`), on stdout,
+//!   or on stderr when the request log lines go to stdout (`SERVICE_LOG_SINK=stdout`), so that
+//!   stdout is only JSON lines.
+//! - With the `otel` feature and `SERVICE_OTEL_OTLP_ENDPOINT` set: spans exported over OTLP/http,
+//!   and the incoming W3C `traceparent` used as the parent of the request span.
+
+use axum::http::HeaderMap;
+use tracing::Span;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Registry};
+
+use crate::config;
+use crate::config::LogFormat;
+use crate::log::LogSinkKind;
+use crate::error::{Error, Result};
+
+#[cfg(feature = "otel")]
+const SERVICE_NAME: &str = "foodeq-be";
+
+/// Keep alive for the process lifetime, the pending spans are exported when dropped.
+pub struct TelemetryGuard {
+    #[cfg(feature = "otel")]
+    tracer_provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(tracer_provider) = self.tracer_provider.take() {
+            let _ = tracer_provider.shutdown();
+        }
+    }
+}
+
+/// Stdout, but when the request log lines are written there.
+fn tracing_writer() -> BoxMakeWriter {
+    match config().LOG_SINK {
+        LogSinkKind::Stdout => BoxMakeWriter::new(std::io::stderr),
+        LogSinkKind::File | LogSinkKind::Http => BoxMakeWriter::new(std::io::stdout),
+    }
+}
+
+pub fn init_tracing() -> Result<TelemetryGuard> {
+    // One of the two, the other is None.
+    let (text_layer, json_layer) = match config().LOG_FORMAT {
+        LogFormat::Text => {
+            let text_layer = tracing_subscriber::fmt::layer()
+                .with_writer(tracing_writer())
+                .without_time() // For early local development, omit timestamps
+                .with_target(false);
+            (Some(text_layer), None)
+        }
+        LogFormat::Json => {
+            let json_layer = tracing_subscriber::fmt::layer()
+                .with_writer(tracing_writer())
+                .json()
+                .with_current_span(true)
+                .with_target(false);
+            (None, Some(json_layer))
+        }
+    };
+
+    let subscriber = Registry::default()
+        .with(EnvFilter::from_default_env())
+        .with(text_layer)
+        .with(json_layer);
+
+    #[cfg(feature = "otel")]
+    {
+        let tracer_provider = config()
+            .OTEL_OTLP_ENDPOINT
+            .as_deref()
+            .map(new_tracer_provider)
+            .transpose()?;
+        let otel_layer = tracer_provider.as_ref().map(|tracer_provider| {
+            use opentelemetry::trace::TracerProvider;
+            tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer(SERVICE_NAME))
+        });
+
+        subscriber
+            .with(otel_layer)
+            .try_init()
+            .map_err(|ex| Error::TelemetryInitFail(ex.to_string()))?;
+
+        Ok(TelemetryGuard { tracer_provider })
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        subscriber
+            .try_init()
+            .map_err(|ex| Error::TelemetryInitFail(ex.to_string()))?;
+
+        if config().OTEL_OTLP_ENDPOINT.is_some() {
+            tracing::warn!(
+                "{:<12} - SERVICE_OTEL_OTLP_ENDPOINT is ignored, built without the otel feature",
+                "TELEMETRY"
+            );
+        }
+
+        Ok(TelemetryGuard {})
+    }
+}
+
+#[cfg(feature = "otel")]
+fn new_tracer_provider(endpoint: &str) -> Result<opentelemetry_sdk::trace::SdkTracerProvider> {
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|ex| Error::TelemetryInitFail(ex.to_string()))?;
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build())
+}
+
+/// Continue the trace of the caller (W3C `traceparent`), when exporting.
+#[cfg(feature = "otel")]
+pub fn set_span_parent(span: &Span, headers: &HeaderMap) {
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl opentelemetry::propagation::Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|v| v.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|k| k.as_str()).collect()
+        }
+    }
+
+    let parent_cx = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    });
+    span.set_parent(parent_cx);
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn set_span_parent(_span: &Span, _headers: &HeaderMap) {}
+
+/// The trace id of the span, when exporting.
+#[cfg(feature = "otel")]
+pub fn span_trace_id(span: &Span) -> Option<String> {
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let span_context = span.context().span().span_context().clone();
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn span_trace_id(_span: &Span) -> Option<String> {
+    None
+}
@@ -0,0 +1,151 @@
+//! Session tokens, used by the AUTH_TOKEN cookie and `Authorization: Bearer`.
+//! Format: `user-[user-id].[expiration].[signature]`
+//! - expiration: unix timestamp (seconds)
+//! - signature: base64url(hmac-sha256(TOKEN_KEY, "user-[user-id].[expiration]"))
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use lazy_regex::regex_captures;
+use sha2::Sha256;
+use time::OffsetDateTime;
+use tracing::debug;
+
+use crate::config;
+use crate::error::{Error, Result};
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug)]
+pub struct Token {
+    pub user_id: u64,
+    pub exp: i64,
+    pub sign_b64u: String,
+}
+
+/// Parse a token of format `user-[user-id].[expiration].[signature]`
+/// (does not validate it).
+pub fn parse_token(token: &str) -> Result<Token> {
+    let (_whole, user_id, exp, sign) = regex_captures!(
+        r#"^user-(\d+)\.(\d+)\.(.+)$"#, // a literal regex
+        token
+    ).ok_or(Error::AuthFailTokenWrongFormat)?;
+
+    let user_id: u64 = user_id
+        .parse()
+        .map_err(|_| Error::AuthFailTokenWrongFormat)?;
+    let exp: i64 = exp
+        .parse()
+        .map_err(|_| Error::AuthFailTokenWrongFormat)?;
+    // The token is a credential, only its user is logged.
+    debug!("{:<12} - parse_token - user_id: {user_id}", "PARSE_TOKEN");
+
+    Ok(Token { user_id, exp, sign_b64u: sign.to_string() })
+}
+
+/// New token for `user_id`, valid for `TOKEN_DURATION_SEC`.
+pub fn generate_token(user_id: u64) -> Result<String> {
+    let exp = OffsetDateTime::now_utc().unix_timestamp() + config().TOKEN_DURATION_SEC;
+
+    sign_token(config().TOKEN_KEY.expose(), user_id, exp)
+}
+
+/// Validate the signature and the expiration, and return the user id.
+/// A token which only has the format (e.g., `user-1.1.x`) is rejected, whether from the cookie or a bearer.
+pub fn validate_token(token: &str) -> Result<u64> {
+    validate_token_with(config().TOKEN_KEY.expose(), token, OffsetDateTime::now_utc().unix_timestamp())
+}
+
+fn sign_token(key: &[u8], user_id: u64, exp: i64) -> Result<String> {
+    let content = format!("user-{user_id}.{exp}");
+    let sign = new_mac(key, &content)?.finalize().into_bytes();
+
+    Ok(format!("{content}.{}", URL_SAFE_NO_PAD.encode(sign)))
+}
+
+fn validate_token_with(key: &[u8], token: &str, now: i64) -> Result<u64> {
+    let Token { user_id, exp, sign_b64u } = parse_token(token)?;
+
+    let sign = URL_SAFE_NO_PAD
+        .decode(sign_b64u)
+        .map_err(|_| Error::AuthFailTokenWrongFormat)?;
+    new_mac(key, &format!("user-{user_id}.{exp}"))?
+        .verify_slice(&sign) // constant time comparison
+        .map_err(|_| Error::AuthFailTokenSignatureNotMatching)?;
+
+    if exp < now {
+        return Err(Error::AuthFailTokenExpired);
+    }
+
+    Ok(user_id)
+}
+
+fn new_mac(key: &[u8], content: &str) -> Result<HmacSha256> {
+    let mut mac = HmacSha256::new_from_slice(key).map_err(|_| Error::TokenKeyFailHmac)?;
+    mac.update(content.as_bytes());
+
+    Ok(mac)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"test_only_key_of_at_least_32_bytes_long";
+    const NOW: i64 = 1_800_000_000;
+
+    #[test]
+    fn test_validate_token_ok() {
+        let token = sign_token(KEY, 42, NOW + 60).unwrap();
+
+        assert_eq!(validate_token_with(KEY, &token, NOW).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_validate_token_unsigned_rejected() {
+        // Only the format, as a script would send it in `Authorization: Bearer`.
+        let result = validate_token_with(KEY, &format!("user-1.{}.c2lnbg", NOW + 60), NOW);
+
+        assert!(matches!(result, Err(Error::AuthFailTokenSignatureNotMatching)), "{result:?}");
+    }
+
+    #[test]
+    fn test_validate_token_other_user_rejected() {
+        let token = sign_token(KEY, 42, NOW + 60).unwrap();
+        let (_, rest) = token.split_once('.').unwrap();
+
+        let result = validate_token_with(KEY, &format!("user-1.{rest}"), NOW);
+
+        assert!(matches!(result, Err(Error::AuthFailTokenSignatureNotMatching)), "{result:?}");
+    }
+
+    #[test]
+    fn test_validate_token_other_key_rejected() {
+        let token = sign_token(b"another_key_of_at_least_32_bytes_long__", 42, NOW + 60).unwrap();
+
+        let result = validate_token_with(KEY, &token, NOW);
+
+        assert!(matches!(result, Err(Error::AuthFailTokenSignatureNotMatching)), "{result:?}");
+    }
+
+    #[test]
+    fn test_validate_token_expired() {
+        let token = sign_token(KEY, 42, NOW - 1).unwrap();
+
+        let result = validate_token_with(KEY, &token, NOW);
+
+        assert!(matches!(result, Err(Error::AuthFailTokenExpired)), "{result:?}");
+    }
+
+    #[test]
+    fn test_validate_token_wrong_format() {
+        for token in ["", "user-1", "user-x.1.sign", "fdq_prefix_secret"] {
+            let result = validate_token_with(KEY, token, NOW);
+
+            assert!(matches!(result, Err(Error::AuthFailTokenWrongFormat)), "{token}: {result:?}");
+        }
+    }
+}
+
+// endregion: --- Tests
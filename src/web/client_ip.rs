@@ -0,0 +1,211 @@
+//! Client ip resolution.
+//! `Fly-Client-IP` and `X-Forwarded-For` are only honored when the
+//! connecting peer is a trusted proxy (`SERVICE_TRUSTED_PROXIES`), or a local
+//! process on the unix socket (`SERVICE_BIND_UNIX_SOCKET`).
+
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+use axum::extract::connect_info::Connected;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::serve::IncomingStream;
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
+
+use crate::config;
+
+const FLY_CLIENT_IP: &str = "fly-client-ip";
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Ip network in cidr notation (e.g., `10.0.0.0/8`, `fdaa::/16`).
+/// A plain ip is a network of one address.
+#[derive(Clone, Copy, Debug)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNet {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_eq(&net.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(&net.octets(), &ip.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("invalid ip '{addr}'"))?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid prefix length '{len}'"))?,
+            None => max_len,
+        };
+
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+fn prefix_eq(net: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = (prefix_len / 8) as usize;
+    let rem_bits = prefix_len % 8;
+
+    if net[..full_bytes] != ip[..full_bytes] {
+        return false;
+    }
+    if rem_bits == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - rem_bits);
+    net[full_bytes] & mask == ip[full_bytes] & mask
+}
+
+fn is_trusted(ip: &IpAddr) -> bool {
+    config().TRUSTED_PROXIES.iter().any(|net| net.contains(ip))
+}
+
+/// Resolve the client ip from the peer address and the proxy headers.
+pub fn resolve_client_ip(headers: &HeaderMap, peer: IpAddr) -> IpAddr {
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    resolve_forwarded_ip(headers).unwrap_or(peer)
+}
+
+/// The client ip from the proxy headers, for a peer which is trusted.
+fn resolve_forwarded_ip(headers: &HeaderMap) -> Option<IpAddr> {
+    // Fly.io sets this one to the original client ip.
+    let fly_client_ip = headers
+        .get(FLY_CLIENT_IP)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<IpAddr>().ok());
+    if fly_client_ip.is_some() {
+        return fly_client_ip;
+    }
+
+    // Right most entry which is not a trusted proxy, since the left ones can be spoofed.
+    let forwarded_for: Vec<IpAddr> = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|v| v.trim().parse::<IpAddr>().ok())
+        .collect();
+
+    forwarded_for
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(ip))
+        .or(forwarded_for.first())
+        .copied()
+}
+
+// ClientIp Extractor
+
+/// Requires the app to be served with `into_make_service_with_connect_info::<SocketAddr>()`
+/// (or `::<UnixPeer>()`), otherwise falls back to the unspecified address.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Infallible> {
+        // The local reverse proxy, trusted without an ip.
+        if parts.extensions.get::<ConnectInfo<UnixPeer>>().is_some() {
+            let client_ip = resolve_forwarded_ip(&parts.headers);
+            return Ok(ClientIp(client_ip.unwrap_or(IpAddr::V6(Ipv6Addr::LOCALHOST))));
+        }
+
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_canonical()) // ipv4 mapped, when dual-stack
+            .unwrap_or(IpAddr::from([0, 0, 0, 0]));
+
+        Ok(ClientIp(resolve_client_ip(&parts.headers, peer)))
+    }
+}
+
+/// Connect info of the unix socket connections, the peer has no ip.
+#[derive(Clone, Copy, Debug)]
+pub struct UnixPeer;
+
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for UnixPeer {
+    fn connect_info(_stream: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        UnixPeer
+    }
+}
+
+// END - ClientIp Extractor
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contains(net: &str, ip: &str) -> bool {
+        net.parse::<IpNet>().unwrap().contains(&ip.parse().unwrap())
+    }
+
+    #[test]
+    fn test_ipnet_contains_v4() {
+        assert!(contains("172.16.0.0/12", "172.16.0.1"));
+        assert!(contains("172.16.0.0/12", "172.31.255.255"));
+        assert!(!contains("172.16.0.0/12", "172.32.0.1"));
+        assert!(contains("10.0.0.0/8", "10.20.30.40"));
+        assert!(contains("127.0.0.1", "127.0.0.1"));
+        assert!(!contains("127.0.0.1", "127.0.0.2"));
+        assert!(contains("0.0.0.0/0", "8.8.8.8"));
+    }
+
+    #[test]
+    fn test_ipnet_contains_v6() {
+        assert!(contains("fdaa::/16", "fdaa:0:1:a7b::2"));
+        assert!(!contains("fdaa::/16", "fdab::1"));
+        assert!(contains("2001:db8::/33", "2001:db8:7fff::1"));
+        assert!(!contains("2001:db8::/33", "2001:db8:8000::1"));
+        assert!(contains("::1", "::1"));
+        assert!(contains("::/0", "2606:4700::1"));
+    }
+
+    #[test]
+    fn test_ipnet_contains_mixed_families() {
+        // An ipv4 mapped peer (dual-stack) matches the ipv4 networks, not the ipv6 ones.
+        assert!(contains("127.0.0.1", "::ffff:127.0.0.1"));
+        assert!(!contains("::/0", "127.0.0.1"));
+        assert!(!contains("0.0.0.0/0", "::1"));
+    }
+
+    #[test]
+    fn test_ipnet_from_str_invalid() {
+        for net in ["", "localhost", "10.0.0.0/33", "fdaa::/129", "10.0.0.0/x"] {
+            assert!(net.parse::<IpNet>().is_err(), "{net}");
+        }
+    }
+}
+
+// endregion: --- Tests
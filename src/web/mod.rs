@@ -1,6 +1,48 @@
-
+pub mod client_ip;
+pub mod routes_analyses;
+pub mod routes_access;
+pub mod routes_api_keys;
+pub mod routes_barcode;
+pub mod routes_foods;
+pub mod routes_health;
 pub mod routes_login;
+pub mod routes_meals;
+pub mod routes_metrics;
+pub mod routes_nutrition;
+pub mod routes_oidc;
+pub mod routes_recipes;
 pub mod routes_ticket;
 pub mod routes_static;
+pub mod server;
+pub mod valid_json;
+
+use tower_cookies::cookie::SameSite;
+use tower_cookies::{Cookie, Cookies};
+
+use crate::config;
+use crate::error::Result;
+use crate::token::generate_token;
+
+pub const AUTH_TOKEN: &str = "auth-token";
+
+/// Set the AUTH_TOKEN cookie with a new session token for `user_id`.
+pub fn set_token_cookie(cookies: &Cookies, user_id: u64) -> Result<()> {
+    let token = generate_token(user_id)?;
+
+    let mut cookie = Cookie::new(AUTH_TOKEN, token);
+    cookie.set_http_only(true);
+    cookie.set_path("/");
+    cookie.set_same_site(SameSite::Lax); // also set by the oidc callback, a top level redirect
+    cookie.set_secure(config().COOKIE_SECURE);
+
+    cookies.add(cookie);
+
+    Ok(())
+}
+
+pub fn remove_token_cookie(cookies: &Cookies) {
+    let mut cookie = Cookie::from(AUTH_TOKEN);
+    cookie.set_path("/");
 
-pub const AUTH_TOKEN: &str = "auth-token";
\ No newline at end of file
+    cookies.remove(cookie);
+}
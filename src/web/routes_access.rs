@@ -0,0 +1,85 @@
+use axum::extract::{FromRef, Path, State};
+use axum::middleware;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use tracing::debug;
+
+use crate::ctx::{Ctx, Permission};
+use crate::middlewares::mw_auth::mw_require_permission;
+use crate::model::dietitian_access::DietitianAccess;
+use crate::model::model::ModelManager;
+use crate::error::Result;
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    mm: ModelManager,
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    let app_state = AppState {mm};
+
+    // Dietitians only.
+    let routes_clients = Router::new()
+        .route("/access/clients", get(list_clients))
+        .route_layer(middleware::from_fn_with_state(
+            Permission::ClientsRead,
+            mw_require_permission,
+        ));
+
+    Router::new()
+        .route("/access/dietitians", get(list_grants))
+        .route("/access/dietitians/{dietitian_id}", post(grant_access).delete(revoke_access))
+        .merge(routes_clients)
+        .with_state(app_state)
+}
+
+// REST Handlers for DietitianAccess
+async fn grant_access(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(dietitian_id): Path<i64>,
+) -> Result<Json<DietitianAccess>> {
+    debug!("{:<12} - grant_access", "HANDLER");
+    ctx.require_session()?;
+
+    let access = mm.grant_dietitian_access(ctx, dietitian_id).await?;
+
+    Ok(Json(access))
+}
+
+async fn revoke_access(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(dietitian_id): Path<i64>,
+) -> Result<Json<DietitianAccess>> {
+    debug!("{:<12} - revoke_access", "HANDLER");
+    ctx.require_session()?;
+
+    let access = mm.revoke_dietitian_access(ctx, dietitian_id).await?;
+
+    Ok(Json(access))
+}
+
+async fn list_grants(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+) -> Result<Json<Vec<DietitianAccess>>> {
+    debug!("{:<12} - list_grants", "HANDLER");
+
+    let grants = mm.list_dietitian_grants(ctx).await?;
+
+    Ok(Json(grants))
+}
+
+async fn list_clients(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+) -> Result<Json<Vec<DietitianAccess>>> {
+    debug!("{:<12} - list_clients", "HANDLER");
+
+    let clients = mm.list_dietitian_clients(ctx).await?;
+
+    Ok(Json(clients))
+}
+
+// END -- REST Handlers for DietitianAccess
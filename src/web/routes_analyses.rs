@@ -0,0 +1,121 @@
+use axum::extract::{FromRef, Path, Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::ctx::{Ctx, Scope};
+use crate::error::Result;
+use crate::model::analysis::{Analysis, AnalysisForCorrect};
+use crate::model::model::ModelManager;
+use crate::web::valid_json::{check_max_len, check_not_blank, InvalidParam, Validate, ValidJson};
+
+const ANALYSIS_LIST_DEFAULT_LIMIT: i64 = 50;
+const CORRECTION_MAX_FOODS: usize = 100;
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    mm: ModelManager,
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    let app_state = AppState {mm};
+
+    Router::new()
+        .route("/analyses", get(list_analyses))
+        .route("/analyses/{id}", get(get_analysis).patch(correct_analysis))
+        .with_state(app_state)
+}
+
+impl Validate for AnalysisForCorrect {
+    fn validate(&self) -> Vec<InvalidParam> {
+        let mut invalid_params = Vec::new();
+        if self.foods.len() > CORRECTION_MAX_FOODS {
+            invalid_params.push(InvalidParam::new(
+                "foods",
+                format!("must be at most {CORRECTION_MAX_FOODS} foods"),
+            ));
+        }
+
+        for (position, food) in self.foods.iter().enumerate() {
+            let name = |field: &str| format!("foods[{position}].{field}");
+            match &food.name {
+                Some(food_name) => {
+                    check_not_blank(&mut invalid_params, &name("name"), food_name);
+                    check_max_len(&mut invalid_params, &name("name"), food_name, 512);
+                }
+                // An added food needs a name, the others keep theirs.
+                None if food.index.is_none() => {
+                    invalid_params.push(InvalidParam::new(name("name"), "missing field"));
+                }
+                None => (),
+            }
+            if food.weight_g.is_some_and(|weight_g| weight_g <= 0.) {
+                invalid_params.push(InvalidParam::new(name("weight_g"), "must be above 0"));
+            }
+            let nutrients = [
+                ("calories", food.calories),
+                ("protein_g", food.protein_g),
+                ("fat_g", food.fat_g),
+                ("carbohydrates_g", food.carbohydrates_g),
+                ("sugar_g", food.sugar_g),
+                ("sodium_mg", food.sodium_mg),
+            ];
+            for (field, value) in nutrients {
+                if value.is_some_and(|value| value < 0.) {
+                    invalid_params.push(InvalidParam::new(name(field), "must not be negative"));
+                }
+            }
+        }
+        invalid_params
+    }
+}
+
+#[derive(Deserialize)]
+struct AnalysisListParams {
+    limit: Option<i64>, // capped to ANALYSIS_LIST_MAX_LIMIT
+}
+
+// REST Handlers for Analysis
+async fn list_analyses(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Query(params): Query<AnalysisListParams>,
+) -> Result<Json<Vec<Analysis>>> {
+    debug!("{:<12} - list_analyses", "HANDLER");
+    ctx.require_scope(Scope::Analyze)?;
+
+    let limit = params.limit.unwrap_or(ANALYSIS_LIST_DEFAULT_LIMIT);
+    let analyses = mm.list_analyses(ctx, limit).await?;
+
+    Ok(Json(analyses))
+}
+
+async fn get_analysis(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<Json<Analysis>> {
+    debug!("{:<12} - get_analysis", "HANDLER");
+    ctx.require_scope(Scope::Analyze)?;
+
+    let analysis = mm.get_analysis(ctx, id).await?;
+
+    Ok(Json(analysis))
+}
+
+async fn correct_analysis(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    ValidJson(analysis_fc): ValidJson<AnalysisForCorrect>,
+) -> Result<Json<Analysis>> {
+    debug!("{:<12} - correct_analysis", "HANDLER");
+    ctx.require_scope(Scope::Analyze)?;
+
+    let analysis = mm.correct_analysis(ctx, id, analysis_fc).await?;
+
+    Ok(Json(analysis))
+}
+
+// END -- REST Handlers for Analysis
@@ -0,0 +1,76 @@
+use axum::extract::{FromRef, Path, State};
+use axum::routing::{delete, post};
+use axum::{Json, Router};
+use tracing::debug;
+
+use crate::ctx::Ctx;
+use crate::model::api_key::{ApiKey, ApiKeyCreated, ApiKeyForCreate};
+use crate::model::model::ModelManager;
+use crate::error::Result;
+use crate::web::valid_json::{check_max_len, check_not_blank, InvalidParam, Validate, ValidJson};
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    mm: ModelManager,
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    let app_state = AppState {mm};
+    Router::new()
+        .route("/keys", post(create_api_key).get(list_api_keys))
+        .route("/keys/{id}", delete(revoke_api_key))
+        .with_state(app_state)
+}
+
+impl Validate for ApiKeyForCreate {
+    fn validate(&self) -> Vec<InvalidParam> {
+        let mut invalid_params = Vec::new();
+        check_not_blank(&mut invalid_params, "name", &self.name);
+        check_max_len(&mut invalid_params, "name", &self.name, 128);
+        if self.scopes.is_empty() {
+            invalid_params.push(InvalidParam::new("scopes", "must not be empty"));
+        }
+        invalid_params
+    }
+}
+
+// REST Handlers for ApiKey
+async fn create_api_key(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    ValidJson(api_key_fc): ValidJson<ApiKeyForCreate>,
+) -> Result<Json<ApiKeyCreated>> {
+    debug!("{:<12} - create_api_key", "HANDLER");
+    ctx.require_session()?;
+
+    let api_key = mm.create_api_key(ctx, api_key_fc).await?;
+
+    Ok(Json(api_key))
+}
+
+async fn list_api_keys(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+) -> Result<Json<Vec<ApiKey>>> {
+    debug!("{:<12} - list_api_keys", "HANDLER");
+    ctx.require_session()?;
+
+    let api_keys = mm.list_api_keys(ctx).await?;
+
+    Ok(Json(api_keys))
+}
+
+async fn revoke_api_key(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<Json<ApiKey>> {
+    debug!("{:<12} - revoke_api_key", "HANDLER");
+    ctx.require_session()?;
+
+    let api_key = mm.revoke_api_key(ctx, id).await?;
+
+    Ok(Json(api_key))
+}
+
+// END -- REST Handlers for ApiKey
@@ -0,0 +1,142 @@
+use axum::extract::{FromRef, Path, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+use tracing::debug;
+
+use crate::ctx::{Ctx, Scope};
+use crate::model::model::ModelManager;
+use crate::model::product::Product;
+use crate::error::Result;
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    mm: ModelManager,
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    let app_state = AppState {mm};
+
+    Router::new()
+        .route("/barcode/{ean}", get(lookup_barcode))
+        .with_state(app_state)
+}
+
+// Same `foods` as an image analysis, so the clients show both the same way.
+#[skip_serializing_none]
+#[derive(Serialize)]
+struct BarcodeResponse {
+    barcode: String,
+    product_id: i64,
+    brand: Option<String>,
+    serving_size: Option<String>, // None when the values are for 100 g
+    foods: Vec<BarcodeFood>,
+}
+
+// The fields of a `FoodItem`, but a nutrient missing from the product is left out, not 0.
+#[skip_serializing_none]
+#[derive(Debug, Serialize)]
+struct BarcodeFood {
+    name: String,
+    weight_g: f32,
+    calories: Option<f32>,
+    protein_g: Option<f32>,
+    fat_g: Option<f32>,
+    carbohydrates_g: Option<f32>,
+    sugar_g: Option<f32>,
+    sodium_mg: Option<f32>,
+}
+
+// REST Handlers for Barcode
+async fn lookup_barcode(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(ean): Path<String>,
+) -> Result<Json<BarcodeResponse>> {
+    debug!("{:<12} - lookup_barcode", "HANDLER");
+    ctx.require_scope(Scope::FoodsRead)?;
+
+    let product = mm.get_product_by_barcode(&ean).await?;
+
+    Ok(Json(barcode_response(product)))
+}
+
+// END -- REST Handlers for Barcode
+
+/// The values per serving, or per 100 g when the serving weight is not known.
+fn barcode_response(product: Product) -> BarcodeResponse {
+    let (serving_size, weight_g) = match product.serving_g {
+        Some(serving_g) if serving_g > 0. => (product.serving_size, serving_g),
+        _ => (None, 100.),
+    };
+    let for_weight = |per_100g: Option<f32>| per_100g.map(|value| (value * weight_g / 100. * 10.).round() / 10.);
+
+    let food = BarcodeFood {
+        name: product.name,
+        weight_g,
+        calories: for_weight(product.calories),
+        protein_g: for_weight(product.protein_g),
+        fat_g: for_weight(product.fat_g),
+        carbohydrates_g: for_weight(product.carbohydrates_g),
+        sugar_g: for_weight(product.sugar_g),
+        sodium_mg: for_weight(product.sodium_mg),
+    };
+
+    BarcodeResponse {
+        barcode: product.barcode,
+        product_id: product.id,
+        brand: product.brand,
+        serving_size,
+        foods: vec![food],
+    }
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(serving_g: Option<f32>) -> Product {
+        Product {
+            id: 1,
+            barcode: "3017620422003".to_string(),
+            name: "Hazelnut spread".to_string(),
+            brand: None,
+            serving_size: Some("15 g".to_string()),
+            serving_g,
+            calories: Some(539.),
+            protein_g: Some(6.3),
+            fat_g: Some(30.9),
+            carbohydrates_g: Some(57.5),
+            sugar_g: None,
+            sodium_mg: None,
+        }
+    }
+
+    #[test]
+    fn test_barcode_response_per_serving() {
+        let response = barcode_response(product(Some(15.)));
+        let food = &response.foods[0];
+
+        assert_eq!(response.serving_size.as_deref(), Some("15 g"));
+        assert_eq!(food.weight_g, 15.);
+        assert_eq!(food.calories, Some(80.9));
+        assert_eq!(food.fat_g, Some(4.6));
+    }
+
+    #[test]
+    fn test_barcode_response_missing_nutrients_left_out() {
+        let response = barcode_response(product(None));
+        let food = serde_json::to_value(&response.foods[0]).unwrap();
+
+        assert!(response.serving_size.is_none());
+        assert_eq!(food["weight_g"], 100.);
+        assert_eq!(food["protein_g"].as_f64().map(|v| v as f32), Some(6.3));
+        assert!(food.get("sugar_g").is_none());
+        assert!(food.get("sodium_mg").is_none());
+    }
+}
+
+// endregion: --- Tests
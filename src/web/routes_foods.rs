@@ -0,0 +1,63 @@
+use axum::extract::{FromRef, Path, Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::ctx::{Ctx, Scope};
+use crate::model::food::{Food, FoodMatch};
+use crate::model::model::ModelManager;
+use crate::error::Result;
+
+const FOOD_SEARCH_DEFAULT_LIMIT: i64 = 20;
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    mm: ModelManager,
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    let app_state = AppState {mm};
+
+    Router::new()
+        .route("/foods", get(search_foods))
+        .route("/foods/{id}", get(get_food))
+        .with_state(app_state)
+}
+
+#[derive(Deserialize)]
+struct FoodSearchParams {
+    #[serde(default)] // missing is blank, refused by the search
+    q: String,
+    limit: Option<i64>, // capped to FOOD_SEARCH_MAX_LIMIT
+}
+
+// REST Handlers for Food
+async fn search_foods(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Query(params): Query<FoodSearchParams>,
+) -> Result<Json<Vec<FoodMatch>>> {
+    debug!("{:<12} - search_foods", "HANDLER");
+    ctx.require_scope(Scope::FoodsRead)?;
+
+    let limit = params.limit.unwrap_or(FOOD_SEARCH_DEFAULT_LIMIT);
+    let foods = mm.search_foods(&params.q, limit).await?;
+
+    Ok(Json(foods))
+}
+
+async fn get_food(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<Json<Food>> {
+    debug!("{:<12} - get_food", "HANDLER");
+    ctx.require_scope(Scope::FoodsRead)?;
+
+    let food = mm.get_food(id).await?;
+
+    Ok(Json(food))
+}
+
+// END -- REST Handlers for Food
@@ -0,0 +1,76 @@
+//! Probes and build info. Merged outside of the layers, so no auth, and no request log lines.
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::config::config_loaded;
+use crate::model::model::ModelManager;
+use crate::nutrition::provider_circuit;
+use crate::shutdown::is_draining;
+
+pub fn routes(mm: ModelManager) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/version", get(version))
+        .with_state(mm)
+}
+
+// Liveness: the process answers.
+async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+// Readiness: the dependencies needed to serve requests are there, and not shutting down.
+// The provider circuit is reported, but does not fail readiness: the provider is shared by
+// all the machines, taking them out of the routing would not bring it back.
+async fn readyz(State(mm): State<ModelManager>) -> (StatusCode, Json<Value>) {
+    let draining = is_draining();
+    let db = mm.ping_db().await;
+    let config = config_loaded();
+    let provider = provider_circuit().status();
+
+    let ready = !draining && db.is_ok() && config;
+    if !ready {
+        debug!(
+            "{:<12} - not ready - draining: {draining}, db: {db:?}, config: {config}, provider: {provider:?}",
+            "READYZ"
+        );
+    }
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "checks": {
+            "shutdown": if draining { "draining" } else { "ok" },
+            "db": if db.is_ok() { "ok" } else { "fail" },
+            "config": if config { "ok" } else { "fail" },
+            "provider_circuit": provider,
+        },
+    });
+
+    (status, Json(body))
+}
+
+// Build info, set by build.rs.
+async fn version() -> Json<Value> {
+    let features: Vec<&str> = env!("BUILD_FEATURES")
+        .split(',')
+        .filter(|f| !f.is_empty())
+        .collect();
+
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": env!("BUILD_GIT_SHA"),
+        "build_time": env!("BUILD_TIME"),
+        "features": features,
+    }))
+}
@@ -1,15 +1,17 @@
-use crate::{error::{Error, Result}, web::AUTH_TOKEN};
+use crate::{error::{Error, Result}, middlewares::mw_rate_limit::LoginLockout, web::set_token_cookie};
+use crate::web::valid_json::{check_max_len, check_not_blank, InvalidParam, Validate, ValidJson};
 
-use axum::{routing::post, Json, Router};
+use axum::{extract::State, routing::post, Json, Router};
 use serde::Deserialize;
 use serde_json::{json, Value};
-use tower_cookies::{Cookie, Cookies};
+use tower_cookies::Cookies;
 use tracing::debug;
 
-pub fn routes() -> Router {
+pub fn routes(login_lockout: LoginLockout) -> Router {
     Router::new()
         .route("/api/login", post(api_login))
         // Add other routes here as needed
+        .with_state(login_lockout)
 }
 
 #[derive(Debug, Deserialize)]
@@ -18,17 +20,35 @@ struct LoginPayload {
     password: String,
 }
 
+impl Validate for LoginPayload {
+    fn validate(&self) -> Vec<InvalidParam> {
+        let mut invalid_params = Vec::new();
+        check_not_blank(&mut invalid_params, "username", &self.username);
+        check_max_len(&mut invalid_params, "username", &self.username, 128);
+        check_not_blank(&mut invalid_params, "password", &self.password);
+        invalid_params
+    }
+}
+
 #[axum::debug_handler]
-async fn api_login(cookies: Cookies, payload: Json<LoginPayload>) -> Result<Json<Value>> {
-    debug!("{:<12} - api_login with payload: {:?}", "HANDLER", payload);
+async fn api_login(
+    State(login_lockout): State<LoginLockout>,
+    cookies: Cookies,
+    ValidJson(payload): ValidJson<LoginPayload>,
+) -> Result<Json<Value>> {
+    debug!("{:<12} - api_login for user: {}", "HANDLER", payload.username);
+
+    login_lockout.check(&payload.username)?;
 
     // TODO: Implement real db/auth logic.
     if payload.username != "demo" || payload.password != "123" {
+        login_lockout.record_failure(&payload.username);
         return Err(Error::LoginFail);
     }
+    login_lockout.record_success(&payload.username);
 
-    // FIXME: Implement real auth-token generatution/signature.
-    cookies.add(Cookie::new(AUTH_TOKEN, "user-1.exp_date.signature"));
+    // TODO: Get the user id from the db once the users have passwords.
+    set_token_cookie(&cookies, 1)?;
     debug!("{:<12} - Login successful for user: {}", "HANDLER", payload.username);
 
     // Create the success body.
@@ -0,0 +1,73 @@
+use axum::extract::{FromRef, Path, Query, State};
+use axum::middleware;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::ctx::{Ctx, Permission, Scope};
+use crate::middlewares::mw_auth::mw_require_permission;
+use crate::model::meal_log::MealLog;
+use crate::model::model::ModelManager;
+use crate::error::Result;
+
+const MEAL_LIST_DEFAULT_LIMIT: i64 = 50;
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    mm: ModelManager,
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    let app_state = AppState {mm};
+
+    // Dietitians only, for the clients who granted them access.
+    let routes_clients = Router::new()
+        .route("/access/clients/{client_id}/meals", get(list_client_meals))
+        .route_layer(middleware::from_fn_with_state(
+            Permission::ClientsRead,
+            mw_require_permission,
+        ));
+
+    Router::new()
+        .route("/meals", get(list_meals))
+        .merge(routes_clients)
+        .with_state(app_state)
+}
+
+#[derive(Deserialize)]
+struct MealListParams {
+    limit: Option<i64>, // capped to MEAL_LIST_MAX_LIMIT
+}
+
+// REST Handlers for MealLog
+async fn list_meals(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Query(params): Query<MealListParams>,
+) -> Result<Json<Vec<MealLog>>> {
+    debug!("{:<12} - list_meals", "HANDLER");
+    ctx.require_scope(Scope::MealsRead)?;
+
+    let limit = params.limit.unwrap_or(MEAL_LIST_DEFAULT_LIMIT);
+    let meals = mm.list_meals(ctx, limit).await?;
+
+    Ok(Json(meals))
+}
+
+async fn list_client_meals(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(client_id): Path<i64>,
+    Query(params): Query<MealListParams>,
+) -> Result<Json<Vec<MealLog>>> {
+    debug!("{:<12} - list_client_meals", "HANDLER");
+    ctx.require_scope(Scope::MealsRead)?;
+
+    let limit = params.limit.unwrap_or(MEAL_LIST_DEFAULT_LIMIT);
+    let meals = mm.list_client_meals(ctx, client_id, limit).await?;
+
+    Ok(Json(meals))
+}
+
+// END -- REST Handlers for MealLog
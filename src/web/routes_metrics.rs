@@ -0,0 +1,57 @@
+use axum::extract::{FromRef, State};
+use axum::http::header::{self, AUTHORIZATION};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use sha2::{Digest, Sha256};
+
+use crate::config;
+use crate::metrics::metrics;
+use crate::model::model::ModelManager;
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    mm: ModelManager,
+    token_sha256: Vec<u8>,
+}
+
+/// Empty router when `SERVICE_METRICS_TOKEN` is not set, the metrics are not public
+/// (routes, auth failures, provider errors, db pool).
+pub fn routes(mm: ModelManager) -> Router {
+    let Some(token) = &config().METRICS_TOKEN else {
+        return Router::new();
+    };
+
+    let app_state = AppState {
+        mm,
+        token_sha256: Sha256::digest(token.expose().as_bytes()).to_vec(),
+    };
+    Router::new()
+        .route("/metrics", get(metrics_text))
+        .with_state(app_state)
+}
+
+// Prometheus text format, see https://prometheus.io/docs/instrumenting/exposition_formats/
+async fn metrics_text(
+    State(mm): State<ModelManager>,
+    State(token_sha256): State<Vec<u8>>,
+    headers: HeaderMap,
+) -> Response {
+    // Outside the layers (not a client request), so answered here, without an Error.
+    // The digests are compared, so that the time does not depend on the matching prefix of the token.
+    let authorized = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| Sha256::digest(token.trim().as_bytes()).as_slice() == token_sha256);
+    if !authorized {
+        return (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")]).into_response();
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics().render(mm.db_pool_status()),
+    )
+        .into_response()
+}
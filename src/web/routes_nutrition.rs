@@ -0,0 +1,190 @@
+use std::time::Duration;
+
+use axum::extract::{FromRef, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use axum::routing::post;
+use axum::{Extension, Json, Router};
+use tracing::{debug, warn};
+
+use crate::ctx::{Ctx, Scope};
+use crate::error::{Error, Result};
+use crate::model::analysis_quota::{format_reset, QuotaStatus};
+use crate::model::model::ModelManager;
+use crate::config;
+use crate::nutrition::{
+    call_gemini_api, call_gemini_label_api, food_prompt_id, ground_foods, AnalysisCache, CachedAnalysis, LabelResponse, NutritionResponse, ProviderCall,
+};
+use crate::web::valid_json::{check_not_blank, InvalidParam, Validate, ValidJson};
+
+// AppState holds the Gemini API client or any other shared state.
+#[derive(Clone, FromRef)]
+struct AppState {
+    mm: ModelManager,
+    gemini_api_key: GeminiApiKey,
+    analysis_cache: AnalysisCache,
+}
+
+#[derive(Clone)]
+struct GeminiApiKey(String);
+
+pub fn routes(mm: ModelManager, gemini_api_key: String) -> Router {
+    let app_state = AppState {
+        mm,
+        gemini_api_key: GeminiApiKey(gemini_api_key),
+        analysis_cache: AnalysisCache::new(
+            config().ANALYSIS_CACHE_CAPACITY,
+            Duration::from_secs(config().ANALYSIS_CACHE_TTL_SEC),
+        ),
+    };
+    Router::new()
+        .route("/analyze-image", post(analyze_image))
+        .route("/analyze-label", post(analyze_label))
+        .with_state(app_state)
+}
+
+// The request body for the /analyze-image endpoint.
+// It expects a single field `image` containing the base64-encoded image data.
+#[derive(serde::Deserialize)]
+struct ImageRequest {
+    image: String,
+}
+
+impl Validate for ImageRequest {
+    fn validate(&self) -> Vec<InvalidParam> {
+        let mut invalid_params = Vec::new();
+        check_not_blank(&mut invalid_params, "image", &self.image);
+        invalid_params
+    }
+}
+
+// Handler for the /analyze-image endpoint
+async fn analyze_image(
+    State(mm): State<ModelManager>,
+    State(GeminiApiKey(gemini_api_key)): State<GeminiApiKey>,
+    State(analysis_cache): State<AnalysisCache>,
+    ctx: Ctx,
+    ValidJson(payload): ValidJson<ImageRequest>,
+) -> Result<(HeaderMap, Option<Extension<ProviderCall>>, Json<NutritionResponse>)> {
+    debug!("{:<12} - analyze_image", "HANDLER");
+    ctx.require_scope(Scope::Analyze)?;
+
+    // Cached before the grounding, so that the reference values are always the current ones.
+    // A cached answer costs no provider call, so it does not count against the quota.
+    // A repeat returns the analysis stored for the provider call, it is not stored again.
+    let cache_key = AnalysisCache::key(&payload.image);
+    if let Some(cached) = analysis_cache.get(ctx.user_id(), &cache_key) {
+        let quota = mm.analysis_quota_status(&ctx).await?;
+        let mut response = cached.response;
+        ground_foods(&mm, &mut response).await;
+        response.analysis_id = match cached.analysis_id {
+            Some(analysis_id) => Some(analysis_id),
+            None => store_analysis(&mm, &ctx, &cache_key, &cached.model, &response).await,
+        };
+        return Ok((quota_headers(&quota), None, Json(response)));
+    }
+
+    // Given back if the call fails, or if the client goes away during the call.
+    let reservation = mm.reserve_analysis_quota(&ctx).await?;
+
+    // Note: The boxed error is not Send, so it is stringified before any await.
+    let result = call_gemini_api(&gemini_api_key, &payload.image)
+        .await
+        .map_err(|e| e.to_string());
+
+    match result {
+        // The provider call goes in the response extensions, for the request log line.
+        Ok((mut response, provider_call)) => {
+            let quota = reservation.status().clone();
+            reservation.commit();
+            let provider_response = response.clone();
+            ground_foods(&mm, &mut response).await;
+            response.analysis_id = store_analysis(&mm, &ctx, &cache_key, &provider_call.model, &response).await;
+            analysis_cache.insert(
+                ctx.user_id(),
+                &cache_key,
+                CachedAnalysis {
+                    response: provider_response,
+                    model: provider_call.model.clone(),
+                    analysis_id: response.analysis_id,
+                },
+            );
+            Ok((
+                quota_headers(&quota),
+                Some(Extension(provider_call)),
+                Json(response),
+            ))
+        }
+        Err(e) => {
+            // The analysis did not happen, so it should not count against the quota.
+            reservation.release().await?;
+            Err(Error::NutritionProviderFail(e))
+        }
+    }
+}
+
+// Handler for the /analyze-label endpoint, a photo of a nutrition facts panel.
+// Same quota as the image analyses, not cached (labels are rarely sent twice).
+async fn analyze_label(
+    State(mm): State<ModelManager>,
+    State(GeminiApiKey(gemini_api_key)): State<GeminiApiKey>,
+    ctx: Ctx,
+    ValidJson(payload): ValidJson<ImageRequest>,
+) -> Result<(HeaderMap, Option<Extension<ProviderCall>>, Json<LabelResponse>)> {
+    debug!("{:<12} - analyze_label", "HANDLER");
+    ctx.require_scope(Scope::Analyze)?;
+
+    let reservation = mm.reserve_analysis_quota(&ctx).await?;
+
+    // Note: The boxed error is not Send, so it is stringified before any await.
+    let result = call_gemini_label_api(&gemini_api_key, &payload.image)
+        .await
+        .map_err(|e| e.to_string());
+
+    match result {
+        Ok((response, provider_call)) => {
+            let quota = reservation.status().clone();
+            reservation.commit();
+            Ok((quota_headers(&quota), Some(Extension(provider_call)), Json(response)))
+        }
+        Err(e) => {
+            reservation.release().await?;
+            Err(Error::NutritionProviderFail(e))
+        }
+    }
+}
+
+/// The analysis id, for its corrections. A failed store leaves the response without it,
+/// the analysis itself is still valid.
+async fn store_analysis(
+    mm: &ModelManager,
+    ctx: &Ctx,
+    image_sha256: &str,
+    model: &str,
+    response: &NutritionResponse,
+) -> Option<i64> {
+    mm.create_analysis(ctx, image_sha256, model, &food_prompt_id(), &response.foods)
+        .await
+        .inspect_err(|ex| warn!("{:<12} - analysis not stored - {ex:?}", "HANDLER"))
+        .ok()
+}
+
+/// The quota of the ctx user, on the analyses and on the `QuotaExceeded` responses.
+pub fn quota_headers(quota: &QuotaStatus) -> HeaderMap {
+    let headers = [
+        ("x-quota-daily-limit", quota.daily_limit.to_string()),
+        ("x-quota-daily-remaining", (quota.daily_limit - quota.daily_used).max(0).to_string()),
+        ("x-quota-daily-reset", format_reset(quota.daily_reset)),
+        ("x-quota-monthly-limit", quota.monthly_limit.to_string()),
+        ("x-quota-monthly-remaining", (quota.monthly_limit - quota.monthly_used).max(0).to_string()),
+        ("x-quota-monthly-reset", format_reset(quota.monthly_reset)),
+    ];
+
+    headers
+        .into_iter()
+        .filter_map(|(name, value)| {
+            HeaderValue::from_str(&value)
+                .ok()
+                .map(|value| (HeaderName::from_static(name), value))
+        })
+        .collect()
+}
@@ -0,0 +1,274 @@
+use axum::extract::{FromRef, Path, Query, State};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::ctx::{Ctx, Scope};
+use crate::error::{Error, Result};
+use crate::model::meal_log::{MealForLog, MealLog};
+use crate::model::model::ModelManager;
+use crate::model::recipe::{Recipe, RecipeForCreate};
+use crate::web::valid_json::{check_max_len, check_not_blank, InvalidParam, Validate, ValidJson};
+
+const RECIPE_MAX_INGREDIENTS: usize = 100;
+const RECIPE_MAX_SERVINGS: f32 = 1000.;
+const INGREDIENT_MAX_GRAMS: f32 = 100_000.;
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    mm: ModelManager,
+}
+
+pub fn routes(mm: ModelManager) -> Router {
+    let app_state = AppState {mm};
+
+    Router::new()
+        .route("/recipes", post(create_recipe).get(list_recipes))
+        .route("/recipes/{id}", get(get_recipe).put(update_recipe).delete(delete_recipe))
+        .route("/recipes/{id}/scaled", get(get_scaled_recipe))
+        .route("/recipes/{id}/clone", post(clone_recipe))
+        .route("/recipes/{id}/log", post(log_recipe_meal))
+        .with_state(app_state)
+}
+
+impl Validate for RecipeForCreate {
+    fn validate(&self) -> Vec<InvalidParam> {
+        let mut invalid_params = Vec::new();
+        check_not_blank(&mut invalid_params, "name", &self.name);
+        check_max_len(&mut invalid_params, "name", &self.name, 256);
+        check_servings(&mut invalid_params, "servings", self.servings);
+        if self.ingredients.is_empty() {
+            invalid_params.push(InvalidParam::new("ingredients", "must not be empty"));
+        }
+        if self.ingredients.len() > RECIPE_MAX_INGREDIENTS {
+            invalid_params.push(InvalidParam::new(
+                "ingredients",
+                format!("must be at most {RECIPE_MAX_INGREDIENTS} ingredients"),
+            ));
+        }
+
+        for (index, ingredient) in self.ingredients.iter().enumerate() {
+            let name = |field: &str| format!("ingredients[{index}].{field}");
+            if !(ingredient.grams > 0. && ingredient.grams <= INGREDIENT_MAX_GRAMS) {
+                invalid_params.push(InvalidParam::new(
+                    name("grams"),
+                    format!("must be above 0 and at most {INGREDIENT_MAX_GRAMS}"),
+                ));
+            }
+            match (ingredient.food_id, &ingredient.analyzed) {
+                (Some(_), None) | (None, Some(_)) => (),
+                _ => invalid_params.push(InvalidParam::new(
+                    format!("ingredients[{index}]"),
+                    "must have either a food_id or an analyzed item",
+                )),
+            }
+        }
+        invalid_params
+    }
+}
+
+impl Validate for MealForLog {
+    fn validate(&self) -> Vec<InvalidParam> {
+        let mut invalid_params = Vec::new();
+        if let Some(servings) = self.servings {
+            check_servings(&mut invalid_params, "servings", servings);
+        }
+        invalid_params
+    }
+}
+
+fn check_servings(invalid_params: &mut Vec<InvalidParam>, name: &str, servings: f32) {
+    if !(servings > 0. && servings <= RECIPE_MAX_SERVINGS) {
+        invalid_params.push(InvalidParam::new(
+            name,
+            format!("must be above 0 and at most {RECIPE_MAX_SERVINGS}"),
+        ));
+    }
+}
+
+#[derive(Deserialize)]
+struct RecipeListParams {
+    #[serde(default)] // the recipes shared by the other users, instead of the own ones
+    shared: bool,
+}
+
+#[derive(Deserialize)]
+struct RecipeScaleParams {
+    servings: f32,
+}
+
+// REST Handlers for Recipe
+async fn create_recipe(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    ValidJson(recipe_fc): ValidJson<RecipeForCreate>,
+) -> Result<Json<Recipe>> {
+    debug!("{:<12} - create_recipe", "HANDLER");
+    ctx.require_scope(Scope::RecipesWrite)?;
+
+    let recipe = mm.create_recipe(ctx, recipe_fc).await?;
+
+    Ok(Json(recipe))
+}
+
+async fn list_recipes(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Query(params): Query<RecipeListParams>,
+) -> Result<Json<Vec<Recipe>>> {
+    debug!("{:<12} - list_recipes", "HANDLER");
+    ctx.require_scope(Scope::RecipesRead)?;
+
+    let recipes = mm.list_recipes(ctx, params.shared).await?;
+
+    Ok(Json(recipes))
+}
+
+async fn get_recipe(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<Json<Recipe>> {
+    debug!("{:<12} - get_recipe", "HANDLER");
+    ctx.require_scope(Scope::RecipesRead)?;
+
+    let recipe = mm.get_recipe(ctx, id).await?;
+
+    Ok(Json(recipe))
+}
+
+async fn get_scaled_recipe(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    Query(params): Query<RecipeScaleParams>,
+) -> Result<Json<Recipe>> {
+    debug!("{:<12} - get_scaled_recipe", "HANDLER");
+    ctx.require_scope(Scope::RecipesRead)?;
+
+    let servings = params.servings;
+    if !(servings > 0. && servings <= RECIPE_MAX_SERVINGS) {
+        return Err(Error::RecipeScaleFailServingsInvalid { servings });
+    }
+    let recipe = mm.get_recipe(ctx, id).await?;
+
+    Ok(Json(recipe.scaled(servings)))
+}
+
+async fn update_recipe(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    ValidJson(recipe_fu): ValidJson<RecipeForCreate>,
+) -> Result<Json<Recipe>> {
+    debug!("{:<12} - update_recipe", "HANDLER");
+    ctx.require_scope(Scope::RecipesWrite)?;
+
+    let recipe = mm.update_recipe(ctx, id, recipe_fu).await?;
+
+    Ok(Json(recipe))
+}
+
+async fn delete_recipe(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<Json<Recipe>> {
+    debug!("{:<12} - delete_recipe", "HANDLER");
+    ctx.require_scope(Scope::RecipesWrite)?;
+
+    let recipe = mm.delete_recipe(ctx, id).await?;
+
+    Ok(Json(recipe))
+}
+
+async fn clone_recipe(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<Json<Recipe>> {
+    debug!("{:<12} - clone_recipe", "HANDLER");
+    ctx.require_scope(Scope::RecipesWrite)?;
+
+    let recipe = mm.clone_recipe(ctx, id).await?;
+
+    Ok(Json(recipe))
+}
+
+async fn log_recipe_meal(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    ValidJson(meal_fl): ValidJson<MealForLog>,
+) -> Result<Json<MealLog>> {
+    debug!("{:<12} - log_recipe_meal", "HANDLER");
+    ctx.require_scope(Scope::MealsWrite)?;
+
+    let meal = mm.log_recipe_meal(ctx, id, meal_fl).await?;
+
+    Ok(Json(meal))
+}
+
+// END -- REST Handlers for Recipe
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe_fc(servings: f32) -> RecipeForCreate {
+        serde_json::from_value(serde_json::json!({
+            "name": "Porridge",
+            "servings": servings,
+            "ingredients": [
+                { "food_id": 1000, "grams": 200 },
+                { "analyzed": { "analysis_id": 1, "item_index": 0 }, "grams": 30 },
+            ],
+        }))
+        .unwrap()
+    }
+
+    fn invalid_names(invalid_params: Vec<InvalidParam>) -> Vec<String> {
+        invalid_params.into_iter().map(|param| param.name).collect()
+    }
+
+    #[test]
+    fn test_recipe_validate_ok() {
+        assert!(recipe_fc(2.).validate().is_empty());
+    }
+
+    #[test]
+    fn test_recipe_validate_servings_zero_or_negative() {
+        assert_eq!(invalid_names(recipe_fc(0.).validate()), ["servings"]);
+        assert_eq!(invalid_names(recipe_fc(-1.).validate()), ["servings"]);
+        assert_eq!(invalid_names(recipe_fc(RECIPE_MAX_SERVINGS + 1.).validate()), ["servings"]);
+    }
+
+    #[test]
+    fn test_meal_validate_servings_zero_or_negative() {
+        let meal: MealForLog = serde_json::from_value(serde_json::json!({ "servings": 0 })).unwrap();
+        assert_eq!(invalid_names(meal.validate()), ["servings"]);
+
+        let meal: MealForLog = serde_json::from_value(serde_json::json!({ "servings": -0.5 })).unwrap();
+        assert_eq!(invalid_names(meal.validate()), ["servings"]);
+    }
+
+    #[test]
+    fn test_recipe_validate_ingredient_food_or_analyzed() {
+        let recipe_fc: RecipeForCreate = serde_json::from_value(serde_json::json!({
+            "name": "Porridge",
+            "servings": 1,
+            "ingredients": [
+                { "grams": 200 },
+                { "food_id": 1, "analyzed": { "analysis_id": 1, "item_index": 0 }, "grams": 30 },
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(invalid_names(recipe_fc.validate()), ["ingredients[0]", "ingredients[1]"]);
+    }
+}
+
+// endregion: --- Tests
@@ -1,11 +1,14 @@
 use axum::extract::{FromRef, Path, State};
-use axum::routing::{delete, post};
+use axum::middleware;
+use axum::routing::{delete, get, post};
 use axum::{Json, Router};
 use tracing::debug;
 
-use crate::ctx::Ctx;
+use crate::ctx::{Ctx, Permission, Scope};
+use crate::middlewares::mw_auth::mw_require_permission;
 use crate::model::model::{ModelManager, Ticket, TicketForCreate};
 use crate::error::Result;
+use crate::web::valid_json::{check_max_len, check_not_blank, InvalidParam, Validate, ValidJson};
 
 #[derive(Clone, FromRef)]
 struct AppState {
@@ -14,19 +17,37 @@ struct AppState {
 
 pub fn routes(mm: ModelManager) -> Router {
     let app_state = AppState {mm};
+    let routes_admin = Router::new()
+        .route("/admin/tickets", get(list_all_tickets))
+        .route_layer(middleware::from_fn_with_state(
+            Permission::TicketsListAll,
+            mw_require_permission,
+        ));
+
     Router::new()
         .route("/tickets", post(create_ticket).get(list_tickets))
         .route("/tickets/{id}", delete(delete_ticket))
+        .merge(routes_admin)
         .with_state(app_state)
 }
 
+impl Validate for TicketForCreate {
+    fn validate(&self) -> Vec<InvalidParam> {
+        let mut invalid_params = Vec::new();
+        check_not_blank(&mut invalid_params, "title", &self.title);
+        check_max_len(&mut invalid_params, "title", &self.title, 256);
+        invalid_params
+    }
+}
+
 // REST Handlers for Ticket
 async fn create_ticket(
     State(mm): State<ModelManager>,
     ctx: Ctx,
-    Json(ticket_fc): Json<TicketForCreate>,
+    ValidJson(ticket_fc): ValidJson<TicketForCreate>,
 ) -> Result<Json<Ticket>> {
     debug!("{:<12} - create_ticket", "HANDLER");
+    ctx.require_scope(Scope::TicketsWrite)?;
 
     let ticket = mm.create_ticket(ctx, ticket_fc).await?;
     
@@ -38,18 +59,32 @@ async fn list_tickets(
     ctx: Ctx,
 ) -> Result<Json<Vec<Ticket>>> {
     debug!("{:<12} - list_tickets", "HANDLER");
+    ctx.require_scope(Scope::TicketsRead)?;
 
     let tickets = mm.list_tickets(ctx).await?;
     
     Ok(Json(tickets))
 }
 
+async fn list_all_tickets(
+    State(mm): State<ModelManager>,
+    ctx: Ctx,
+) -> Result<Json<Vec<Ticket>>> {
+    debug!("{:<12} - list_all_tickets", "HANDLER");
+    ctx.require_scope(Scope::TicketsRead)?;
+
+    let tickets = mm.list_all_tickets(ctx).await?;
+    
+    Ok(Json(tickets))
+}
+
 async fn delete_ticket(
     State(mm): State<ModelManager>,
     ctx: Ctx,
     Path(id): Path<u64>,
 ) -> Result<Json<Ticket>> {
     debug!("{:<12} - delete_ticket", "HANDLER");
+    ctx.require_scope(Scope::TicketsWrite)?;
 
     let ticket = mm.delete_ticket(ctx, id).await?;
     
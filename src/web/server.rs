@@ -0,0 +1,158 @@
+//! Listening, as configured:
+//! - `SERVICE_BIND_UNIX_SOCKET`: a unix socket, e.g., behind a local reverse proxy.
+//! - else tcp on `SERVICE_BIND_ADDR:SERVICE_PORT` (or `PORT`), dual-stack with `SERVICE_BIND_DUAL_STACK`,
+//!   and TLS terminated here (rustls) when `SERVICE_TLS_CERT_PATH` and `SERVICE_TLS_KEY_PATH` are set.
+//!
+//! On shutdown, no new connections are accepted, and `serve` returns once the in-flight requests
+//! are done, or after `SERVICE_SHUTDOWN_DRAIN_TIMEOUT_SEC`.
+
+use std::future::{Future, IntoFuture};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use socket2::{Domain, Protocol, Socket, Type};
+use tracing::{info, warn};
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::metrics::metrics;
+use crate::shutdown::Shutdown;
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+pub async fn serve(app: Router, shutdown: Shutdown) -> Result<()> {
+    if let Some(path) = &config().BIND_UNIX_SOCKET {
+        return serve_unix(app, path, shutdown).await;
+    }
+
+    // Behind a proxy, the client ips would all be the proxy's (a shared rate limit and login lockout).
+    if config().TRUSTED_PROXIES.is_empty() && !config().BIND_ADDR.is_loopback() {
+        warn!(
+            "{:<12} - SERVICE_TRUSTED_PROXIES is empty, the proxy headers are ignored (set it when behind a proxy)",
+            "LISTENING"
+        );
+    }
+
+    let addr = SocketAddr::new(config().BIND_ADDR, config().PORT);
+    let listener = tcp_listener(addr).map_err(|ex| Error::ServeFail(format!("bind {addr} - {ex}")))?;
+
+    match (&config().TLS_CERT_PATH, &config().TLS_KEY_PATH) {
+        (Some(cert_path), Some(key_path)) => {
+            serve_tls(app, listener, cert_path, key_path, shutdown).await
+        }
+        _ => serve_tcp(app, listener, shutdown).await,
+    }
+}
+
+/// Bound with socket2, for the ipv6 only flag (the OS default varies).
+fn tcp_listener(addr: SocketAddr) -> std::io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(!config().BIND_DUAL_STACK)?;
+    }
+    socket.set_reuse_address(true)?; // restart without waiting for the TIME_WAIT sockets
+    socket.set_nonblocking(true)?; // required by tokio
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+
+    Ok(socket.into())
+}
+
+async fn serve_tcp(app: Router, listener: std::net::TcpListener, shutdown: Shutdown) -> Result<()> {
+    let listener = tokio::net::TcpListener::from_std(listener)
+        .map_err(|ex| Error::ServeFail(ex.to_string()))?;
+    info!("{:<12} - http://{}\n", "LISTENING", local_addr(&listener.local_addr()));
+
+    // ConnectInfo gives the peer address, for the client ip resolution.
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(triggered(shutdown.clone()));
+
+    drain(server.into_future(), &shutdown).await
+}
+
+async fn serve_tls(
+    app: Router,
+    listener: std::net::TcpListener,
+    cert_path: &str,
+    key_path: &str,
+    shutdown: Shutdown,
+) -> Result<()> {
+    // Err when already installed, which is fine.
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let tls_config = RustlsConfig::from_pem_file(cert_path, key_path)
+        .await
+        .map_err(|ex| Error::ServeFail(format!("tls cert/key - {ex}")))?;
+    info!("{:<12} - https://{}\n", "LISTENING", local_addr(&listener.local_addr()));
+
+    let handle = Handle::new();
+    tokio::spawn({
+        let (handle, shutdown) = (handle.clone(), shutdown.clone());
+        async move {
+            shutdown.triggered().await;
+            handle.graceful_shutdown(None); // the timeout is in drain, as for the other listeners
+        }
+    });
+    let server = axum_server::from_tcp_rustls(listener, tls_config)
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+
+    drain(server, &shutdown).await
+}
+
+#[cfg(unix)]
+async fn serve_unix(app: Router, path: &str, shutdown: Shutdown) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    use crate::web::client_ip::UnixPeer;
+
+    // Left over by a previous run, binding would fail otherwise. Anything else is kept.
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path).map_err(|ex| Error::ServeFail(format!("{path} - {ex}")))?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|ex| Error::ServeFail(format!("bind {path} - {ex}")))?;
+    info!("{:<12} - unix:{path}\n", "LISTENING");
+
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<UnixPeer>())
+        .with_graceful_shutdown(triggered(shutdown.clone()));
+
+    drain(server.into_future(), &shutdown).await
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_app: Router, path: &str, _shutdown: Shutdown) -> Result<()> {
+    Err(Error::ServeFail(format!("unix:{path} - unix sockets not supported on this platform")))
+}
+
+/// The server until it is done, or cut off when the drain takes longer than the timeout.
+async fn drain(server: impl Future<Output = std::io::Result<()>>, shutdown: &Shutdown) -> Result<()> {
+    let drain_timeout = Duration::from_secs(config().SHUTDOWN_DRAIN_TIMEOUT_SEC);
+    let drain_deadline = async {
+        shutdown.triggered().await;
+        tokio::time::sleep(drain_timeout).await;
+    };
+
+    tokio::select! {
+        res = server => res.map_err(|ex| Error::ServeFail(ex.to_string())),
+        _ = drain_deadline => {
+            warn!(
+                "{:<12} - drain timed out, {} in-flight requests cut off",
+                "SHUTDOWN",
+                metrics().http_requests_in_flight()
+            );
+            Ok(())
+        }
+    }
+}
+
+async fn triggered(shutdown: Shutdown) {
+    shutdown.triggered().await
+}
+
+fn local_addr(addr: &std::io::Result<SocketAddr>) -> String {
+    addr.as_ref().map(ToString::to_string).unwrap_or_default()
+}
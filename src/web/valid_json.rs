@@ -0,0 +1,108 @@
+//! Json body extractor with field-level errors.
+//! Deserialization failures (malformed body, missing field, wrong type) and
+//! `Validate` failures both become an `Error` with `invalid_params`,
+//! instead of axum's plain-text rejection.
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequest, Request};
+use lazy_regex::regex_captures;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InvalidParam {
+    pub name: String,
+    pub reason: String,
+}
+
+impl InvalidParam {
+    pub fn new(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Semantic checks, once the body deserialized.
+pub trait Validate {
+    fn validate(&self) -> Vec<InvalidParam> {
+        Vec::new()
+    }
+}
+
+// region:    --- Validation helpers
+
+pub fn check_not_blank(invalid_params: &mut Vec<InvalidParam>, name: &str, value: &str) {
+    if value.trim().is_empty() {
+        invalid_params.push(InvalidParam::new(name, "must not be blank"));
+    }
+}
+
+pub fn check_max_len(invalid_params: &mut Vec<InvalidParam>, name: &str, value: &str, max: usize) {
+    if value.chars().count() > max {
+        invalid_params.push(InvalidParam::new(name, format!("must be at most {max} characters")));
+    }
+}
+
+// endregion: --- Validation helpers
+
+// ValidJson Extractor
+
+pub struct ValidJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self> {
+        let axum::Json(value) = axum::Json::<T>::from_request(req, state).await?;
+
+        let invalid_params = value.validate();
+        if !invalid_params.is_empty() {
+            return Err(Error::ReqBodyValidationFail { invalid_params });
+        }
+
+        Ok(Self(value))
+    }
+}
+
+// END - ValidJson Extractor
+
+impl From<JsonRejection> for Error {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(ex) => Error::ReqBodyDataFail {
+                invalid_params: vec![data_error_param(&ex.body_text())],
+            },
+            JsonRejection::MissingJsonContentType(_) => Error::ReqBodyMissingJsonContentType,
+            // Syntax errors, body read errors, and future variants.
+            other => Error::ReqBodySyntaxFail {
+                invalid_params: vec![InvalidParam::new("body", other.body_text())],
+            },
+        }
+    }
+}
+
+/// e.g., "Failed to deserialize the JSON body into the target type: missing field `title` at line 1 column 2"
+///       "Failed to deserialize the JSON body into the target type: title: invalid type: integer `1`, ..."
+fn data_error_param(body_text: &str) -> InvalidParam {
+    let msg = body_text
+        .split_once("target type: ")
+        .map(|(_, msg)| msg)
+        .unwrap_or(body_text);
+
+    if let Some((_, field)) = regex_captures!(r#"missing field `([^`]+)`"#, msg) {
+        return InvalidParam::new(field, "missing field");
+    }
+
+    match msg.split_once(": ") {
+        Some((path, reason)) if !path.contains(' ') => InvalidParam::new(path, reason),
+        _ => InvalidParam::new("body", msg),
+    }
+}